@@ -0,0 +1,410 @@
+//! Who may use which commands. Admins map a group (`song`) or a single
+//! command (`song play`) to roles and permissions in the guild's settings;
+//! members need one of the roles or all of the permissions.
+
+use crate::{error::CommandError, services::Invocation, settings::GuildSettings};
+use serde::{Deserialize, Serialize};
+use serenity::{
+    client::Context,
+    framework::standard::{macros::check, Args, CommandOptions, Reason},
+    model::{channel::Message, permissions::Permissions},
+};
+
+/// Groups whose commands can be restricted.
+pub const GROUPS: &[&str] = &["doom", "playlist", "role", "song"];
+
+/// The commands in each group, by the name their access is checked under.
+const COMMANDS: &[(&str, &[&str])] = &[
+    (
+        "doom",
+        &[
+            "host",
+            "extend",
+            "maps",
+            "vote",
+            "map",
+            "nextmap",
+            "schedule",
+            "schedules",
+            "cancel",
+            "history",
+            "top",
+        ],
+    ),
+    ("playlist", &["save", "load", "list", "delete", "add"]),
+    ("role", &["add", "assign"]),
+    (
+        "song",
+        &[
+            "join", "leave", "move", "play", "search", "skip", "stop", "current", "queue", "pause",
+            "resume", "seek", "volume", "loop", "remove", "reorder", "shuffle", "clear", "jump",
+        ],
+    ),
+];
+
+#[derive(Serialize, Deserialize, Clone, Default, PartialEq, Debug)]
+#[serde(default)]
+pub struct AccessRule {
+    pub roles: Vec<String>,
+    /// Permission names as Discord shows them, like "Manage Roles".
+    pub permissions: Vec<String>,
+}
+
+impl AccessRule {
+    pub fn is_open(&self) -> bool {
+        self.roles.is_empty() && self.permissions.is_empty()
+    }
+
+    pub fn allows(&self, roles: &[String], permissions: Permissions) -> bool {
+        if self.is_open() || permissions.administrator() {
+            return true;
+        }
+
+        let has_role = self
+            .roles
+            .iter()
+            .any(|role| roles.iter().any(|name| name.eq_ignore_ascii_case(role)));
+        let required = self
+            .permissions
+            .iter()
+            .filter_map(|name| parse_permission(name))
+            .fold(Permissions::empty(), |all, permission| all | permission);
+        has_role || (!required.is_empty() && permissions.contains(required))
+    }
+
+    pub fn describe(&self) -> String {
+        if self.is_open() {
+            return String::from("everyone");
+        }
+
+        let mut parts: Vec<String> = self.roles.iter().map(|role| format!("@{}", role)).collect();
+        if !self.permissions.is_empty() {
+            parts.push(self.permissions.join(" + "));
+        }
+        parts.join(" or ")
+    }
+}
+
+/// The rule used when a guild hasn't set one. Only creating roles is
+/// restricted out of the box.
+pub fn default_rule(target: &str) -> AccessRule {
+    match target {
+        "role add" => AccessRule {
+            roles: Vec::new(),
+            permissions: vec![String::from("Manage Roles")],
+        },
+        _ => AccessRule::default(),
+    }
+}
+
+/// The rule for a command. A guild's own rules come first, the command's
+/// before its group's, then the defaults in the same order.
+pub fn rule_for(settings: &GuildSettings, group: &str, command: &str) -> AccessRule {
+    let command = format!("{} {}", group, command);
+    if let Some(rule) = settings
+        .access
+        .get(&command)
+        .or_else(|| settings.access.get(group))
+    {
+        return rule.clone();
+    }
+    let default = default_rule(&command);
+    if default.is_open() {
+        default_rule(group)
+    } else {
+        default
+    }
+}
+
+/// The rule that applies to a group or "group command" target, the way
+/// `rule_for` would decide it.
+pub fn effective_rule(settings: &GuildSettings, target: &str) -> AccessRule {
+    match target.split_once(' ') {
+        Some((group, command)) => rule_for(settings, group, command),
+        None => settings
+            .access
+            .get(target)
+            .cloned()
+            .unwrap_or_else(|| default_rule(target)),
+    }
+}
+
+/// Checks that `target` is a group or a command in one, returning it with the
+/// words separated by single spaces.
+pub fn validate_target(target: &str) -> Result<String, String> {
+    let words: Vec<&str> = target.split_whitespace().collect();
+    let commands = match COMMANDS
+        .iter()
+        .find(|(group, _)| words.first() == Some(group))
+    {
+        Some((_, commands)) => commands,
+        None => {
+            return Err(format!(
+                "\"{}\" is not a group or command, try one of: {}",
+                target,
+                GROUPS.join(", ")
+            ))
+        }
+    };
+
+    match words[1..] {
+        [] => Ok(words[0].to_string()),
+        [command] if commands.contains(&command) => Ok(words.join(" ")),
+        _ => Err(format!(
+            "\"{}\" is not a command, `{}` has: {}",
+            target,
+            words[0],
+            commands.join(", ")
+        )),
+    }
+}
+
+/// Finds a permission by its name, ignoring case and underscores.
+pub fn parse_permission(name: &str) -> Option<Permissions> {
+    let name = name.replace('_', " ");
+    (0..64)
+        .map(|bit| Permissions::from_bits_truncate(1 << bit))
+        .filter(|permission| !permission.is_empty())
+        .find(|permission| {
+            permission
+                .get_permission_names()
+                .first()
+                .map_or(false, |known| known.eq_ignore_ascii_case(&name))
+        })
+}
+
+/// The display name of a permission, like "Manage Roles".
+pub fn permission_name(permission: Permissions) -> String {
+    permission
+        .get_permission_names()
+        .first()
+        .map(|name| name.to_string())
+        .unwrap_or_default()
+}
+
+/// Fails with a user error if the invoker may not use the command.
+pub async fn check(
+    ctx: &Context,
+    invocation: &Invocation,
+    group: &str,
+    command: &str,
+) -> Result<(), CommandError> {
+    // Nothing to check against outside of guilds.
+    let guild = match invocation.guild_id {
+        Some(guild_id) => match guild_id.to_guild_cached(&ctx.cache).await {
+            Some(guild) => guild,
+            None => {
+                return Err(CommandError::internal(anyhow::anyhow!(
+                    "Could not get guild"
+                )))
+            }
+        },
+        None => return Ok(()),
+    };
+
+    let settings = crate::settings::for_guild(ctx, Some(guild.id))
+        .await
+        .map_err(CommandError::internal)?;
+    let rule = rule_for(&settings, group, command);
+    if rule.is_open() {
+        return Ok(());
+    }
+
+    let member = guild
+        .member(ctx, invocation.user_id)
+        .await
+        .map_err(CommandError::internal)?;
+    let permissions = member
+        .permissions(ctx)
+        .await
+        .map_err(CommandError::internal)?;
+    let roles: Vec<String> = member
+        .roles
+        .iter()
+        .filter_map(|id| guild.roles.get(id))
+        .map(|role| role.name.clone())
+        .collect();
+
+    if rule.allows(&roles, permissions) {
+        Ok(())
+    } else {
+        Err(CommandError::user(format!(
+            "Only {} can use `{} {}`",
+            rule.describe(),
+            group,
+            command
+        )))
+    }
+}
+
+async fn check_message(
+    ctx: &Context,
+    msg: &Message,
+    group: &str,
+    options: &CommandOptions,
+) -> Result<(), Reason> {
+    let command = options.names.first().copied().unwrap_or_default();
+    match check(ctx, &Invocation::from(msg), group, command).await {
+        Ok(()) => Ok(()),
+        Err(CommandError::User(reason)) => Err(Reason::User(reason)),
+        Err(CommandError::Internal(why)) => {
+            log::error!("Could not check access to {} {}: {:?}", group, command, why);
+            Err(Reason::User(String::from(
+                "Could not check your permissions",
+            )))
+        }
+    }
+}
+
+#[check]
+#[name = "DoomAccess"]
+async fn doom_access(
+    ctx: &Context,
+    msg: &Message,
+    _: &mut Args,
+    options: &CommandOptions,
+) -> Result<(), Reason> {
+    check_message(ctx, msg, "doom", options).await
+}
+
+#[check]
+#[name = "PlaylistAccess"]
+async fn playlist_access(
+    ctx: &Context,
+    msg: &Message,
+    _: &mut Args,
+    options: &CommandOptions,
+) -> Result<(), Reason> {
+    check_message(ctx, msg, "playlist", options).await
+}
+
+#[check]
+#[name = "RoleAccess"]
+async fn role_access(
+    ctx: &Context,
+    msg: &Message,
+    _: &mut Args,
+    options: &CommandOptions,
+) -> Result<(), Reason> {
+    check_message(ctx, msg, "role", options).await
+}
+
+#[check]
+#[name = "SongAccess"]
+async fn song_access(
+    ctx: &Context,
+    msg: &Message,
+    _: &mut Args,
+    options: &CommandOptions,
+) -> Result<(), Reason> {
+    check_message(ctx, msg, "song", options).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(roles: &[&str], permissions: &[&str]) -> AccessRule {
+        AccessRule {
+            roles: roles.iter().map(|role| role.to_string()).collect(),
+            permissions: permissions.iter().map(|name| name.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn parses_permissions() {
+        assert_eq!(
+            parse_permission("Manage Roles"),
+            Some(Permissions::MANAGE_ROLES)
+        );
+        assert_eq!(
+            parse_permission("manage_roles"),
+            Some(Permissions::MANAGE_ROLES)
+        );
+        assert_eq!(parse_permission("DJ"), None);
+    }
+
+    #[test]
+    fn roles_or_permissions() {
+        let rule = rule(&["DJ"], &["Manage Channels"]);
+        assert!(rule.allows(&[String::from("dj")], Permissions::empty()));
+        assert!(rule.allows(&[], Permissions::MANAGE_CHANNELS));
+        assert!(rule.allows(&[], Permissions::ADMINISTRATOR));
+        assert!(!rule.allows(&[String::from("Doom Host")], Permissions::SEND_MESSAGES));
+        assert!(AccessRule::default().allows(&[], Permissions::empty()));
+    }
+
+    #[test]
+    fn commands_override_groups() {
+        let mut settings = GuildSettings::default();
+        settings
+            .access
+            .insert(String::from("song"), rule(&["DJ"], &[]));
+        settings
+            .access
+            .insert(String::from("song current"), AccessRule::default());
+
+        assert_eq!(rule_for(&settings, "song", "play"), rule(&["DJ"], &[]));
+        assert!(rule_for(&settings, "song", "current").is_open());
+        assert_eq!(
+            rule_for(&settings, "role", "add"),
+            rule(&[], &["Manage Roles"])
+        );
+        assert!(rule_for(&settings, "role", "assign").is_open());
+
+        // A guild's rule for the group wins over the built-in one for `role add`.
+        settings
+            .access
+            .insert(String::from("role"), rule(&["Mods"], &[]));
+        assert_eq!(rule_for(&settings, "role", "add"), rule(&["Mods"], &[]));
+    }
+
+    #[test]
+    fn effective_rules_follow_rule_for() {
+        let mut settings = GuildSettings::default();
+        assert_eq!(
+            effective_rule(&settings, "role add"),
+            rule(&[], &["Manage Roles"])
+        );
+        assert!(effective_rule(&settings, "role").is_open());
+
+        // The group rule is what's enforced for `role add`, so that's what shows.
+        settings
+            .access
+            .insert(String::from("role"), rule(&["Mods"], &[]));
+        assert_eq!(effective_rule(&settings, "role add"), rule(&["Mods"], &[]));
+        assert_eq!(effective_rule(&settings, "role"), rule(&["Mods"], &[]));
+
+        settings
+            .access
+            .insert(String::from("role add"), rule(&["Admins"], &[]));
+        assert_eq!(
+            effective_rule(&settings, "role add"),
+            rule(&["Admins"], &[])
+        );
+    }
+
+    #[test]
+    fn targets_must_exist() {
+        assert_eq!(validate_target("song"), Ok(String::from("song")));
+        assert_eq!(
+            validate_target("song   play"),
+            Ok(String::from("song play"))
+        );
+        assert_eq!(validate_target("doom top"), Ok(String::from("doom top")));
+        assert_eq!(
+            validate_target("music"),
+            Err(String::from(
+                "\"music\" is not a group or command, try one of: doom, playlist, role, song"
+            ))
+        );
+        assert_eq!(
+            validate_target("role delete"),
+            Err(String::from(
+                "\"role delete\" is not a command, `role` has: add, assign"
+            ))
+        );
+        assert!(validate_target("song play now").is_err());
+        assert!(validate_target("").is_err());
+    }
+}
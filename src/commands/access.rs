@@ -0,0 +1,132 @@
+use super::settings::update;
+use crate::{
+    access::{self, AccessRule},
+    error::CommandError,
+};
+use serenity::{
+    framework::standard::{macros::command, Args, CommandResult},
+    model::prelude::*,
+    prelude::*,
+};
+
+#[command("list")]
+#[description("Show who can use each group and command")]
+async fn list_access(ctx: &Context, msg: &Message) -> CommandResult {
+    let settings = crate::settings::for_guild(ctx, msg.guild_id).await?;
+
+    let mut targets: Vec<String> = access::GROUPS
+        .iter()
+        .map(|group| group.to_string())
+        .collect();
+    targets.push(String::from("role add"));
+    targets.extend(settings.access.keys().cloned());
+    targets.sort();
+    targets.dedup();
+
+    let lines: Vec<String> = targets
+        .iter()
+        .map(|target| {
+            let rule = access::effective_rule(&settings, target);
+            format!("{}: {}", target, rule.describe())
+        })
+        .collect();
+    msg.channel_id
+        .say(&ctx.http, format!("```\n{}\n```", lines.join("\n")))
+        .await?;
+
+    Ok(())
+}
+
+#[command("allow")]
+#[description("Let a role, or members with a permission, use a group or command")]
+#[usage("<group | \"group command\"> <role | permission>")]
+#[example("song DJ")]
+#[example("\"role add\" Manage Roles")]
+async fn allow_access(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let (target, requirement) = parse(&mut args)?;
+    update(ctx, msg, |settings| {
+        // Start from whatever applies now, which may be the group's rule.
+        let current = access::effective_rule(settings, &target);
+        let rule = settings.access.entry(target.clone()).or_insert(current);
+        let (list, name) = match access::parse_permission(&requirement) {
+            Some(permission) => (&mut rule.permissions, access::permission_name(permission)),
+            None => (&mut rule.roles, requirement.clone()),
+        };
+        if list.iter().any(|known| known.eq_ignore_ascii_case(&name)) {
+            return Err(format!("{} already allows \"{}\"", target, name));
+        }
+        list.push(name);
+        Ok(())
+    })
+    .await
+}
+
+#[command("revoke")]
+#[description("Take away a role or permission from a group or command")]
+#[usage("<group | \"group command\"> <role | permission>")]
+async fn revoke_access(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let (target, requirement) = parse(&mut args)?;
+    update(ctx, msg, |settings| {
+        // Start from whatever applies now, which may be the group's rule.
+        let current = access::effective_rule(settings, &target);
+        let rule = settings.access.entry(target.clone()).or_insert(current);
+        let before = rule.roles.len() + rule.permissions.len();
+        rule.roles
+            .retain(|role| !role.eq_ignore_ascii_case(&requirement));
+        let permission = access::parse_permission(&requirement).map(access::permission_name);
+        rule.permissions
+            .retain(|name| Some(name) != permission.as_ref());
+        if rule.roles.len() + rule.permissions.len() == before {
+            return Err(format!("{} doesn't mention \"{}\"", target, requirement));
+        }
+        if rule.is_open() {
+            // An empty rule would open it up to everyone.
+            return Err(format!(
+                "That would let everyone use {}, use `access open` for that",
+                target
+            ));
+        }
+        Ok(())
+    })
+    .await
+}
+
+#[command("open")]
+#[description("Let everyone use a group or command")]
+#[usage("<group | \"group command\">")]
+async fn open_access(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let target = parse_target(&mut args)?;
+    update(ctx, msg, |settings| {
+        settings.access.insert(target, AccessRule::default());
+        Ok(())
+    })
+    .await
+}
+
+#[command("reset")]
+#[description("Go back to the default access for a group or command")]
+#[usage("<group | \"group command\">")]
+async fn reset_access(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let target = parse_target(&mut args)?;
+    update(ctx, msg, |settings| {
+        settings.access.remove(&target);
+        Ok(())
+    })
+    .await
+}
+
+fn parse_target(args: &mut Args) -> Result<String, CommandError> {
+    let target = args
+        .single_quoted::<String>()
+        .map_err(|_| CommandError::user("Must provide a group or command"))?;
+    access::validate_target(&target.to_lowercase()).map_err(CommandError::user)
+}
+
+fn parse(args: &mut Args) -> Result<(String, String), CommandError> {
+    let target = parse_target(args)?;
+    let requirement = args.rest().trim().to_string();
+    if requirement.is_empty() {
+        return Err(CommandError::user("Must provide a role or permission"));
+    }
+    Ok((target, requirement))
+}
@@ -1,183 +1,194 @@
-use anyhow::Context;
-use bytes::Buf;
-use glob::glob;
+use crate::{
+    access::DOOMACCESS_CHECK,
+    cooldown::{self, HOST},
+    services::{
+        discord::{doom_service, ChannelMessenger},
+        Invocation,
+    },
+};
 use serenity::{
     client::Context as SerenityContext,
     framework::standard::{macros::command, Args, CommandResult},
     model::prelude::*,
 };
-use std::{
-    ffi::OsStr,
-    fs::File,
-    io::copy,
-    path::{Path, PathBuf},
-    process::Command,
-    time::Duration,
-};
-use url::Url;
 
 #[command]
+#[description("Host a Zandronum server")]
+#[usage("<iwad> <url> | <preset>")]
+// Also in the General group, which doesn't check access itself.
+#[checks(DoomAccess)]
 async fn host(ctx: &SerenityContext, msg: &Message, mut args: Args) -> CommandResult {
-    let iwad = match args.single::<String>() {
-        Ok(iwad) => iwad,
-        Err(_) => {
-            msg.channel_id
-                .say(&ctx.http, "Must provide the IWAD")
-                .await?;
-            return Ok(());
-        }
-    };
-    let url = match args.single::<String>() {
-        Ok(url) => Some(url),
-        Err(_) => {
-            msg.channel_id.say(&ctx.http, "Must provide a url").await?;
-            return Ok(());
-        }
-    };
-
-    let config = crate::config::doom::get(&ctx).await;
-    let iwad = match iwad.as_str() {
-        "doom" => config.iwads.doom,
-        "doom2" => config.iwads.doom2,
-        "tnt" => config.iwads.tnt,
-        "plutonia" => config.iwads.plutonia,
-        _ => {
-            msg.channel_id.say(&ctx.http, "Invalid IWAD").await?;
-            return Ok(());
-        }
-    };
-
-    if let Some(url) = url {
-        let mut download_url = Some(String::from(&url));
-
-        if url.contains("doomworld.com/idgames") {
-            download_url = get_idgames_download_url(&url, &config.idgames_mirror);
-        } else if url.contains("dropbox.com") {
-            download_url = get_dropbox_download_url(&url);
-        } else if url.contains("drive.google.com") {
-            download_url = get_google_drive_download_url(&url);
-        }
-
-        if let Some(url) = download_url {
-            let path = match download_zip(&url).await {
-                Ok(path) => path,
-                Err(_e) => {
-                    // TODO: Log error
-                    msg.channel_id
-                        .say(&ctx.http, "Could not download the wad")
-                        .await?;
-                    return Ok(());
-                }
-            };
-
-            let search = format!("{}/**/*.wad", path.to_str().unwrap());
-            let first_wad = glob(&search)?.filter_map(Result::ok).next();
-            let mut server_name = String::from(config.base_name);
-            if let Some(first_wad) = first_wad {
-                let name = first_wad.file_name().unwrap_or(OsStr::new("unknown"));
-                server_name.push_str(&format!(" ({})", name.to_str().unwrap_or("unknown")));
-            }
-
-            let mut child = Command::new(config.executable)
-                .arg("-host")
-                .arg("-iwad")
-                .arg(iwad)
-                .arg("-file")
-                .args(glob(&search)?.filter_map(Result::ok))
-                .args(config.arguments.split_whitespace())
-                .arg("+sv_hostname")
-                .arg(&server_name)
-                .spawn()?;
-
-            msg.channel_id
-                .say(
-                    &ctx.http,
-                    format!("Created Zandronum server \"{}\", have fun!", &server_name),
-                )
-                .await?;
-
-            tokio::time::sleep(Duration::from_secs(config.timeout)).await;
-
-            let _ = child.kill();
-        }
-    }
+    let target = args.single::<String>().ok();
+    let url = args.single::<String>().ok();
+    let config = crate::config::doom::get(ctx).await;
+    let settings = crate::settings::for_guild(ctx, msg.guild_id).await?;
+    let invocation = Invocation::from(msg);
+    let service = doom_service(ctx).await;
+
+    cooldown::limit(
+        ctx,
+        HOST,
+        &invocation,
+        service.host(
+            &ChannelMessenger::new(ctx, msg.channel_id),
+            &invocation,
+            config.as_ref(),
+            &settings,
+            target.as_deref(),
+            url.as_deref(),
+        ),
+    )
+    .await
+}
 
-    Ok(())
+#[command]
+#[description("Extend the time left on the server you are hosting")]
+#[usage("[minutes]")]
+async fn extend(ctx: &SerenityContext, msg: &Message, mut args: Args) -> CommandResult {
+    let minutes = args.single::<u64>().ok();
+    let config = crate::config::doom::get(ctx).await;
+
+    doom_service(ctx)
+        .await
+        .extend(
+            &ChannelMessenger::new(ctx, msg.channel_id),
+            &Invocation::from(msg),
+            config.as_ref(),
+            minutes,
+        )
+        .await
 }
 
-async fn download_zip(url: &str) -> anyhow::Result<PathBuf> {
-    let res = reqwest::get(url)
+#[command]
+#[description("List the maps on the server hosted in this channel")]
+async fn maps(ctx: &SerenityContext, msg: &Message) -> CommandResult {
+    doom_service(ctx)
+        .await
+        .maps(
+            &ChannelMessenger::new(ctx, msg.channel_id),
+            &Invocation::from(msg),
+        )
         .await
-        .context("Could not download zip file")?;
+}
+
+#[command]
+#[description("Start a vote on the server hosted in this channel")]
+#[sub_commands(map)]
+async fn vote(ctx: &SerenityContext, msg: &Message) -> CommandResult {
+    msg.channel_id
+        .say(&ctx.http, "Vote for what? Try `vote map <map>`")
+        .await?;
+    Ok(())
+}
 
-    let mut tmpfile: File = tempfile::tempfile()?;
-    let content = res.bytes().await?;
-    let hash = blake3::Hasher::new().update(&content).finalize();
-    copy(&mut content.reader(), &mut tmpfile)?;
+#[command]
+#[description("Vote to change the map")]
+#[usage("<map>")]
+async fn map(ctx: &SerenityContext, msg: &Message, mut args: Args) -> CommandResult {
+    let name = args.single::<String>().ok();
+    let config = crate::config::doom::get(ctx).await;
+
+    doom_service(ctx)
+        .await
+        .vote_map(
+            &ChannelMessenger::new(ctx, msg.channel_id),
+            &Invocation::from(msg),
+            config.as_ref(),
+            name.as_deref(),
+        )
+        .await
+}
 
-    let mut zip = zip::ZipArchive::new(tmpfile)?;
+#[command]
+#[description("Vote to skip to the next map")]
+async fn nextmap(ctx: &SerenityContext, msg: &Message) -> CommandResult {
+    let config = crate::config::doom::get(ctx).await;
 
-    let path = format!("./tmp/{}", hash.to_hex());
-    let path = Path::new(&path);
-    zip.extract(path)?;
+    doom_service(ctx)
+        .await
+        .next_map(
+            &ChannelMessenger::new(ctx, msg.channel_id),
+            &Invocation::from(msg),
+            config.as_ref(),
+        )
+        .await
+}
 
-    Ok(path.to_path_buf())
+#[command]
+#[description("Book a server for later. The time is either `HH:MM`, `YYYY-MM-DDTHH:MM` or relative like `+1h30m`")]
+#[usage("<time> <iwad> <url> | <time> <preset>")]
+async fn schedule(ctx: &SerenityContext, msg: &Message, mut args: Args) -> CommandResult {
+    let time = args.single::<String>().ok();
+    let target = args.single::<String>().ok();
+    let url = args.single::<String>().ok();
+    let config = crate::config::doom::get(ctx).await;
+    let settings = crate::settings::for_guild(ctx, msg.guild_id).await?;
+
+    doom_service(ctx)
+        .await
+        .schedule(
+            &ChannelMessenger::new(ctx, msg.channel_id),
+            &Invocation::from(msg),
+            config.as_ref(),
+            &settings,
+            time.as_deref(),
+            target.as_deref(),
+            url.as_deref(),
+        )
+        .await
 }
 
-fn get_idgames_download_url(url: &str, mirror: &str) -> Option<String> {
-    if let Some(index) = url.find("doomworld.com/idgames") {
-        let index = index + "doomworld.com/idgames".len();
-        let level = &url[index..];
+#[command]
+#[description("List the scheduled servers")]
+async fn schedules(ctx: &SerenityContext, msg: &Message) -> CommandResult {
+    let config = crate::config::doom::get(ctx).await;
 
-        let mut url = String::from(mirror);
-        url.push_str(level);
-        url.push_str(".zip");
+    doom_service(ctx)
+        .await
+        .schedules(
+            &ChannelMessenger::new(ctx, msg.channel_id),
+            &Invocation::from(msg),
+            config.as_ref(),
+        )
+        .await
+}
 
-        return Some(url);
-    }
+#[command]
+#[description("Cancel a scheduled server you booked")]
+#[usage("<id>")]
+async fn cancel(ctx: &SerenityContext, msg: &Message, mut args: Args) -> CommandResult {
+    let id = args.single::<u64>().ok();
 
-    None
+    doom_service(ctx)
+        .await
+        .cancel(
+            &ChannelMessenger::new(ctx, msg.channel_id),
+            &Invocation::from(msg),
+            id,
+        )
+        .await
 }
 
-fn get_dropbox_download_url(url: &str) -> Option<String> {
-    let mut url = match Url::parse(url) {
-        Ok(url) => url,
-        Err(_) => {
-            // TODO: Log error
-            return None;
-        }
-    };
-    // All that needs to be done to get a downloadable Dropbox link is
-    // to set the query string.
-    url.set_query(Some("raw=1"));
-    Some(url.to_string())
+#[command]
+#[description("Show the servers hosted recently")]
+async fn history(ctx: &SerenityContext, msg: &Message) -> CommandResult {
+    doom_service(ctx)
+        .await
+        .history(
+            &ChannelMessenger::new(ctx, msg.channel_id),
+            &Invocation::from(msg),
+        )
+        .await
 }
 
-fn get_google_drive_download_url(url: &str) -> Option<String> {
-    let url = match Url::parse(url) {
-        Ok(url) => url,
-        Err(_) => {
-            // TODO: Log error
-            return None;
-        }
-    };
-
-    // Google Drive links have the path /file/d/<id>/view
-    // We need to extract the id from the path so we can create a url that we can
-    // download the file from.
-    let mut path_segments = url.path_segments().unwrap();
-    path_segments.next();
-    path_segments.next();
-    let id = match path_segments.next() {
-        Some(id) => id,
-        None => {
-            // TODO: Log error
-            return None;
-        }
-    };
-
-    let mut url = String::from("https://drive.google.com/uc?export=download&id=");
-    url.push_str(id);
-
-    Some(url)
+#[command]
+#[description("Show the most popular WADs")]
+async fn top(ctx: &SerenityContext, msg: &Message) -> CommandResult {
+    doom_service(ctx)
+        .await
+        .top(
+            &ChannelMessenger::new(ctx, msg.channel_id),
+            &Invocation::from(msg),
+        )
+        .await
 }
@@ -1,3 +1,4 @@
+use crate::util::{fit_lines, MAX_MESSAGE_LENGTH};
 use rand::{seq::SliceRandom, thread_rng};
 use serenity::{
     framework::standard::{macros::command, CommandResult},
@@ -5,6 +6,8 @@ use serenity::{
     prelude::*,
 };
 
+const CLOSE: &str = "\n```";
+
 #[command]
 async fn drown(ctx: &Context, msg: &Message) -> CommandResult {
     let messages = [
@@ -22,3 +25,66 @@ async fn drown(ctx: &Context, msg: &Message) -> CommandResult {
     msg.channel_id.say(&ctx.http, choice).await?;
     Ok(())
 }
+
+#[command]
+#[owners_only]
+#[description("Reload the config files")]
+async fn reload(ctx: &Context, msg: &Message) -> CommandResult {
+    let reply = match crate::config::reload(&ctx.data).await {
+        Ok(changes) if changes.is_empty() => String::from("Reloaded config, nothing changed"),
+        Ok(changes) => code_block("Reloaded config:", &changes),
+        Err(why) => {
+            let lines: Vec<String> = why.to_string().lines().map(String::from).collect();
+            code_block("Could not reload config:", &lines)
+        }
+    };
+
+    msg.channel_id.say(&ctx.http, reply).await?;
+    Ok(())
+}
+
+/// Puts lines in a code block under a heading, cutting it short if it would
+/// be too long for one message.
+fn code_block(heading: &str, lines: &[String]) -> String {
+    let opening = format!("{}\n```\n", heading);
+    let length = MAX_MESSAGE_LENGTH.saturating_sub(opening.len() + CLOSE.len());
+    format!("{}{}{}", opening, fit_lines(lines, length), CLOSE)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn short_replies_are_kept() {
+        let lines = vec![String::from("music.idle_timeout: 600 -> 60")];
+        assert_eq!(
+            code_block("Reloaded config:", &lines),
+            "Reloaded config:\n```\nmusic.idle_timeout: 600 -> 60\n```"
+        );
+    }
+
+    #[test]
+    fn long_replies_fit_in_a_message() {
+        // Multibyte characters, so a cut by bytes would land inside one.
+        let lines: Vec<String> = (0..100)
+            .map(|i| format!("doom.presets.{}.url: \"é{}\" -> \"ü\"", i, "ö".repeat(20)))
+            .collect();
+
+        let reply = code_block("Reloaded config:", &lines);
+        assert!(reply.len() <= MAX_MESSAGE_LENGTH);
+        assert!(reply.starts_with("Reloaded config:\n```\ndoom.presets.0.url"));
+        assert!(reply.ends_with("more\n```"));
+        assert_eq!(reply.matches("```").count(), 2);
+        assert!(reply.contains('…'));
+    }
+
+    #[test]
+    fn one_long_line_is_cut() {
+        let lines = vec!["ö".repeat(3000)];
+
+        let reply = code_block("Could not reload config:", &lines);
+        assert!(reply.len() <= MAX_MESSAGE_LENGTH);
+        assert!(reply.ends_with("ö…\n```"));
+    }
+}
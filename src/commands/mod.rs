@@ -1,4 +1,7 @@
+pub mod access;
 pub mod doom;
 pub mod meta;
+pub mod playlist;
 pub mod role;
+pub mod settings;
 pub mod song;
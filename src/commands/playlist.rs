@@ -0,0 +1,124 @@
+use crate::{
+    cooldown::{self, PLAY},
+    services::{
+        discord::{playlist_service, playlists_embed, ChannelMessenger},
+        Invocation,
+    },
+};
+use serenity::{
+    framework::standard::{macros::command, Args, CommandResult},
+    model::prelude::*,
+    prelude::*,
+};
+
+#[command("save")]
+#[description("Save the song playing and the queue after it as a playlist")]
+#[usage("[guild] <name>")]
+#[example("Road trip")]
+#[example("guild Friday night")]
+async fn save_playlist(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let shared = shared(&mut args);
+
+    playlist_service(ctx)
+        .await
+        .save(
+            &ChannelMessenger::new(ctx, msg.channel_id),
+            &Invocation::from(msg),
+            shared,
+            args.rest(),
+        )
+        .await
+}
+
+#[command("load")]
+#[aliases("play")]
+#[description("Queue the songs in a playlist. Your own playlists are used before the server's")]
+#[usage("[guild] <name>")]
+#[example("Road trip")]
+async fn load_playlist(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let shared = shared(&mut args);
+    let settings = crate::settings::for_guild(ctx, msg.guild_id).await?;
+    let invocation = Invocation::from(msg);
+    let service = playlist_service(ctx).await;
+
+    cooldown::limit(
+        ctx,
+        PLAY,
+        &invocation,
+        service.load(
+            &ChannelMessenger::new(ctx, msg.channel_id),
+            &invocation,
+            &settings,
+            shared,
+            args.rest(),
+        ),
+    )
+    .await
+}
+
+#[command("list")]
+#[description("Show your playlists and the server's")]
+async fn list_playlists(ctx: &Context, msg: &Message) -> CommandResult {
+    let listing = playlist_service(ctx)
+        .await
+        .list(&Invocation::from(msg))
+        .await?;
+
+    msg.channel_id
+        .send_message(&ctx.http, |m| m.embed(|e| playlists_embed(e, &listing)))
+        .await?;
+    Ok(())
+}
+
+#[command("delete")]
+#[aliases("remove")]
+#[description("Delete a playlist")]
+#[usage("[guild] <name>")]
+async fn delete_playlist(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let shared = shared(&mut args);
+
+    playlist_service(ctx)
+        .await
+        .delete(
+            &ChannelMessenger::new(ctx, msg.channel_id),
+            &Invocation::from(msg),
+            shared,
+            args.rest(),
+        )
+        .await
+}
+
+#[command("add")]
+#[description(
+    "Add a song to a playlist, or the song playing if none is given. Quote names with spaces"
+)]
+#[usage("[guild] <name> [url | search]")]
+#[example("\"Road trip\" https://youtu.be/dQw4w9WgXcQ")]
+#[example("guild Favorites")]
+async fn add_to_playlist(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let shared = shared(&mut args);
+    let name = args.quoted().single::<String>().unwrap_or_default();
+
+    playlist_service(ctx)
+        .await
+        .add(
+            &ChannelMessenger::new(ctx, msg.channel_id),
+            &Invocation::from(msg),
+            shared,
+            &name,
+            args.rest(),
+        )
+        .await
+}
+
+/// Whether the guild's playlist is meant, reading the `guild` before its name
+/// if there is one.
+fn shared(args: &mut Args) -> bool {
+    let shared = args
+        .current()
+        .map_or(false, |word| word.eq_ignore_ascii_case("guild"));
+    if shared {
+        args.advance();
+    }
+    shared
+}
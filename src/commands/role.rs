@@ -1,3 +1,11 @@
+use crate::{
+    cooldown::{self, ROLE_ADD},
+    error::CommandError,
+    services::{
+        discord::{role_service, ChannelMessenger},
+        Invocation,
+    },
+};
 use serenity::{
     framework::standard::{macros::command, Args, CommandResult},
     model::prelude::*,
@@ -10,40 +18,24 @@ use serenity::{
 #[usage("<role name>")]
 #[only_in(guilds)]
 async fn add(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
-    let name = match args.single_quoted::<String>() {
-        Ok(url) => url,
-        Err(_) => {
-            msg.channel_id
-                .say(&ctx.http, "Must provide a name for the role")
-                .await?;
-            return Ok(());
-        }
-    };
-
-    let guild = match msg.guild(&ctx.cache).await {
-        Some(guild) => guild,
-        None => {
-            log::error!("Could not get guild");
-            return Ok(());
-        }
-    };
-
-    if let Some(role) = guild.role_by_name(&name) {
-        msg.channel_id
-            .say(&ctx.http, format!("\"{}\" already exists.", role.name))
-            .await?;
-        return Ok(());
-    }
-
-    let role = guild
-        .create_role(&ctx.http, |r| r.name(name).hoist(false).mentionable(true))
-        .await?;
-
-    msg.channel_id
-        .say(&ctx.http, format!("<@&{}> has been created!", role.id))
-        .await?;
-
-    Ok(())
+    let name = args
+        .single_quoted::<String>()
+        .map_err(|_| CommandError::user("Must provide a name for the role"))?;
+    let config = crate::config::get(ctx).await;
+    let invocation = Invocation::from(msg);
+
+    cooldown::limit(
+        ctx,
+        ROLE_ADD,
+        &invocation,
+        role_service(ctx).add(
+            &ChannelMessenger::new(ctx, msg.channel_id),
+            &invocation,
+            &config.roles,
+            &name,
+        ),
+    )
+    .await
 }
 
 #[command]
@@ -52,64 +44,17 @@ async fn add(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
 #[usage("<role name>")]
 #[only_in(guilds)]
 async fn assign(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
-    let name = match args.single_quoted::<String>() {
-        Ok(url) => url,
-        Err(_) => {
-            msg.channel_id
-                .say(&ctx.http, "Must provide a name for the role")
-                .await?;
-            return Ok(());
-        }
-    };
-
-    let guild = match msg.guild(&ctx.cache).await {
-        Some(guild) => guild,
-        None => {
-            log::error!("Could not get guild");
-            return Ok(());
-        }
-    };
-
-    if let Some(role) = guild.role_by_name(&name) {
-        let has_role = match msg.author.has_role(&ctx.http, guild.id, role).await {
-            Ok(has_role) => has_role,
-            Err(why) => {
-                msg.channel_id
-                    .say(&ctx.http, "Error finding user's roles")
-                    .await?;
-                log::error!("Couldn't get user's roles: {:?}", why);
-                return Ok(());
-            }
-        };
-
-        if let Ok(mut member) = guild.member(&ctx.http, msg.author.id).await {
-            if has_role {
-                member.remove_role(&ctx.http, role.id).await?;
-                msg.channel_id
-                    .say(
-                        &ctx.http,
-                        format!(
-                            "<@{}>: You no longer have the \"{}\" role",
-                            msg.author.id, role.name
-                        ),
-                    )
-                    .await?;
-            } else {
-                member.add_role(&ctx.http, role.id).await?;
-                msg.channel_id
-                    .say(
-                        &ctx.http,
-                        format!(
-                            "<@{}>: You now have the \"{}\" role",
-                            msg.author.id, role.name
-                        ),
-                    )
-                    .await?;
-            }
-        }
-    } else {
-        msg.channel_id.say(&ctx.http, "Could not find role").await?;
-    }
-
-    Ok(())
+    let name = args
+        .single_quoted::<String>()
+        .map_err(|_| CommandError::user("Must provide a name for the role"))?;
+    let settings = crate::settings::for_guild(ctx, msg.guild_id).await?;
+
+    role_service(ctx)
+        .assign(
+            &ChannelMessenger::new(ctx, msg.channel_id),
+            &Invocation::from(msg),
+            &settings,
+            &name,
+        )
+        .await
 }
@@ -0,0 +1,116 @@
+use crate::{
+    error::CommandError,
+    settings::{self, KEYS},
+};
+use serenity::{
+    framework::standard::{macros::command, Args, CommandResult},
+    model::prelude::*,
+    prelude::*,
+};
+
+#[command("get")]
+#[description("Show the value of a setting")]
+#[usage("<key>")]
+async fn get_setting(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let key = key(&mut args)?;
+
+    let settings = settings::for_guild(ctx, msg.guild_id).await?;
+    let reply = match settings.get(&key).map_err(CommandError::user)? {
+        Some(value) => format!("{} = {}", key, value),
+        None => format!("{} is not set", key),
+    };
+    msg.channel_id.say(&ctx.http, reply).await?;
+
+    Ok(())
+}
+
+#[command("set")]
+#[description("Change a setting")]
+#[usage("<key> <value>")]
+async fn set_setting(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let key = key(&mut args)?;
+    let value = args.rest().trim();
+    if value.is_empty() {
+        return Err(CommandError::user("Must provide a value").into());
+    }
+
+    update(ctx, msg, |settings| settings.set(&key, value)).await?;
+    Ok(())
+}
+
+#[command("unset")]
+#[description("Reset a setting to its default")]
+#[usage("<key>")]
+async fn unset_setting(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let key = key(&mut args)?;
+
+    update(ctx, msg, |settings| settings.unset(&key)).await?;
+    Ok(())
+}
+
+#[command("list")]
+#[description("Show every setting that has been changed")]
+async fn list_settings(ctx: &Context, msg: &Message) -> CommandResult {
+    let settings = settings::for_guild(ctx, msg.guild_id).await?;
+    let lines: Vec<String> = settings
+        .list()
+        .iter()
+        .map(|(key, value)| format!("{} = {}", key, value))
+        .collect();
+
+    if lines.is_empty() {
+        msg.channel_id
+            .say(&ctx.http, "Everything is set to the defaults")
+            .await?;
+    } else {
+        msg.channel_id
+            .say(&ctx.http, format!("```\n{}\n```", lines.join("\n")))
+            .await?;
+    }
+
+    Ok(())
+}
+
+/// Applies a change to the guild's settings and saves them if it worked.
+pub async fn update<F>(ctx: &Context, msg: &Message, change: F) -> CommandResult
+where
+    F: FnOnce(&mut settings::GuildSettings) -> Result<(), String>,
+{
+    let guild_id = msg
+        .guild_id
+        .ok_or_else(|| CommandError::user("This command only works in a server"))?;
+
+    let db = crate::db::get(ctx).await;
+    let mut guild_settings = settings::get(&db, guild_id)?;
+    change(&mut guild_settings).map_err(CommandError::user)?;
+    settings::save(&db, guild_id, &guild_settings)?;
+    msg.channel_id.say(&ctx.http, "Saved").await?;
+
+    Ok(())
+}
+
+fn key(args: &mut Args) -> Result<String, CommandError> {
+    args.single::<String>()
+        .map_err(|_| CommandError::user(format!("Must provide a key: {}", KEYS.join(", "))))
+}
+
+#[command]
+#[description("Show or change the command prefix for this server")]
+#[usage("[prefix]")]
+#[only_in(guilds)]
+#[required_permissions("ADMINISTRATOR")]
+async fn prefix(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let new_prefix = match args.single::<String>() {
+        Ok(new_prefix) => new_prefix,
+        Err(_) => {
+            let prefix = crate::settings::prefix(ctx, msg.guild_id).await;
+            msg.channel_id
+                .say(&ctx.http, format!("The prefix is `{}`", prefix))
+                .await?;
+            return Ok(());
+        }
+    };
+
+    update(ctx, msg, |settings| settings.set("prefix", &new_prefix)).await?;
+    Ok(())
+}
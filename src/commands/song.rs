@@ -1,5 +1,12 @@
-use crate::util::LavalinkKey;
-use lavalink_rs::LavalinkClient;
+use crate::{
+    cooldown::{self, PLAY},
+    error::CommandError,
+    pages, picker,
+    services::{
+        discord::{now_playing_embed, queue_embed, search_embed, song_service, ChannelMessenger},
+        Invocation,
+    },
+};
 use serenity::{
     framework::standard::{macros::command, Args, CommandResult},
     model::prelude::*,
@@ -10,239 +17,324 @@ use serenity::{
 #[description("Tell cantdrown to join your voice channel")]
 #[only_in(guilds)]
 async fn join(ctx: &Context, msg: &Message) -> CommandResult {
-    let guild = match msg.guild(&ctx.cache).await {
-        Some(guild) => guild,
-        None => {
-            log::error!("Could not get guild");
-            return Ok(());
-        }
-    };
-    let guild_id = guild.id;
-    let channel_id = guild
-        .voice_states
-        .get(&msg.author.id)
-        .and_then(|voice_state| voice_state.channel_id);
-
-    let connect_to = match channel_id {
-        Some(channel) => channel,
-        None => {
-            msg.channel_id
-                .say(&ctx.http, "Not in a voice channel")
-                .await?;
-            return Ok(());
-        }
-    };
-
-    let manager = songbird::get(ctx)
-        .await
-        .expect("Couldn't get Songbird voice client")
-        .clone();
-    let (_, handler) = manager.join_gateway(guild_id, connect_to).await;
-
-    match handler {
-        Ok(connection_info) => {
-            let data = ctx.data.read().await;
-            let lavalink_client = data.get::<LavalinkKey>().unwrap().clone();
-            lavalink_client
-                .create_session_with_songbird(&connection_info)
-                .await?;
-        }
-        Err(why) => {
-            msg.channel_id
-                .say(&ctx.http, format!("Error joining channel: {}", why))
-                .await?;
-        }
-    };
-
-    Ok(())
+    song_service(ctx).join(&Invocation::from(msg)).await
 }
 
 #[command]
 #[description("Tell cantdrown to get out of your voice channel")]
 #[only_in(guilds)]
 async fn leave(ctx: &Context, msg: &Message) -> CommandResult {
-    let guild = match msg.guild(&ctx.cache).await {
-        Some(guild) => guild,
-        None => {
-            log::error!("Could not get guild");
-            return Ok(());
-        }
-    };
-    let guild_id = guild.id;
-    let manager = songbird::get(ctx)
-        .await
-        .expect("Couldn't get Songbird voice client")
-        .clone();
-    let has_handler = manager.get(guild_id).is_some();
-
-    if has_handler {
-        if let Err(e) = manager.remove(guild_id).await {
-            msg.channel_id
-                .say(&ctx.http, format!("Failed: {:?}", e))
-                .await?;
-        }
-
-        {
-            let data = ctx.data.read().await;
-            let lavalink_client = data.get::<LavalinkKey>().unwrap().clone();
-            lavalink_client.destroy(guild_id).await?;
-        }
-    } else {
-        msg.channel_id
-            .say(&ctx.http, "Not in a voice channel to play in")
-            .await?;
-    }
+    song_service(ctx).leave(&Invocation::from(msg)).await
+}
 
-    Ok(())
+#[command("move")]
+#[description("Bring cantdrown to your voice channel, keeping the queue")]
+#[only_in(guilds)]
+async fn move_voice(ctx: &Context, msg: &Message) -> CommandResult {
+    song_service(ctx).follow(&Invocation::from(msg)).await
 }
 
 #[command]
-#[aliases("add", "queue")]
+#[aliases("add")]
 #[description("Play a song in the voice channel")]
-#[usage("<url>")]
-#[only_in(guilds)]
-async fn play(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
-    let guild = match msg.guild(&ctx.cache).await {
-        Some(guild) => guild,
-        None => {
-            log::error!("Could not get guild");
-            return Ok(());
-        }
-    };
-    let guild_id = guild.id;
-
-    let url = match args.single::<String>() {
-        Ok(url) => url,
-        Err(_) => {
-            msg.channel_id
-                .say(&ctx.http, "Must provide a URL to a video or audio")
-                .await?;
-            return Ok(());
-        }
-    };
+#[usage("<url | search>")]
+#[only_in(guilds)]
+async fn play(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let url = args.rest().trim();
+    if url.is_empty() {
+        return Err(CommandError::user("Must provide a URL to a video or audio").into());
+    }
+    let settings = crate::settings::for_guild(ctx, msg.guild_id).await?;
+    let invocation = Invocation::from(msg);
 
-    let lavalink_client: LavalinkClient = {
-        let data = ctx.data.read().await;
-        data.get::<LavalinkKey>().unwrap().clone()
-    };
+    cooldown::limit(
+        ctx,
+        PLAY,
+        &invocation,
+        song_service(ctx).play(
+            &ChannelMessenger::new(ctx, msg.channel_id),
+            &invocation,
+            &settings,
+            url,
+        ),
+    )
+    .await
+}
 
-    let manager = songbird::get(ctx).await.unwrap().clone();
-
-    if let Some(_handler) = manager.get(guild_id) {
-        let query_info = lavalink_client.auto_search_tracks(&url).await?;
-
-        if query_info.tracks.is_empty() {
-            msg.channel_id
-                .say(&ctx.http, "Could not find video")
-                .await?;
-            return Ok(());
-        }
-
-        for track in query_info.tracks.iter() {
-            if let Err(why) = &lavalink_client.play(guild_id, track.clone()).queue().await {
-                log::error!("{}", why);
-                return Ok(());
-            }
-        }
-
-        if query_info.tracks.len() > 1 {
-            msg.channel_id
-                .say(
-                    &ctx.http,
-                    format!("Added {} songs to the queue", query_info.tracks.len()),
-                )
-                .await?;
-        } else {
-            msg.channel_id
-                .say(
-                    &ctx.http,
-                    format!(
-                        "Added song to queue: {}",
-                        query_info.tracks[0].info.as_ref().unwrap().title
-                    ),
-                )
-                .await?;
-        }
+#[command]
+#[description("Search for songs and pick which ones to play")]
+#[usage("<query>")]
+#[example("doom e1m1")]
+#[only_in(guilds)]
+async fn search(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let query = args.rest().trim();
+    if query.is_empty() {
+        return Err(CommandError::user("Must provide something to search for").into());
     }
+    let service = song_service(ctx);
+    let invocation = Invocation::from(msg);
 
-    Ok(())
+    let results = service.search(&invocation, query).await?;
+    let message = msg
+        .channel_id
+        .send_message(&ctx.http, |m| m.embed(|e| search_embed(e, query, &results)))
+        .await?;
+    let picks = match picker::pick(ctx, &message, msg.author.id, results.len()).await? {
+        Some(picks) => picks,
+        None => return Ok(()),
+    };
+
+    // Only counts once something was picked.
+    let settings = crate::settings::for_guild(ctx, msg.guild_id).await?;
+    cooldown::limit(
+        ctx,
+        PLAY,
+        &invocation,
+        service.play_tracks(
+            &ChannelMessenger::new(ctx, msg.channel_id),
+            &invocation,
+            &settings,
+            picks.into_iter().map(|i| results[i].clone()).collect(),
+        ),
+    )
+    .await
 }
 
 #[command]
 #[description("Skip the current song in the queue")]
 #[only_in(guilds)]
 async fn skip(ctx: &Context, msg: &Message) -> CommandResult {
-    let guild = match msg.guild(&ctx.cache).await {
-        Some(guild) => guild,
-        None => {
-            log::error!("Could not get guild");
-            return Ok(());
-        }
-    };
-    let guild_id = guild.id;
-
-    let data = ctx.data.read().await;
-    let lavalink_client = data.get::<LavalinkKey>().unwrap().clone();
-
-    if let Some(track) = lavalink_client.skip(guild_id).await {
-        msg.channel_id
-            .say(
-                &ctx.http,
-                format!("Skipped: {}", track.track.info.as_ref().unwrap().title),
-            )
-            .await?;
-    }
-
-    Ok(())
+    song_service(ctx)
+        .skip(
+            &ChannelMessenger::new(ctx, msg.channel_id),
+            &Invocation::from(msg),
+        )
+        .await
 }
 
 #[command]
 #[description("Stop playing music in the voice channel")]
 #[only_in(guilds)]
 async fn stop(ctx: &Context, msg: &Message) -> CommandResult {
-    let guild = match msg.guild(&ctx.cache).await {
-        Some(guild) => guild,
-        None => {
-            log::error!("Could not get guild");
-            return Ok(());
-        }
-    };
-    let guild_id = guild.id;
+    song_service(ctx).stop(&Invocation::from(msg)).await
+}
 
-    let data = ctx.data.read().await;
-    let lavalink_client = data.get::<LavalinkKey>().unwrap().clone();
+#[command]
+#[description("Show the songs in the queue")]
+#[usage("[page]")]
+#[only_in(guilds)]
+async fn queue(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let listing = song_service(ctx).queue(&Invocation::from(msg)).await?;
+    let page = args
+        .single::<usize>()
+        .unwrap_or(1)
+        .clamp(1, listing.page_count());
 
-    lavalink_client.stop(guild_id).await?;
+    let message = msg
+        .channel_id
+        .send_message(&ctx.http, |m| m.embed(|e| queue_embed(e, &listing, page)))
+        .await?;
+    pages::paginate(
+        ctx,
+        message,
+        msg.author.id,
+        page,
+        listing.page_count(),
+        |e, page| queue_embed(e, &listing, page),
+    )
+    .await
+}
 
-    Ok(())
+#[command]
+#[description("Pause the current song")]
+#[only_in(guilds)]
+async fn pause(ctx: &Context, msg: &Message) -> CommandResult {
+    song_service(ctx)
+        .pause(
+            &ChannelMessenger::new(ctx, msg.channel_id),
+            &Invocation::from(msg),
+        )
+        .await
 }
 
 #[command]
-#[description("Get the URL for the current song")]
+#[description("Keep playing the paused song")]
 #[only_in(guilds)]
-async fn current(ctx: &Context, msg: &Message) -> CommandResult {
-    let guild = match msg.guild(&ctx.cache).await {
-        Some(guild) => guild,
-        None => {
-            log::error!("Could not get guild");
-            return Ok(());
-        }
+async fn resume(ctx: &Context, msg: &Message) -> CommandResult {
+    song_service(ctx)
+        .resume(
+            &ChannelMessenger::new(ctx, msg.channel_id),
+            &Invocation::from(msg),
+        )
+        .await
+}
+
+#[command]
+#[description("Jump to a time in the current song, or skip ahead or back")]
+#[usage("<mm:ss | +30s | -30s>")]
+#[example("1:30")]
+#[example("+30s")]
+#[only_in(guilds)]
+async fn seek(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let target = args
+        .single::<String>()
+        .map_err(|_| CommandError::user("Must provide a time to seek to"))?;
+
+    song_service(ctx)
+        .seek(
+            &ChannelMessenger::new(ctx, msg.channel_id),
+            &Invocation::from(msg),
+            &target,
+        )
+        .await
+}
+
+#[command]
+#[description("Show or change the volume, from 0 to 150")]
+#[usage("[volume]")]
+#[only_in(guilds)]
+async fn volume(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let volume = match args.single::<String>() {
+        Ok(volume) => Some(
+            volume
+                .trim_end_matches('%')
+                .parse::<u16>()
+                .map_err(|_| CommandError::user("The volume must be a number"))?,
+        ),
+        Err(_) => None,
     };
-    let guild_id = guild.id;
-
-    let data = ctx.data.read().await;
-    let lavalink_client = data.get::<LavalinkKey>().unwrap().clone();
-
-    if let Some(node) = lavalink_client.nodes().await.get(guild_id.as_u64()) {
-        if let Some(track) = &node.now_playing {
-            msg.channel_id
-                .say(
-                    &ctx.http,
-                    format!("Now playing: {}", track.track.info.as_ref().unwrap().title),
-                )
-                .await?;
-        }
+    let settings = crate::settings::for_guild(ctx, msg.guild_id).await?;
+
+    song_service(ctx)
+        .volume(
+            &ChannelMessenger::new(ctx, msg.channel_id),
+            &Invocation::from(msg),
+            &settings,
+            volume,
+        )
+        .await?;
+    if let (Some(guild_id), Some(volume)) = (msg.guild_id, volume) {
+        crate::settings::modify(ctx, guild_id, |settings| settings.volume = Some(volume)).await?;
     }
+    Ok(())
+}
+
+#[command("loop")]
+#[description("Loop the current song or the whole queue")]
+#[usage("[track | queue | off]")]
+#[only_in(guilds)]
+async fn loop_mode(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let mode = args.single::<String>().ok();
+
+    song_service(ctx)
+        .set_loop(
+            &ChannelMessenger::new(ctx, msg.channel_id),
+            &Invocation::from(msg),
+            mode.as_deref(),
+        )
+        .await
+}
+
+#[command]
+#[description("Remove a song, or a range of songs, from the queue")]
+#[usage("<position> [end]")]
+#[example("3")]
+#[example("5 20")]
+#[only_in(guilds)]
+async fn remove(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let start = position(&mut args)?;
+    let end = args.single::<usize>().ok();
+
+    song_service(ctx)
+        .remove(
+            &ChannelMessenger::new(ctx, msg.channel_id),
+            &Invocation::from(msg),
+            start,
+            end,
+        )
+        .await
+}
+
+#[command]
+#[description("Move a song to another position in the queue")]
+#[usage("<from> <to>")]
+#[example("7 1")]
+#[only_in(guilds)]
+async fn reorder(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let from = position(&mut args)?;
+    let to = position(&mut args)?;
+
+    song_service(ctx)
+        .reorder(
+            &ChannelMessenger::new(ctx, msg.channel_id),
+            &Invocation::from(msg),
+            from,
+            to,
+        )
+        .await
+}
 
+#[command]
+#[description("Shuffle the songs after the current one")]
+#[only_in(guilds)]
+async fn shuffle(ctx: &Context, msg: &Message) -> CommandResult {
+    song_service(ctx)
+        .shuffle(
+            &ChannelMessenger::new(ctx, msg.channel_id),
+            &Invocation::from(msg),
+        )
+        .await
+}
+
+#[command]
+#[description("Empty the queue but keep playing the current song")]
+#[only_in(guilds)]
+async fn clear(ctx: &Context, msg: &Message) -> CommandResult {
+    song_service(ctx)
+        .clear(
+            &ChannelMessenger::new(ctx, msg.channel_id),
+            &Invocation::from(msg),
+        )
+        .await
+}
+
+#[command]
+#[description("Skip straight to a song in the queue")]
+#[usage("<position>")]
+#[only_in(guilds)]
+async fn jump(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let position = position(&mut args)?;
+
+    song_service(ctx)
+        .jump(
+            &ChannelMessenger::new(ctx, msg.channel_id),
+            &Invocation::from(msg),
+            position,
+        )
+        .await
+}
+
+#[command]
+#[description("Show the song that's playing and what's next")]
+#[only_in(guilds)]
+async fn current(ctx: &Context, msg: &Message) -> CommandResult {
+    let now_playing = song_service(ctx)
+        .now_playing(&Invocation::from(msg))
+        .await?;
+    let message = msg
+        .channel_id
+        .send_message(&ctx.http, |m| {
+            m.embed(|e| now_playing_embed(e, &now_playing))
+        })
+        .await?;
+
+    if let Some(guild_id) = msg.guild_id {
+        crate::music::show_now_playing(ctx, guild_id, &message).await?;
+    }
     Ok(())
 }
+
+fn position(args: &mut Args) -> Result<usize, CommandError> {
+    args.single::<usize>()
+        .map_err(|_| CommandError::user("Must provide a position in the queue"))
+}
@@ -1,10 +1,13 @@
-use home::home_dir;
-use serde::Deserialize;
+use super::ConfigError;
+use serde::{Deserialize, Serialize};
 use serenity::{
     client::{ClientBuilder, Context},
     prelude::TypeMapKey,
 };
-use std::{fs::File, io::Read, path::PathBuf};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
 
 pub struct DoomConfigKey;
 
@@ -12,7 +15,8 @@ impl TypeMapKey for DoomConfigKey {
     type Value = DoomConfig;
 }
 
-#[derive(Deserialize, Clone)]
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
 pub struct DoomConfig {
     pub executable: String,
     pub arguments: String,
@@ -24,9 +28,43 @@ pub struct DoomConfig {
     pub idgames_mirror: String,
 
     pub timeout: u64,
+    pub extend_time: u64,
+    pub idle_timeout: Option<u64>,
+    pub vote_time: u64,
+
+    pub presets: HashMap<String, Preset>,
+
+    pub schedule_lead: u64,
+    pub schedule_grace: u64,
+    pub utc_offset: i32,
+
+    pub on_shutdown: ShutdownAction,
+}
+
+impl Default for DoomConfig {
+    fn default() -> Self {
+        DoomConfig {
+            executable: String::from("zandronum-server"),
+            arguments: String::new(),
+            base_name: String::from("cantdrown"),
+            iwads: IWads::default(),
+            wads_path: String::new(),
+            idgames_mirror: String::from("https://www.gamers.org/pub/idgames"),
+            timeout: 3 * 60 * 60,
+            extend_time: 30 * 60,
+            idle_timeout: None,
+            vote_time: 30,
+            presets: HashMap::new(),
+            schedule_lead: 10 * 60,
+            schedule_grace: 15 * 60,
+            utc_offset: 0,
+            on_shutdown: ShutdownAction::default(),
+        }
+    }
 }
 
-#[derive(Deserialize, Clone)]
+#[derive(Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
 pub struct IWads {
     pub doom: String,
     pub doom2: String,
@@ -34,44 +72,187 @@ pub struct IWads {
     pub plutonia: String,
 }
 
-pub fn register(client_builder: ClientBuilder) -> ClientBuilder {
-    let path = get_config_path();
-    let mut file = File::open(&path).expect("Could not open doom config");
+/// What to do with running servers when the bot shuts down.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ShutdownAction {
+    Stop,
+    Detach,
+}
 
-    let mut buffer = String::new();
-    file.read_to_string(&mut buffer)
-        .expect("Could not read doom config");
+impl Default for ShutdownAction {
+    fn default() -> Self {
+        ShutdownAction::Stop
+    }
+}
 
-    let config: DoomConfig = toml::from_str(&buffer).expect("Could not parse doom config");
-    client_builder.type_map_insert::<DoomConfigKey>(config)
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Preset {
+    pub iwad: String,
+    pub url: String,
 }
 
-pub async fn get(context: &Context) -> DoomConfig {
+/// Parses the `[doom]` section and checks that the files it points to exist.
+pub fn from_value(value: toml::Value, path: &Path) -> Result<DoomConfig, ConfigError> {
+    let config: DoomConfig = value.try_into().map_err(|source| ConfigError::Parse {
+        path: path.to_path_buf(),
+        source,
+    })?;
+
+    let mut problems = Vec::new();
+    if !executable_exists(&config.executable) {
+        problems.push((
+            String::from("doom.executable"),
+            format!("\"{}\" could not be found", config.executable),
+        ));
+    }
+    let iwads = [
+        ("doom.iwads.doom", &config.iwads.doom),
+        ("doom.iwads.doom2", &config.iwads.doom2),
+        ("doom.iwads.tnt", &config.iwads.tnt),
+        ("doom.iwads.plutonia", &config.iwads.plutonia),
+    ];
+    for (field, iwad) in iwads.iter() {
+        if !Path::new(iwad).is_file() {
+            problems.push((field.to_string(), format!("\"{}\" does not exist", iwad)));
+        }
+    }
+    if !Path::new(&config.wads_path).is_dir() {
+        problems.push((
+            String::from("doom.wads_path"),
+            format!("\"{}\" is not a directory", config.wads_path),
+        ));
+    }
+    for (name, preset) in config.presets.iter() {
+        if crate::doom::resolve_iwad(&config, &preset.iwad).is_none() {
+            problems.push((
+                format!("doom.presets.{}.iwad", name),
+                format!("\"{}\" is not an IWAD", preset.iwad),
+            ));
+        }
+    }
+
+    if problems.is_empty() {
+        Ok(config)
+    } else {
+        Err(ConfigError::Invalid {
+            path: path.to_path_buf(),
+            problems,
+        })
+    }
+}
+
+/// Doom config, or `None` if it could not be loaded.
+pub async fn get(context: &Context) -> Option<DoomConfig> {
     let data = context.data.read().await;
-    let config = data
-        .get::<DoomConfigKey>()
-        .expect("Doom config is not in TypeMap");
-    config.clone()
+    data.get::<DoomConfigKey>().cloned()
 }
 
-fn get_config_path() -> PathBuf {
-    if let Ok(dir) = std::env::var("CANTDROWN_CONFIG_DIR") {
-        let mut path = PathBuf::from(dir);
-        path.push("doom.toml");
-        path
-    } else {
-        let mut path = home_dir().expect("Could not find home directory");
-        path.push(".config/cantdrown/doom.toml");
-        path
+/// Checks for the executable either at its path or on the `PATH`.
+fn executable_exists(executable: &str) -> bool {
+    let path = Path::new(executable);
+    if path.components().count() > 1 {
+        return path.is_file();
     }
+
+    std::env::var_os("PATH")
+        .map(|paths| std::env::split_paths(&paths).any(|dir| dir.join(path).is_file()))
+        .unwrap_or(false)
+}
+
+/// Path of the standalone Doom config used before `cantdrown.toml`.
+pub fn get_legacy_config_path() -> PathBuf {
+    super::config_dir().join("doom.toml")
 }
 
 pub trait DoomConfigInit {
-    fn register_doom(self) -> Self;
+    fn register_doom(self, config: Option<DoomConfig>) -> Self;
 }
 
 impl DoomConfigInit for ClientBuilder<'_> {
-    fn register_doom(self) -> Self {
-        register(self)
+    fn register_doom(self, config: Option<DoomConfig>) -> Self {
+        match config {
+            Some(config) => self.type_map_insert::<DoomConfigKey>(config),
+            None => self,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn doom_section(executable: &Path, dir: &Path) -> toml::Value {
+        let iwad = dir.join("doom2.wad");
+        std::fs::write(&iwad, b"IWAD").unwrap();
+        let iwad = toml::Value::String(iwad.display().to_string());
+        let mut iwads = toml::value::Table::new();
+        for name in crate::doom::IWADS {
+            iwads.insert(name.to_string(), iwad.clone());
+        }
+
+        let mut section = toml::value::Table::new();
+        section.insert(
+            String::from("executable"),
+            toml::Value::String(executable.display().to_string()),
+        );
+        section.insert(
+            String::from("wads_path"),
+            toml::Value::String(dir.display().to_string()),
+        );
+        section.insert(String::from("iwads"), toml::Value::Table(iwads));
+        toml::Value::Table(section)
+    }
+
+    #[test]
+    fn accepts_files_that_exist() {
+        let dir = tempfile::tempdir().unwrap();
+        let executable = std::env::current_exe().unwrap();
+
+        let config = from_value(
+            doom_section(&executable, dir.path()),
+            Path::new("doom.toml"),
+        )
+        .unwrap();
+        assert_eq!(config.executable, executable.display().to_string());
+        assert_eq!(config.timeout, DoomConfig::default().timeout);
+    }
+
+    #[test]
+    fn reports_every_problem() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut section = doom_section(Path::new("/no/such/zandronum"), dir.path());
+        let table = section.as_table_mut().unwrap();
+        table.remove("iwads");
+        let preset: toml::Value = toml::from_str("iwad = \"quake\"\nurl = \"scythe.zip\"").unwrap();
+        let mut presets = toml::value::Table::new();
+        presets.insert(String::from("scythe"), preset);
+        table.insert(String::from("presets"), toml::Value::Table(presets));
+
+        let problems = match from_value(section, Path::new("doom.toml")) {
+            Err(ConfigError::Invalid { problems, .. }) => problems,
+            _ => panic!("Expected the config to be rejected"),
+        };
+        let fields: Vec<&str> = problems.iter().map(|(field, _)| field.as_str()).collect();
+        assert_eq!(
+            fields,
+            vec![
+                "doom.executable",
+                "doom.iwads.doom",
+                "doom.iwads.doom2",
+                "doom.iwads.tnt",
+                "doom.iwads.plutonia",
+                "doom.presets.scythe.iwad",
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_the_wrong_types() {
+        let value: toml::Value = toml::from_str("timeout = \"forever\"").unwrap();
+        assert!(matches!(
+            from_value(value, Path::new("doom.toml")),
+            Err(ConfigError::Parse { .. })
+        ));
     }
 }
@@ -0,0 +1,177 @@
+use super::{doom::DoomConfig, set, Config, ConfigError};
+use std::{collections::BTreeMap, path::Path};
+
+/// Environment variables that were used before `cantdrown.toml` existed.
+const LEGACY_VARIABLES: &[(&str, &str)] = &[
+    ("bot.token", "DISCORD_TOKEN"),
+    ("lavalink.password", "LAVALINK_PASSWORD"),
+    ("logging.directory", "CANTDROWN_LOG_DIR"),
+];
+
+/// Overrides config keys with `CANTDROWN_*` environment variables. The
+/// variable for `doom.iwads.doom2` is `CANTDROWN_DOOM_IWADS_DOOM2`.
+pub fn apply_overrides(value: &mut toml::Value, path: &Path) -> Result<(), ConfigError> {
+    apply_overrides_from(value, path, |name| std::env::var(name).ok())
+}
+
+/// Overrides config keys with the variables `lookup` finds.
+fn apply_overrides_from(
+    value: &mut toml::Value,
+    path: &Path,
+    lookup: impl Fn(&str) -> Option<String>,
+) -> Result<(), ConfigError> {
+    // Every key that can be overridden, along with a value of the right type.
+    let mut keys = BTreeMap::new();
+    flatten_typed("", &template(), &mut keys);
+    flatten_typed("", value, &mut keys);
+
+    let mut problems = Vec::new();
+    for (key, example) in keys.iter() {
+        let legacy = LEGACY_VARIABLES
+            .iter()
+            .find(|(legacy_key, _)| legacy_key == key)
+            .map(|(_, variable)| *variable);
+        let name = variable_name(key);
+
+        let (name, raw) = match lookup(&name) {
+            Some(raw) => (name, raw),
+            None => match legacy.and_then(&lookup) {
+                Some(raw) => (legacy.unwrap().to_string(), raw),
+                None => continue,
+            },
+        };
+
+        match parse_like(example, &raw) {
+            Some(parsed) => set(value, key, parsed),
+            None => problems.push((name, format!("expected a {}", example.type_str()))),
+        }
+    }
+
+    if problems.is_empty() {
+        Ok(())
+    } else {
+        Err(ConfigError::Invalid {
+            path: path.to_path_buf(),
+            problems,
+        })
+    }
+}
+
+fn variable_name(key: &str) -> String {
+    format!("CANTDROWN_{}", key.replace('.', "_").to_uppercase())
+}
+
+/// A config with every key filled in, including the Doom section.
+fn template() -> toml::Value {
+    let mut value = toml::Value::try_from(Config::default()).expect("Config is always valid TOML");
+    let doom = toml::Value::try_from(DoomConfig::default()).expect("Config is always valid TOML");
+    set(&mut value, "doom", doom);
+
+    // Unset optional keys are left out when serializing.
+    set(&mut value, "doom.idle_timeout", toml::Value::Integer(0));
+    value
+}
+
+/// Collects every leaf key along with its value, so its type is known.
+fn flatten_typed(prefix: &str, value: &toml::Value, keys: &mut BTreeMap<String, toml::Value>) {
+    match value {
+        toml::Value::Table(table) => {
+            for (key, value) in table.iter() {
+                let key = if prefix.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{}.{}", prefix, key)
+                };
+                flatten_typed(&key, value, keys);
+            }
+        }
+        value => {
+            keys.entry(prefix.to_string())
+                .or_insert_with(|| value.clone());
+        }
+    }
+}
+
+/// Parses an environment variable as the same type as `example`.
+fn parse_like(example: &toml::Value, raw: &str) -> Option<toml::Value> {
+    match example {
+        toml::Value::String(_) => Some(toml::Value::String(raw.to_string())),
+        toml::Value::Integer(_) => raw.parse().ok().map(toml::Value::Integer),
+        toml::Value::Float(_) => raw.parse().ok().map(toml::Value::Float),
+        toml::Value::Boolean(_) => raw.parse().ok().map(toml::Value::Boolean),
+        _ => {
+            let document: toml::Value = toml::from_str(&format!("value = {}", raw)).ok()?;
+            document.get("value").cloned()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn apply(variables: &[(&str, &str)]) -> Result<toml::Value, ConfigError> {
+        let variables: HashMap<String, String> = variables
+            .iter()
+            .map(|(name, raw)| (name.to_string(), raw.to_string()))
+            .collect();
+        let mut value = toml::Value::Table(toml::value::Table::new());
+        apply_overrides_from(&mut value, Path::new("cantdrown.toml"), |name| {
+            variables.get(name).cloned()
+        })?;
+        Ok(value)
+    }
+
+    #[test]
+    fn overrides_keep_their_types() {
+        let value = apply(&[
+            ("CANTDROWN_ROLES_HOIST", "true"),
+            ("CANTDROWN_LAVALINK_PORT", "2334"),
+            ("CANTDROWN_DOOM_IWADS_DOOM2", "/iwads/doom2.wad"),
+            ("DISCORD_TOKEN", "token"),
+        ])
+        .unwrap();
+
+        assert_eq!(value["roles"]["hoist"], toml::Value::Boolean(true));
+        assert_eq!(value["lavalink"]["port"], toml::Value::Integer(2334));
+        assert_eq!(
+            value["doom"]["iwads"]["doom2"],
+            toml::Value::String(String::from("/iwads/doom2.wad"))
+        );
+        assert_eq!(
+            value["bot"]["token"],
+            toml::Value::String(String::from("token"))
+        );
+    }
+
+    #[test]
+    fn unknown_variables_are_ignored() {
+        let value = apply(&[("CANTDROWN_NOT_A_KEY", "1")]).unwrap();
+        assert_eq!(value, toml::Value::Table(toml::value::Table::new()));
+    }
+
+    #[test]
+    fn bad_values_are_reported() {
+        let problems = match apply(&[
+            ("CANTDROWN_LAVALINK_PORT", "many"),
+            ("CANTDROWN_ROLES_HOIST", "yes"),
+        ]) {
+            Err(ConfigError::Invalid { problems, .. }) => problems,
+            _ => panic!("Expected the values to be rejected"),
+        };
+        assert_eq!(
+            problems,
+            vec![
+                (
+                    String::from("CANTDROWN_LAVALINK_PORT"),
+                    String::from("expected a integer")
+                ),
+                (
+                    String::from("CANTDROWN_ROLES_HOIST"),
+                    String::from("expected a boolean")
+                ),
+            ]
+        );
+    }
+}
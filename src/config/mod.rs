@@ -1 +1,513 @@
 pub mod doom;
+mod env;
+pub mod watch;
+
+use doom::{DoomConfig, DoomConfigKey};
+use home::home_dir;
+use serde::{Deserialize, Serialize};
+use serenity::{
+    client::Context,
+    prelude::{RwLock, TypeMap, TypeMapKey},
+};
+use std::{
+    collections::BTreeMap,
+    fmt, fs, io,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+const REDACTED_KEYS: &[&str] = &["token", "password"];
+/// Only read at startup, so changing these does nothing until a restart.
+const RESTART_KEYS: &[&str] = &["bot.token", "lavalink", "logging"];
+
+pub struct ConfigKey;
+
+impl TypeMapKey for ConfigKey {
+    type Value = Config;
+}
+
+/// Settings for every part of the bot, read from `cantdrown.toml`.
+#[derive(Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct Config {
+    pub bot: BotConfig,
+    pub lavalink: LavalinkConfig,
+    pub roles: RoleConfig,
+    pub logging: LoggingConfig,
+    pub cooldowns: CooldownConfig,
+    pub music: MusicConfig,
+    #[serde(skip_deserializing, skip_serializing_if = "Option::is_none")]
+    pub doom: Option<DoomConfig>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct BotConfig {
+    pub token: String,
+    pub prefix: String,
+}
+
+impl Default for BotConfig {
+    fn default() -> Self {
+        BotConfig {
+            token: String::new(),
+            prefix: String::from("!"),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct LavalinkConfig {
+    pub host: String,
+    pub port: u16,
+    pub password: String,
+}
+
+impl Default for LavalinkConfig {
+    fn default() -> Self {
+        LavalinkConfig {
+            host: String::from("127.0.0.1"),
+            port: 2333,
+            password: String::from("youshallnotpass"),
+        }
+    }
+}
+
+/// How often the expensive commands can be used.
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct CooldownConfig {
+    pub host: Cooldown,
+    pub play: Cooldown,
+    pub role_add: Cooldown,
+}
+
+impl Default for CooldownConfig {
+    fn default() -> Self {
+        CooldownConfig {
+            host: Cooldown {
+                delay: 120,
+                per: CooldownScope::User,
+            },
+            play: Cooldown {
+                delay: 3,
+                per: CooldownScope::User,
+            },
+            role_add: Cooldown {
+                delay: 30,
+                per: CooldownScope::Guild,
+            },
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy)]
+pub struct Cooldown {
+    /// Seconds between uses, or 0 for no limit.
+    pub delay: u64,
+    #[serde(default)]
+    pub per: CooldownScope,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum CooldownScope {
+    User,
+    Guild,
+}
+
+impl Default for CooldownScope {
+    fn default() -> Self {
+        CooldownScope::User
+    }
+}
+
+/// When cantdrown leaves voice channels by itself, in seconds. 0 never leaves.
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct MusicConfig {
+    /// How long to stay once everyone else has left the channel.
+    pub empty_timeout: u64,
+    /// How long to stay when nothing is playing.
+    pub idle_timeout: u64,
+}
+
+impl Default for MusicConfig {
+    fn default() -> Self {
+        MusicConfig {
+            empty_timeout: 120,
+            idle_timeout: 600,
+        }
+    }
+}
+
+/// How roles created with `role add` are set up.
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct RoleConfig {
+    pub hoist: bool,
+    pub mentionable: bool,
+}
+
+impl Default for RoleConfig {
+    fn default() -> Self {
+        RoleConfig {
+            hoist: false,
+            mentionable: true,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct LoggingConfig {
+    pub directory: String,
+    pub level: String,
+}
+
+impl Default for LoggingConfig {
+    fn default() -> Self {
+        LoggingConfig {
+            directory: String::from("logs"),
+            level: String::from("info"),
+        }
+    }
+}
+
+impl Config {
+    /// Renders the config as TOML with secrets hidden.
+    pub fn to_redacted_string(&self) -> String {
+        let mut value = toml::Value::try_from(self).expect("Config is always valid TOML");
+        redact(&mut value);
+        toml::to_string_pretty(&value).expect("Config is always valid TOML")
+    }
+}
+
+/// The config along with why the Doom section was rejected, if it was. The
+/// rest of the bot can still run without Doom hosting.
+pub struct Loaded {
+    pub config: Config,
+    pub doom_error: Option<ConfigError>,
+}
+
+/// Why a config file could not be loaded.
+#[derive(Debug)]
+pub enum ConfigError {
+    Io {
+        path: PathBuf,
+        source: io::Error,
+    },
+    Parse {
+        path: PathBuf,
+        source: toml::de::Error,
+    },
+    Invalid {
+        path: PathBuf,
+        problems: Vec<(String, String)>,
+    },
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigError::Io { path, source } => {
+                write!(f, "Could not read {}: {}", path.display(), source)
+            }
+            ConfigError::Parse { path, source } => match source.line_col() {
+                Some((line, column)) => write!(
+                    f,
+                    "{}:{}:{}: {}",
+                    path.display(),
+                    line + 1,
+                    column + 1,
+                    source
+                ),
+                None => write!(f, "{}: {}", path.display(), source),
+            },
+            ConfigError::Invalid { path, problems } => {
+                write!(f, "{} is invalid:", path.display())?;
+                for (field, problem) in problems {
+                    write!(f, "\n  {}: {}", field, problem)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ConfigError::Io { source, .. } => Some(source),
+            ConfigError::Parse { source, .. } => Some(source),
+            ConfigError::Invalid { .. } => None,
+        }
+    }
+}
+
+/// Reads `cantdrown.toml`, falling back to the old `doom.toml` for the Doom
+/// section, and applies `CANTDROWN_*` environment overrides.
+pub fn load() -> Result<Loaded, ConfigError> {
+    let path = get_config_path();
+    let mut value = match read_toml(&path) {
+        Ok(value) => value,
+        Err(ConfigError::Io { source, .. }) if source.kind() == io::ErrorKind::NotFound => {
+            toml::Value::Table(toml::value::Table::new())
+        }
+        Err(why) => return Err(why),
+    };
+
+    let mut doom_path = path.clone();
+    let legacy_path = doom::get_legacy_config_path();
+    if value.get("doom").is_none() && legacy_path.is_file() {
+        let doom = read_toml(&legacy_path)?;
+        set(&mut value, "doom", doom);
+        doom_path = legacy_path;
+    }
+
+    env::apply_overrides(&mut value, &path)?;
+
+    let doom_value = value.as_table_mut().and_then(|table| table.remove("doom"));
+    let mut config: Config = value.try_into().map_err(|source| ConfigError::Parse {
+        path: path.clone(),
+        source,
+    })?;
+
+    let mut doom_error = None;
+    if let Some(doom_value) = doom_value {
+        match doom::from_value(doom_value, &doom_path) {
+            Ok(doom) => config.doom = Some(doom),
+            Err(why) => doom_error = Some(why),
+        }
+    }
+
+    Ok(Loaded { config, doom_error })
+}
+
+pub async fn get(context: &Context) -> Config {
+    let data = context.data.read().await;
+    let config = data.get::<ConfigKey>().expect("Config is not in TypeMap");
+    config.clone()
+}
+
+/// Re-reads the config files and swaps them in. Running servers keep the
+/// settings they were started with. Returns a description of each changed key,
+/// noting the ones that only take effect after a restart.
+pub async fn reload(data: &Arc<RwLock<TypeMap>>) -> Result<Vec<String>, ConfigError> {
+    let loaded = load()?;
+    if let Some(why) = loaded.doom_error {
+        return Err(why);
+    }
+    let config = loaded.config;
+
+    let mut data = data.write().await;
+    let changes = match data.get::<ConfigKey>() {
+        Some(old) => diff(old, &config),
+        None => Vec::new(),
+    };
+    match &config.doom {
+        Some(doom) => data.insert::<DoomConfigKey>(doom.clone()),
+        None => {
+            data.remove::<DoomConfigKey>();
+        }
+    }
+    data.insert::<ConfigKey>(config);
+
+    Ok(changes)
+}
+
+fn read_toml(path: &Path) -> Result<toml::Value, ConfigError> {
+    let buffer = fs::read_to_string(path).map_err(|source| ConfigError::Io {
+        path: path.to_path_buf(),
+        source,
+    })?;
+
+    toml::from_str(&buffer).map_err(|source| ConfigError::Parse {
+        path: path.to_path_buf(),
+        source,
+    })
+}
+
+/// Sets a dotted key, creating any tables along the way.
+fn set(value: &mut toml::Value, key: &str, new_value: toml::Value) {
+    let mut parts: Vec<&str> = key.split('.').collect();
+    let last = parts.pop().unwrap();
+
+    let mut table = value;
+    for part in parts {
+        table = table
+            .as_table_mut()
+            .expect("Config keys are always nested in tables")
+            .entry(part)
+            .or_insert_with(|| toml::Value::Table(toml::value::Table::new()));
+    }
+    if let Some(table) = table.as_table_mut() {
+        table.insert(last.to_string(), new_value);
+    }
+}
+
+fn is_secret(key: &str) -> bool {
+    let name = key.rsplit('.').next().unwrap_or(key);
+    REDACTED_KEYS.contains(&name)
+}
+
+fn needs_restart(key: &str) -> bool {
+    RESTART_KEYS.iter().any(|restart| {
+        key == *restart
+            || key
+                .strip_prefix(restart)
+                .map_or(false, |rest| rest.starts_with('.'))
+    })
+}
+
+fn redact(value: &mut toml::Value) {
+    if let Some(table) = value.as_table_mut() {
+        for (key, value) in table.iter_mut() {
+            match value {
+                toml::Value::String(secret)
+                    if REDACTED_KEYS.contains(&key.as_str()) && !secret.is_empty() =>
+                {
+                    *secret = String::from("<redacted>");
+                }
+                value => redact(value),
+            }
+        }
+    }
+}
+
+/// Lists the keys that differ between two configs.
+fn diff<T: Serialize>(old: &T, new: &T) -> Vec<String> {
+    let mut old_values = BTreeMap::new();
+    let mut new_values = BTreeMap::new();
+    if let Ok(value) = toml::Value::try_from(old) {
+        flatten("", &value, &mut old_values);
+    }
+    if let Ok(value) = toml::Value::try_from(new) {
+        flatten("", &value, &mut new_values);
+    }
+
+    let mut changes = Vec::new();
+    for (key, old_value) in old_values.iter() {
+        match new_values.get(key) {
+            Some(new_value) if new_value != old_value => {
+                if is_secret(key) {
+                    changes.push((key, String::from("changed")))
+                } else {
+                    changes.push((key, format!("{} -> {}", old_value, new_value)))
+                }
+            }
+            Some(_) => {}
+            None => changes.push((key, String::from("removed"))),
+        }
+    }
+    for (key, new_value) in new_values.iter() {
+        if !old_values.contains_key(key) {
+            if is_secret(key) {
+                changes.push((key, String::from("added")));
+            } else {
+                changes.push((key, format!("added {}", new_value)));
+            }
+        }
+    }
+
+    changes
+        .into_iter()
+        .map(|(key, change)| {
+            if needs_restart(key) {
+                format!("{}: {} (needs a restart)", key, change)
+            } else {
+                format!("{}: {}", key, change)
+            }
+        })
+        .collect()
+}
+
+fn flatten(prefix: &str, value: &toml::Value, values: &mut BTreeMap<String, String>) {
+    match value {
+        toml::Value::Table(table) => {
+            for (key, value) in table.iter() {
+                let key = if prefix.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{}.{}", prefix, key)
+                };
+                flatten(&key, value, values);
+            }
+        }
+        value => {
+            values.insert(prefix.to_string(), value.to_string());
+        }
+    }
+}
+
+/// Directory the config files are read from.
+pub fn config_dir() -> PathBuf {
+    if let Ok(dir) = std::env::var("CANTDROWN_CONFIG_DIR") {
+        PathBuf::from(dir)
+    } else {
+        let mut path = home_dir().expect("Could not find home directory");
+        path.push(".config/cantdrown");
+        path
+    }
+}
+
+pub fn get_config_path() -> PathBuf {
+    config_dir().join("cantdrown.toml")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn startup_keys_need_a_restart() {
+        assert!(needs_restart("bot.token"));
+        assert!(needs_restart("lavalink.host"));
+        assert!(needs_restart("lavalink.password"));
+        assert!(needs_restart("logging.level"));
+        // The prefix is looked up for every message.
+        assert!(!needs_restart("bot.prefix"));
+        assert!(!needs_restart("cooldowns.play.delay"));
+        assert!(!needs_restart("lavalinks.host"));
+    }
+
+    #[test]
+    fn diffs_mark_restarts_and_hide_secrets() {
+        let old = Config::default();
+        let mut new = Config::default();
+        new.bot.token = String::from("new token");
+        new.bot.prefix = String::from("?");
+        new.lavalink.port = 2334;
+        new.lavalink.password = String::from("hunter2");
+        new.music.idle_timeout = 60;
+
+        assert_eq!(
+            diff(&old, &new),
+            vec![
+                "bot.prefix: \"!\" -> \"?\"",
+                "bot.token: changed (needs a restart)",
+                "lavalink.password: changed (needs a restart)",
+                "lavalink.port: 2333 -> 2334 (needs a restart)",
+                "music.idle_timeout: 600 -> 60",
+            ]
+        );
+    }
+
+    #[test]
+    fn redacts_secrets() {
+        let mut config = Config::default();
+        config.bot.token = String::from("very secret token");
+        config.lavalink.password = String::from("hunter2");
+
+        let shown = config.to_redacted_string();
+        assert!(!shown.contains("very secret token"));
+        assert!(!shown.contains("hunter2"));
+        assert_eq!(shown.matches("<redacted>").count(), 2);
+        assert!(shown.contains("prefix = \"!\""));
+
+        // Nothing is hidden when there is nothing to hide.
+        config.bot.token = String::new();
+        assert_eq!(config.to_redacted_string().matches("<redacted>").count(), 1);
+    }
+}
@@ -0,0 +1,41 @@
+use notify::{DebouncedEvent, RecursiveMode, Watcher};
+use serenity::prelude::{RwLock, TypeMap};
+use std::{sync::Arc, time::Duration};
+
+/// Reloads the config whenever a file in the config directory changes.
+pub fn watch(data: Arc<RwLock<TypeMap>>) -> notify::Result<()> {
+    let dir = super::config_dir();
+    let (tx, rx) = std::sync::mpsc::channel();
+    let mut watcher = notify::watcher(tx, Duration::from_secs(2))?;
+    watcher.watch(&dir, RecursiveMode::NonRecursive)?;
+
+    let handle = tokio::runtime::Handle::current();
+    std::thread::spawn(move || {
+        // The watcher stops once it's dropped, so it has to live in here.
+        let _watcher = watcher;
+
+        for event in rx {
+            match event {
+                DebouncedEvent::Create(_)
+                | DebouncedEvent::Write(_)
+                | DebouncedEvent::Rename(_, _) => {
+                    let data = data.clone();
+                    handle.spawn(async move {
+                        match super::reload(&data).await {
+                            Ok(changes) if changes.is_empty() => {}
+                            Ok(changes) => {
+                                log::info!("Reloaded config:\n{}", changes.join("\n"))
+                            }
+                            Err(why) => log::error!("Could not reload config: {}", why),
+                        }
+                    });
+                }
+                DebouncedEvent::Error(why, _) => log::error!("Config watcher error: {:?}", why),
+                _ => {}
+            }
+        }
+    });
+
+    log::info!("Watching {} for changes", dir.display());
+    Ok(())
+}
@@ -0,0 +1,235 @@
+//! Cooldowns for the commands that download, spawn processes or create roles.
+//! Prefix and slash commands both run through `limit`, so they share the same
+//! limits.
+
+use crate::{
+    config::{Cooldown, CooldownScope},
+    error::CommandError,
+    services::Invocation,
+};
+use serenity::{
+    client::Context,
+    framework::standard::CommandResult,
+    model::id::{GuildId, UserId},
+    prelude::TypeMapKey,
+};
+use std::{
+    collections::HashMap,
+    future::Future,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+pub const HOST: &str = "host";
+pub const PLAY: &str = "play";
+pub const ROLE_ADD: &str = "role_add";
+
+pub struct CooldownsKey;
+
+impl TypeMapKey for CooldownsKey {
+    type Value = Cooldowns;
+}
+
+/// Who a cooldown applies to. Per-user cooldowns are kept apart in each guild.
+type Key = (&'static str, Option<GuildId>, Option<UserId>);
+
+/// When each user or guild last used a command.
+#[derive(Clone, Default)]
+pub struct Cooldowns {
+    last_used: Arc<Mutex<HashMap<Key, Instant>>>,
+}
+
+/// A use recorded by `Cooldowns::try_use`, kept so it can be taken back.
+pub struct Use {
+    key: Option<Key>,
+    at: Instant,
+    previous: Option<Instant>,
+}
+
+impl Cooldowns {
+    /// Records a use of `bucket`, or returns how long is left if it is too soon.
+    pub fn try_use(
+        &self,
+        bucket: &'static str,
+        cooldown: &Cooldown,
+        invocation: &Invocation,
+    ) -> Result<Use, Duration> {
+        let now = Instant::now();
+        let delay = Duration::from_secs(cooldown.delay);
+        if delay.as_secs() == 0 {
+            return Ok(Use {
+                key: None,
+                at: now,
+                previous: None,
+            });
+        }
+
+        let key = match (cooldown.per, invocation.guild_id) {
+            (CooldownScope::Guild, Some(guild_id)) => (bucket, Some(guild_id), None),
+            (_, guild_id) => (bucket, guild_id, Some(invocation.user_id)),
+        };
+        let mut last_used = self.last_used.lock().unwrap();
+        let previous = last_used.get(&key).copied();
+        if let Some(last) = previous {
+            let elapsed = now.duration_since(last);
+            if elapsed < delay {
+                return Err(delay - elapsed);
+            }
+        }
+        last_used.insert(key, now);
+        Ok(Use {
+            key: Some(key),
+            at: now,
+            previous,
+        })
+    }
+
+    /// Takes back a use, unless the command has been used again since.
+    pub fn undo(&self, used: Use) {
+        let key = match used.key {
+            Some(key) => key,
+            None => return,
+        };
+        let mut last_used = self.last_used.lock().unwrap();
+        if last_used.get(&key) != Some(&used.at) {
+            return;
+        }
+        match used.previous {
+            Some(previous) => last_used.insert(key, previous),
+            None => last_used.remove(&key),
+        };
+    }
+}
+
+/// Runs a command if its cooldown is up, failing with how long to wait. Uses
+/// that end in a user error, like a missing argument, don't count.
+pub async fn limit(
+    ctx: &Context,
+    bucket: &'static str,
+    invocation: &Invocation,
+    command: impl Future<Output = CommandResult>,
+) -> CommandResult {
+    let config = crate::config::get(ctx).await.cooldowns;
+    let cooldown = match bucket {
+        HOST => config.host,
+        PLAY => config.play,
+        _ => config.role_add,
+    };
+    let cooldowns = {
+        let data = ctx.data.read().await;
+        data.get::<CooldownsKey>()
+            .expect("Cooldowns are not in TypeMap")
+            .clone()
+    };
+
+    let used = cooldowns
+        .try_use(bucket, &cooldown, invocation)
+        .map_err(|remaining| CommandError::user(try_again(remaining)))?;
+    let result = command.await;
+    if let Err(error) = &result {
+        if let Some(CommandError::User(_)) = error.downcast_ref::<CommandError>() {
+            cooldowns.undo(used);
+        }
+    }
+    result
+}
+
+fn try_again(remaining: Duration) -> String {
+    format!("Try again in {} seconds", remaining.as_secs().max(1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serenity::model::id::ChannelId;
+
+    fn invocation(guild_id: u64, user_id: u64) -> Invocation {
+        Invocation {
+            guild_id: Some(GuildId(guild_id)),
+            channel_id: ChannelId(2),
+            user_id: UserId(user_id),
+        }
+    }
+
+    #[test]
+    fn user_cooldowns_are_per_guild() {
+        let cooldowns = Cooldowns::default();
+        let cooldown = Cooldown {
+            delay: 60,
+            per: CooldownScope::User,
+        };
+
+        assert!(cooldowns
+            .try_use(PLAY, &cooldown, &invocation(1, 4))
+            .is_ok());
+        assert!(cooldowns
+            .try_use(PLAY, &cooldown, &invocation(1, 4))
+            .is_err());
+        assert!(cooldowns
+            .try_use(PLAY, &cooldown, &invocation(1, 5))
+            .is_ok());
+        assert!(cooldowns
+            .try_use(PLAY, &cooldown, &invocation(7, 4))
+            .is_ok());
+        assert!(cooldowns
+            .try_use(HOST, &cooldown, &invocation(1, 4))
+            .is_ok());
+    }
+
+    #[test]
+    fn guild_cooldowns_are_shared() {
+        let cooldowns = Cooldowns::default();
+        let cooldown = Cooldown {
+            delay: 60,
+            per: CooldownScope::Guild,
+        };
+
+        assert!(cooldowns
+            .try_use(ROLE_ADD, &cooldown, &invocation(1, 4))
+            .is_ok());
+        assert!(cooldowns
+            .try_use(ROLE_ADD, &cooldown, &invocation(1, 5))
+            .is_err());
+        // A guild's id can't collide with a user's.
+        assert!(cooldowns
+            .try_use(ROLE_ADD, &cooldown, &invocation(4, 1))
+            .is_ok());
+        let direct = Invocation {
+            guild_id: None,
+            ..invocation(1, 1)
+        };
+        assert!(cooldowns.try_use(ROLE_ADD, &cooldown, &direct).is_ok());
+        assert!(cooldowns.try_use(ROLE_ADD, &cooldown, &direct).is_err());
+    }
+
+    #[test]
+    fn undone_uses_dont_count() {
+        let cooldowns = Cooldowns::default();
+        let cooldown = Cooldown {
+            delay: 60,
+            per: CooldownScope::User,
+        };
+
+        let used = cooldowns
+            .try_use(PLAY, &cooldown, &invocation(1, 4))
+            .unwrap();
+        cooldowns.undo(used);
+        assert!(cooldowns
+            .try_use(PLAY, &cooldown, &invocation(1, 4))
+            .is_ok());
+        assert!(cooldowns
+            .try_use(PLAY, &cooldown, &invocation(1, 4))
+            .is_err());
+
+        // Commands without a cooldown have nothing to undo.
+        let none = Cooldown {
+            delay: 0,
+            per: CooldownScope::User,
+        };
+        let used = cooldowns.try_use(PLAY, &none, &invocation(1, 4)).unwrap();
+        cooldowns.undo(used);
+        assert!(cooldowns
+            .try_use(PLAY, &cooldown, &invocation(1, 4))
+            .is_err());
+    }
+}
@@ -0,0 +1,62 @@
+use home::home_dir;
+use serde::{de::DeserializeOwned, Serialize};
+use serenity::{client::Context, prelude::TypeMapKey};
+use std::path::PathBuf;
+
+pub struct DbKey;
+
+impl TypeMapKey for DbKey {
+    type Value = sled::Db;
+}
+
+pub fn open() -> sled::Result<sled::Db> {
+    sled::open(get_db_path())
+}
+
+pub async fn get(context: &Context) -> sled::Db {
+    let data = context.data.read().await;
+    data.get::<DbKey>()
+        .expect("Database is not in TypeMap")
+        .clone()
+}
+
+/// Stores `value` as JSON under `key`.
+pub fn insert<K: AsRef<[u8]>, V: Serialize>(
+    tree: &sled::Tree,
+    key: K,
+    value: &V,
+) -> anyhow::Result<()> {
+    tree.insert(key, serde_json::to_vec(value)?)?;
+    Ok(())
+}
+
+/// Reads the JSON value stored under `key`.
+pub fn get_value<K: AsRef<[u8]>, V: DeserializeOwned>(
+    tree: &sled::Tree,
+    key: K,
+) -> anyhow::Result<Option<V>> {
+    match tree.get(key)? {
+        Some(bytes) => Ok(Some(serde_json::from_slice(&bytes)?)),
+        None => Ok(None),
+    }
+}
+
+/// Reads every JSON value in the tree, in key order.
+pub fn values<V: DeserializeOwned>(tree: &sled::Tree) -> anyhow::Result<Vec<V>> {
+    tree.iter()
+        .values()
+        .map(|bytes| Ok(serde_json::from_slice(&bytes?)?))
+        .collect()
+}
+
+fn get_db_path() -> PathBuf {
+    if let Ok(dir) = std::env::var("CANTDROWN_DATA_DIR") {
+        let mut path = PathBuf::from(dir);
+        path.push("db");
+        path
+    } else {
+        let mut path = home_dir().expect("Could not find home directory");
+        path.push(".local/share/cantdrown/db");
+        path
+    }
+}
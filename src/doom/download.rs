@@ -0,0 +1,105 @@
+use anyhow::Context;
+use bytes::Buf;
+use glob::glob;
+use std::{
+    fs::File,
+    io::copy,
+    path::{Path, PathBuf},
+};
+use url::Url;
+
+/// Downloads the zip at `url` and returns the paths of the WADs inside it.
+pub async fn download_wads(url: &str, idgames_mirror: &str) -> anyhow::Result<Vec<PathBuf>> {
+    let mut download_url = Some(String::from(url));
+
+    if url.contains("doomworld.com/idgames") {
+        download_url = get_idgames_download_url(url, idgames_mirror);
+    } else if url.contains("dropbox.com") {
+        download_url = get_dropbox_download_url(url);
+    } else if url.contains("drive.google.com") {
+        download_url = get_google_drive_download_url(url);
+    }
+
+    let download_url = download_url.context("Could not get a download link")?;
+    let path = download_zip(&download_url).await?;
+
+    let search = format!("{}/**/*.wad", path.to_str().unwrap());
+    Ok(glob(&search)?.filter_map(Result::ok).collect())
+}
+
+async fn download_zip(url: &str) -> anyhow::Result<PathBuf> {
+    let res = reqwest::get(url)
+        .await
+        .context("Could not download zip file")?;
+
+    let mut tmpfile: File = tempfile::tempfile()?;
+    let content = res.bytes().await?;
+    let hash = blake3::Hasher::new().update(&content).finalize();
+    copy(&mut content.reader(), &mut tmpfile)?;
+
+    let mut zip = zip::ZipArchive::new(tmpfile)?;
+
+    let path = format!("./tmp/{}", hash.to_hex());
+    let path = Path::new(&path);
+    zip.extract(path)?;
+
+    Ok(path.to_path_buf())
+}
+
+fn get_idgames_download_url(url: &str, mirror: &str) -> Option<String> {
+    if let Some(index) = url.find("doomworld.com/idgames") {
+        let index = index + "doomworld.com/idgames".len();
+        let level = &url[index..];
+
+        let mut url = String::from(mirror);
+        url.push_str(level);
+        url.push_str(".zip");
+
+        return Some(url);
+    }
+
+    None
+}
+
+fn get_dropbox_download_url(url: &str) -> Option<String> {
+    let mut url = match Url::parse(url) {
+        Ok(url) => url,
+        Err(why) => {
+            log::warn!("Invalid Dropbox url {}: {}", url, why);
+            return None;
+        }
+    };
+    // All that needs to be done to get a downloadable Dropbox link is
+    // to set the query string.
+    url.set_query(Some("raw=1"));
+    Some(url.to_string())
+}
+
+fn get_google_drive_download_url(url: &str) -> Option<String> {
+    let url = match Url::parse(url) {
+        Ok(url) => url,
+        Err(why) => {
+            log::warn!("Invalid Google Drive url {}: {}", url, why);
+            return None;
+        }
+    };
+
+    // Google Drive links have the path /file/d/<id>/view
+    // We need to extract the id from the path so we can create a url that we can
+    // download the file from.
+    let mut path_segments = url.path_segments().unwrap();
+    path_segments.next();
+    path_segments.next();
+    let id = match path_segments.next() {
+        Some(id) => id,
+        None => {
+            log::warn!("Google Drive url {} has no file id", url);
+            return None;
+        }
+    };
+
+    let mut url = String::from("https://drive.google.com/uc?export=download&id=");
+    url.push_str(id);
+
+    Some(url)
+}
@@ -0,0 +1,87 @@
+use super::server::{ExitReason, ServerInfo};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serenity::model::id::{GuildId, UserId};
+use std::collections::HashMap;
+
+const TREE: &str = "history";
+
+/// A server that was hosted and has since stopped.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct HostRecord {
+    pub name: String,
+    pub host: UserId,
+    pub guild_id: Option<GuildId>,
+    pub iwad: String,
+    pub wads: Vec<String>,
+    pub started: DateTime<Utc>,
+    pub duration: u64,
+    pub exit_reason: ExitReason,
+    pub peak_players: usize,
+}
+
+impl HostRecord {
+    pub fn new(server: &ServerInfo, exit_reason: ExitReason) -> Self {
+        HostRecord {
+            name: server.name.clone(),
+            host: server.host,
+            guild_id: server.guild_id,
+            iwad: server.iwad.clone(),
+            wads: server.wads.clone(),
+            started: server.started_at,
+            duration: server.started.elapsed().as_secs(),
+            exit_reason,
+            peak_players: server.peak_players,
+        }
+    }
+}
+
+pub fn record(db: &sled::Db, record: &HostRecord) -> anyhow::Result<()> {
+    let mut key = guild_prefix(record.guild_id).to_vec();
+    key.extend_from_slice(&db.generate_id()?.to_be_bytes());
+    crate::db::insert(&db.open_tree(TREE)?, key, record)
+}
+
+/// Returns the most recently hosted servers in a guild, newest first.
+pub fn recent(
+    db: &sled::Db,
+    guild_id: Option<GuildId>,
+    limit: usize,
+) -> anyhow::Result<Vec<HostRecord>> {
+    db.open_tree(TREE)?
+        .scan_prefix(guild_prefix(guild_id))
+        .values()
+        .rev()
+        .take(limit)
+        .map(|bytes| Ok(serde_json::from_slice(&bytes?)?))
+        .collect()
+}
+
+/// Returns the WADs hosted most often in a guild along with how many times
+/// they were hosted.
+pub fn top(
+    db: &sled::Db,
+    guild_id: Option<GuildId>,
+    limit: usize,
+) -> anyhow::Result<Vec<(String, usize)>> {
+    let mut counts: HashMap<String, usize> = HashMap::new();
+    for bytes in db
+        .open_tree(TREE)?
+        .scan_prefix(guild_prefix(guild_id))
+        .values()
+    {
+        let record: HostRecord = serde_json::from_slice(&bytes?)?;
+        for wad in record.wads {
+            *counts.entry(wad).or_default() += 1;
+        }
+    }
+
+    let mut counts: Vec<(String, usize)> = counts.into_iter().collect();
+    counts.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    counts.truncate(limit);
+    Ok(counts)
+}
+
+fn guild_prefix(guild_id: Option<GuildId>) -> [u8; 8] {
+    guild_id.map(|id| id.0).unwrap_or(0).to_be_bytes()
+}
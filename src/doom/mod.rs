@@ -0,0 +1,108 @@
+pub mod download;
+pub mod history;
+pub mod schedule;
+pub mod server;
+pub mod wad;
+
+use crate::config::doom::{DoomConfig, ShutdownAction};
+use serenity::{
+    http::Http,
+    model::id::{ChannelId, GuildId, UserId},
+};
+use server::{Launch, ServerInfo, Servers};
+use std::{
+    ffi::OsStr,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+use tokio::process::Command;
+
+/// Where a server's output goes when it is left running on shutdown.
+fn log_file(config: &DoomConfig) -> PathBuf {
+    Path::new(&config.wads_path).join("logs").join(format!(
+        "{}.log",
+        chrono::Utc::now().format("%Y%m%d-%H%M%S%.3f")
+    ))
+}
+
+/// Short names of the IWADs that can be played.
+pub const IWADS: &[&str] = &["doom", "doom2", "tnt", "plutonia"];
+
+/// Looks up the path of an IWAD by its short name.
+pub fn resolve_iwad(config: &DoomConfig, name: &str) -> Option<String> {
+    match name {
+        "doom" => Some(config.iwads.doom.clone()),
+        "doom2" => Some(config.iwads.doom2.clone()),
+        "tnt" => Some(config.iwads.tnt.clone()),
+        "plutonia" => Some(config.iwads.plutonia.clone()),
+        _ => None,
+    }
+}
+
+/// Who asked for a server and where to report on it.
+#[derive(Clone, Copy)]
+pub struct Origin {
+    pub host: UserId,
+    pub guild_id: Option<GuildId>,
+    pub channel_id: ChannelId,
+}
+
+/// Starts a Zandronum server for already downloaded WADs.
+pub async fn host(
+    http: Arc<Http>,
+    servers: &Servers,
+    config: &DoomConfig,
+    iwad: &str,
+    wads: &[PathBuf],
+    origin: Origin,
+) -> anyhow::Result<ServerInfo> {
+    let mut server_name = config.base_name.clone();
+    if let Some(first_wad) = wads.first() {
+        server_name.push_str(&format!(" ({})", file_name(first_wad)));
+    }
+
+    let mut maps = vec![PathBuf::from(iwad)];
+    maps.extend(wads.iter().cloned());
+    let maps = wad::collect_maps(&maps);
+
+    let mut command = Command::new(&config.executable);
+    command
+        .arg("-host")
+        .arg("-iwad")
+        .arg(Path::new(iwad))
+        .arg("-file")
+        .args(wads)
+        .args(config.arguments.split_whitespace())
+        .arg("+sv_hostname")
+        .arg(&server_name);
+
+    let server = servers
+        .spawn(
+            http,
+            command,
+            Launch {
+                name: server_name,
+                host: origin.host,
+                guild_id: origin.guild_id,
+                channel_id: origin.channel_id,
+                iwad: file_name(Path::new(iwad)),
+                wads: wads.iter().map(|wad| file_name(wad)).collect(),
+                maps,
+                timeout: Duration::from_secs(config.timeout),
+                idle_timeout: config.idle_timeout.map(Duration::from_secs),
+                log_file: match config.on_shutdown {
+                    ShutdownAction::Detach => Some(log_file(config)),
+                    ShutdownAction::Stop => None,
+                },
+            },
+        )
+        .await?;
+
+    Ok(server)
+}
+
+fn file_name(path: &Path) -> String {
+    let name = path.file_name().unwrap_or(OsStr::new("unknown"));
+    name.to_str().unwrap_or("unknown").to_string()
+}
@@ -0,0 +1,300 @@
+use super::{download::download_wads, server::ServersKey, Origin};
+use crate::{config::doom::DoomConfigKey, db::DbKey};
+use anyhow::Context;
+use chrono::{DateTime, Duration, FixedOffset, NaiveDateTime, NaiveTime, TimeZone, Utc};
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serenity::{
+    http::Http,
+    model::id::{ChannelId, GuildId, UserId},
+    prelude::{RwLock, TypeMap},
+};
+use std::sync::Arc;
+
+const TREE: &str = "schedules";
+
+lazy_static! {
+    static ref RELATIVE_TIME: Regex = Regex::new(r"^(?:(\d+)h)?(?:(\d+)m)?$").unwrap();
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Schedule {
+    pub id: u64,
+    pub host: UserId,
+    #[serde(default)]
+    pub guild_id: Option<GuildId>,
+    pub channel_id: ChannelId,
+    pub time: DateTime<Utc>,
+    pub iwad: String,
+    pub url: String,
+}
+
+pub fn add(
+    db: &sled::Db,
+    origin: Origin,
+    time: DateTime<Utc>,
+    iwad: String,
+    url: String,
+) -> anyhow::Result<Schedule> {
+    let schedule = Schedule {
+        id: db.generate_id()?,
+        host: origin.host,
+        guild_id: origin.guild_id,
+        channel_id: origin.channel_id,
+        time,
+        iwad,
+        url,
+    };
+    crate::db::insert(&db.open_tree(TREE)?, schedule.id.to_be_bytes(), &schedule)?;
+    Ok(schedule)
+}
+
+/// Returns every pending schedule, soonest first.
+pub fn list(db: &sled::Db) -> anyhow::Result<Vec<Schedule>> {
+    let mut schedules: Vec<Schedule> = crate::db::values(&db.open_tree(TREE)?)?;
+    schedules.sort_by_key(|schedule| schedule.time);
+    Ok(schedules)
+}
+
+pub fn get(db: &sled::Db, id: u64) -> anyhow::Result<Option<Schedule>> {
+    crate::db::get_value(&db.open_tree(TREE)?, id.to_be_bytes())
+}
+
+pub fn remove(db: &sled::Db, id: u64) -> anyhow::Result<Option<Schedule>> {
+    match db.open_tree(TREE)?.remove(id.to_be_bytes())? {
+        Some(bytes) => Ok(Some(serde_json::from_slice(&bytes)?)),
+        None => Ok(None),
+    }
+}
+
+/// Starts waiting on every schedule that was stored before the bot restarted.
+/// Schedules that were missed by more than the grace period are dropped.
+pub async fn restore(http: Arc<Http>, data: Arc<RwLock<TypeMap>>) {
+    let (db, grace) = {
+        let data = data.read().await;
+        let db = data
+            .get::<DbKey>()
+            .expect("Database is not in TypeMap")
+            .clone();
+        let config = match data.get::<DoomConfigKey>() {
+            Some(config) => config,
+            None => {
+                log::warn!("Not restoring schedules since Doom hosting is disabled");
+                return;
+            }
+        };
+        (db, Duration::seconds(config.schedule_grace as i64))
+    };
+
+    let schedules = match list(&db) {
+        Ok(schedules) => schedules,
+        Err(why) => {
+            log::error!("Could not load schedules: {:?}", why);
+            return;
+        }
+    };
+
+    for schedule in schedules {
+        if schedule.time + grace < Utc::now() {
+            log::warn!("Dropping missed schedule {}", schedule.id);
+            let _ = remove(&db, schedule.id);
+            let _ = schedule
+                .channel_id
+                .say(
+                    &http,
+                    format!(
+                        "Missed the scheduled server for {} while offline",
+                        schedule.url
+                    ),
+                )
+                .await;
+        } else {
+            spawn(http.clone(), data.clone(), schedule);
+        }
+    }
+}
+
+/// Waits for the schedule's time, then downloads the WADs and hosts the server.
+pub fn spawn(http: Arc<Http>, data: Arc<RwLock<TypeMap>>, schedule: Schedule) {
+    tokio::spawn(async move {
+        if let Err(why) = run(&http, &data, &schedule).await {
+            log::error!("Scheduled server {} failed: {:?}", schedule.id, why);
+            let _ = schedule
+                .channel_id
+                .say(
+                    &http,
+                    format!("Could not start the scheduled server: {}", why),
+                )
+                .await;
+        }
+    });
+}
+
+async fn run(
+    http: &Arc<Http>,
+    data: &Arc<RwLock<TypeMap>>,
+    schedule: &Schedule,
+) -> anyhow::Result<()> {
+    let (config, db, servers) = {
+        let data = data.read().await;
+        (
+            data.get::<DoomConfigKey>()
+                .context("Doom hosting is not configured")?
+                .clone(),
+            data.get::<DbKey>()
+                .expect("Database is not in TypeMap")
+                .clone(),
+            data.get::<ServersKey>()
+                .expect("Doom servers are not in TypeMap")
+                .clone(),
+        )
+    };
+
+    // Download ahead of time so the server is ready right when it was booked.
+    sleep_until(schedule.time - Duration::seconds(config.schedule_lead as i64)).await;
+    if get(&db, schedule.id)?.is_none() {
+        return Ok(());
+    }
+    let wads = download_wads(&schedule.url, &config.idgames_mirror).await;
+
+    sleep_until(schedule.time).await;
+    if remove(&db, schedule.id)?.is_none() {
+        return Ok(());
+    }
+    let wads = wads?;
+    let iwad = super::resolve_iwad(&config, &schedule.iwad).context("Invalid IWAD")?;
+
+    let server = super::host(
+        http.clone(),
+        &servers,
+        &config,
+        &iwad,
+        &wads,
+        Origin {
+            host: schedule.host,
+            guild_id: schedule.guild_id,
+            channel_id: schedule.channel_id,
+        },
+    )
+    .await?;
+
+    // Each guild picks its own role, since role IDs only mean anything there.
+    let role = match schedule.guild_id {
+        Some(guild_id) => crate::settings::get(&db, guild_id)?.schedule_role,
+        None => None,
+    };
+    let mention = role
+        .map(|role| format!("<@&{}> ", role))
+        .unwrap_or_default();
+    schedule
+        .channel_id
+        .say(
+            http,
+            format!(
+                "{}Scheduled Zandronum server \"{}\" is up, have fun!",
+                mention, server.name
+            ),
+        )
+        .await?;
+
+    Ok(())
+}
+
+async fn sleep_until(time: DateTime<Utc>) {
+    if let Ok(duration) = (time - Utc::now()).to_std() {
+        tokio::time::sleep(duration).await;
+    }
+}
+
+/// Parses a time as `+1h30m`, `HH:MM` (the next time it comes around) or
+/// `YYYY-MM-DDTHH:MM`, in the given timezone.
+pub fn parse_time(input: &str, now: DateTime<Utc>, offset: FixedOffset) -> Option<DateTime<Utc>> {
+    if let Some(relative) = input.strip_prefix('+') {
+        let captures = RELATIVE_TIME.captures(relative)?;
+        let hours = captures.get(1).map(|m| m.as_str().parse::<i64>());
+        let minutes = captures.get(2).map(|m| m.as_str().parse::<i64>());
+        if hours.is_none() && minutes.is_none() {
+            return None;
+        }
+        let minutes = hours
+            .unwrap_or(Ok(0))
+            .ok()?
+            .checked_mul(60)?
+            .checked_add(minutes.unwrap_or(Ok(0)).ok()?)?;
+        // chrono panics on durations longer than `i64::MAX` milliseconds.
+        let seconds = minutes.checked_mul(60).filter(|s| *s <= i64::MAX / 1000)?;
+        return now.checked_add_signed(Duration::seconds(seconds));
+    }
+
+    if let Ok(datetime) = NaiveDateTime::parse_from_str(input, "%Y-%m-%dT%H:%M") {
+        return offset
+            .from_local_datetime(&datetime)
+            .single()
+            .map(|time| time.with_timezone(&Utc));
+    }
+
+    if let Ok(time) = NaiveTime::parse_from_str(input, "%H:%M") {
+        let local = now.with_timezone(&offset);
+        let mut datetime = local.date().and_time(time)?;
+        if datetime <= local {
+            datetime = datetime + Duration::days(1);
+        }
+        return Some(datetime.with_timezone(&Utc));
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn restore_without_a_doom_config() {
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        // Long missed, so it would be dropped if schedules were restored.
+        let schedule = add(
+            &db,
+            Origin {
+                host: UserId(4),
+                guild_id: Some(GuildId(1)),
+                channel_id: ChannelId(2),
+            },
+            Utc::now() - Duration::days(1),
+            String::from("doom2"),
+            String::from("https://example.com/av.zip"),
+        )
+        .unwrap();
+        let mut data = TypeMap::new();
+        data.insert::<DbKey>(db.clone());
+
+        restore(
+            Arc::new(Http::new_with_token("")),
+            Arc::new(RwLock::new(data)),
+        )
+        .await;
+
+        // Kept for when Doom hosting is configured again.
+        assert!(get(&db, schedule.id).unwrap().is_some());
+    }
+
+    #[test]
+    fn parses_relative_times() {
+        let now = Utc.ymd(2021, 6, 1).and_hms(12, 0, 0);
+        let utc = FixedOffset::east(0);
+
+        assert_eq!(
+            parse_time("+1h30m", now, utc),
+            Some(now + Duration::minutes(90))
+        );
+        assert_eq!(
+            parse_time("+45m", now, utc),
+            Some(now + Duration::minutes(45))
+        );
+        assert_eq!(parse_time("+", now, utc), None);
+        assert_eq!(parse_time("+99999999999h", now, utc), None);
+        assert_eq!(parse_time("+99999999999999999999h", now, utc), None);
+        assert_eq!(parse_time("+9223372036854775807m", now, utc), None);
+    }
+}
@@ -0,0 +1,462 @@
+use super::history::{self, HostRecord};
+use chrono::{DateTime, Utc};
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serenity::{
+    http::Http,
+    model::id::{ChannelId, GuildId, UserId},
+    prelude::TypeMapKey,
+};
+use std::{
+    collections::HashMap,
+    fmt, fs, io,
+    path::PathBuf,
+    process::Stdio,
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    process::{Child, Command},
+    sync::{mpsc, Mutex},
+};
+
+/// How often running servers are checked for timeouts.
+const CHECK_INTERVAL: Duration = Duration::from_secs(15);
+/// How long to wait for servers to stop when the bot shuts down.
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(10);
+/// How often a server's log file is checked for new output.
+const FOLLOW_INTERVAL: Duration = Duration::from_millis(500);
+
+lazy_static! {
+    static ref CONNECTED: Regex = Regex::new(r"has connected\.?$").unwrap();
+    static ref DISCONNECTED: Regex =
+        Regex::new(r"(disconnected|has left the game|timed out)").unwrap();
+}
+
+pub struct ServersKey;
+
+impl TypeMapKey for ServersKey {
+    type Value = Servers;
+}
+
+/// Why a hosted server stopped running.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum ExitReason {
+    Timeout,
+    Idle,
+    Exited,
+    Shutdown,
+    Detached,
+}
+
+impl fmt::Display for ExitReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ExitReason::Timeout => write!(f, "timed out"),
+            ExitReason::Idle => write!(f, "idle"),
+            ExitReason::Exited => write!(f, "exited"),
+            ExitReason::Shutdown => write!(f, "stopped for shutdown"),
+            ExitReason::Detached => write!(f, "detached"),
+        }
+    }
+}
+
+/// Messages sent to a server's supervisor.
+enum Control {
+    Console(String),
+    Stop,
+    Detach,
+}
+
+/// Everything needed to supervise a server besides its process.
+pub struct Launch {
+    pub name: String,
+    pub host: UserId,
+    pub guild_id: Option<GuildId>,
+    pub channel_id: ChannelId,
+    pub iwad: String,
+    pub wads: Vec<String>,
+    pub maps: Vec<String>,
+    pub timeout: Duration,
+    pub idle_timeout: Option<Duration>,
+    /// Where the server writes its output instead of a pipe, so it keeps
+    /// running after the bot exits. Its console is only reachable until then.
+    pub log_file: Option<PathBuf>,
+}
+
+#[derive(Clone)]
+pub struct ServerInfo {
+    pub id: u32,
+    pub name: String,
+    pub host: UserId,
+    pub guild_id: Option<GuildId>,
+    pub channel_id: ChannelId,
+    pub iwad: String,
+    pub wads: Vec<String>,
+    pub maps: Vec<String>,
+    pub started: Instant,
+    pub started_at: DateTime<Utc>,
+    pub deadline: Instant,
+    pub players: usize,
+    pub peak_players: usize,
+    pub empty_since: Instant,
+    has_console: bool,
+    control: mpsc::UnboundedSender<Control>,
+}
+
+impl ServerInfo {
+    pub fn has_console(&self) -> bool {
+        self.has_console
+    }
+
+    /// Sends a command to the server's console.
+    pub fn console(&self, command: &str) -> bool {
+        self.has_console
+            && self
+                .control
+                .send(Control::Console(command.to_string()))
+                .is_ok()
+    }
+}
+
+/// Registry of the Zandronum servers that are currently running.
+#[derive(Clone)]
+pub struct Servers {
+    servers: Arc<Mutex<HashMap<u32, ServerInfo>>>,
+    next_id: Arc<AtomicU32>,
+    db: sled::Db,
+}
+
+impl Servers {
+    /// Creates an empty registry that records finished servers in `db`.
+    pub fn new(db: sled::Db) -> Self {
+        Servers {
+            servers: Arc::new(Mutex::new(HashMap::new())),
+            next_id: Arc::new(AtomicU32::new(0)),
+            db,
+        }
+    }
+
+    /// Spawns the server process and supervises it until it exits, times out
+    /// or has been empty for longer than the idle timeout.
+    pub async fn spawn(
+        &self,
+        http: Arc<Http>,
+        mut command: Command,
+        launch: Launch,
+    ) -> io::Result<ServerInfo> {
+        let mut child = match &launch.log_file {
+            Some(path) => {
+                if let Some(dir) = path.parent() {
+                    fs::create_dir_all(dir)?;
+                }
+                let log = fs::OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(path)?;
+                command
+                    .stdin(Stdio::piped())
+                    .stdout(log.try_clone()?)
+                    .stderr(log)
+                    .spawn()?
+            }
+            None => command
+                .stdin(Stdio::piped())
+                .stdout(Stdio::piped())
+                .spawn()?,
+        };
+        let output = read_output(&mut child, launch.log_file);
+
+        let (control, control_rx) = mpsc::unbounded_channel();
+        let now = Instant::now();
+        let info = ServerInfo {
+            id: self.next_id.fetch_add(1, Ordering::Relaxed) + 1,
+            name: launch.name,
+            host: launch.host,
+            guild_id: launch.guild_id,
+            channel_id: launch.channel_id,
+            iwad: launch.iwad,
+            wads: launch.wads,
+            maps: launch.maps,
+            started: now,
+            started_at: Utc::now(),
+            deadline: now + launch.timeout,
+            players: 0,
+            peak_players: 0,
+            empty_since: now,
+            has_console: child.stdin.is_some(),
+            control,
+        };
+        self.servers.lock().await.insert(info.id, info.clone());
+
+        let servers = self.clone();
+        let id = info.id;
+        let idle_timeout = launch.idle_timeout;
+        tokio::spawn(async move {
+            servers
+                .supervise(id, child, output, control_rx, http, idle_timeout)
+                .await;
+        });
+
+        Ok(info)
+    }
+
+    /// Returns the most recently started server hosted by `host`.
+    pub async fn find_by_host(&self, host: UserId) -> Option<ServerInfo> {
+        self.servers
+            .lock()
+            .await
+            .values()
+            .filter(|server| server.host == host)
+            .max_by_key(|server| server.started)
+            .cloned()
+    }
+
+    /// Returns the most recently started server that was hosted from `channel_id`.
+    pub async fn find_by_channel(&self, channel_id: ChannelId) -> Option<ServerInfo> {
+        self.servers
+            .lock()
+            .await
+            .values()
+            .filter(|server| server.channel_id == channel_id)
+            .max_by_key(|server| server.started)
+            .cloned()
+    }
+
+    /// Pushes back the deadline of a running server, but no later than
+    /// `latest`, returning the new deadline.
+    pub async fn extend(&self, id: u32, duration: Duration, latest: Instant) -> Option<Instant> {
+        let mut servers = self.servers.lock().await;
+        let server = servers.get_mut(&id)?;
+        server.deadline = server
+            .deadline
+            .checked_add(duration)
+            .map_or(latest, |deadline| deadline.min(latest));
+        Some(server.deadline)
+    }
+
+    /// Runs a command on a running server's console, returning whether it got there.
+    pub async fn console(&self, id: u32, command: &str) -> bool {
+        self.servers
+            .lock()
+            .await
+            .get(&id)
+            .map_or(false, |server| server.console(command))
+    }
+
+    async fn supervise(
+        &self,
+        id: u32,
+        mut child: Child,
+        output: mpsc::UnboundedReceiver<String>,
+        mut control: mpsc::UnboundedReceiver<Control>,
+        http: Arc<Http>,
+        idle_timeout: Option<Duration>,
+    ) {
+        let mut stdin = child.stdin.take();
+        let mut lines = Some(output);
+        let mut interval = tokio::time::interval(CHECK_INTERVAL);
+
+        let reason = loop {
+            tokio::select! {
+                _ = child.wait() => break ExitReason::Exited,
+                Some(line) = next_line(&mut lines) => self.update_players(id, &line).await,
+                Some(control) = control.recv() => match control {
+                    Control::Console(command) => {
+                        if let Some(stdin) = stdin.as_mut() {
+                            let line = format!("{}\n", command);
+                            if let Err(why) = stdin.write_all(line.as_bytes()).await {
+                                log::error!("Could not write to server {} console: {:?}", id, why);
+                            }
+                        }
+                    }
+                    Control::Stop => {
+                        if let Err(why) = child.kill().await {
+                            log::error!("Could not kill server {}: {:?}", id, why);
+                        }
+                        break ExitReason::Shutdown;
+                    }
+                    Control::Detach => break ExitReason::Detached,
+                },
+                _ = interval.tick() => {
+                    if let Some(reason) = self.check_timeouts(id, idle_timeout).await {
+                        if let Err(why) = child.kill().await {
+                            log::error!("Could not kill server {}: {:?}", id, why);
+                        }
+                        break reason;
+                    }
+                }
+            }
+        };
+
+        // Kept in the registry until it has been announced, since `shutdown`
+        // waits for the registry to empty.
+        let server = match self.servers.lock().await.get(&id).cloned() {
+            Some(server) => server,
+            None => return,
+        };
+        log::info!("Server \"{}\" stopped: {:?}", server.name, reason);
+
+        if let Err(why) = history::record(&self.db, &HostRecord::new(&server, reason)) {
+            log::error!("Could not record server history: {:?}", why);
+        }
+
+        let message = match reason {
+            ExitReason::Timeout => format!("Zandronum server \"{}\" has timed out", server.name),
+            ExitReason::Idle => format!(
+                "Zandronum server \"{}\" was shut down after being empty for {} minutes",
+                server.name,
+                idle_timeout.unwrap_or_default().as_secs() / 60
+            ),
+            ExitReason::Exited => format!("Zandronum server \"{}\" has stopped", server.name),
+            ExitReason::Shutdown => format!(
+                "cantdrown is shutting down, so Zandronum server \"{}\" was stopped",
+                server.name
+            ),
+            ExitReason::Detached => format!(
+                "cantdrown is shutting down, Zandronum server \"{}\" will keep running until it is closed",
+                server.name
+            ),
+        };
+        if let Err(why) = server.channel_id.say(&http, message).await {
+            log::error!("Could not announce server shutdown: {:?}", why);
+        }
+        self.servers.lock().await.remove(&id);
+    }
+
+    /// Stops or detaches every running server, waiting a short while for the
+    /// supervisors to record and announce them.
+    pub async fn shutdown(&self, detach: bool) {
+        let controls: Vec<mpsc::UnboundedSender<Control>> = self
+            .servers
+            .lock()
+            .await
+            .values()
+            .map(|server| server.control.clone())
+            .collect();
+        for control in controls {
+            let _ = control.send(if detach {
+                Control::Detach
+            } else {
+                Control::Stop
+            });
+        }
+
+        let deadline = Instant::now() + SHUTDOWN_TIMEOUT;
+        while Instant::now() < deadline && !self.servers.lock().await.is_empty() {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+    }
+
+    /// Tracks the player count from the server's console output.
+    async fn update_players(&self, id: u32, line: &str) {
+        let mut servers = self.servers.lock().await;
+        let server = match servers.get_mut(&id) {
+            Some(server) => server,
+            None => return,
+        };
+
+        if CONNECTED.is_match(line) {
+            server.players += 1;
+            server.peak_players = server.peak_players.max(server.players);
+        } else if DISCONNECTED.is_match(line) && server.players > 0 {
+            server.players -= 1;
+            if server.players == 0 {
+                server.empty_since = Instant::now();
+            }
+        }
+    }
+
+    async fn check_timeouts(&self, id: u32, idle_timeout: Option<Duration>) -> Option<ExitReason> {
+        let servers = self.servers.lock().await;
+        let server = servers.get(&id)?;
+
+        if Instant::now() >= server.deadline {
+            return Some(ExitReason::Timeout);
+        }
+        match idle_timeout {
+            Some(idle) if server.players == 0 && server.empty_since.elapsed() >= idle => {
+                Some(ExitReason::Idle)
+            }
+            _ => None,
+        }
+    }
+}
+
+async fn next_line(lines: &mut Option<mpsc::UnboundedReceiver<String>>) -> Option<String> {
+    let receiver = match lines {
+        Some(receiver) => receiver,
+        None => return std::future::pending().await,
+    };
+
+    match receiver.recv().await {
+        Some(line) => Some(line),
+        None => {
+            *lines = None;
+            None
+        }
+    }
+}
+
+/// Reads the server's console output line by line, from its stdout or by
+/// following its log file.
+fn read_output(child: &mut Child, log_file: Option<PathBuf>) -> mpsc::UnboundedReceiver<String> {
+    let (sender, receiver) = mpsc::unbounded_channel();
+    match (child.stdout.take(), log_file) {
+        (Some(stdout), _) => {
+            tokio::spawn(async move {
+                let mut lines = BufReader::new(stdout).lines();
+                while let Ok(Some(line)) = lines.next_line().await {
+                    if sender.send(line).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+        (None, Some(path)) => {
+            tokio::spawn(follow(path, sender));
+        }
+        (None, None) => {}
+    }
+    receiver
+}
+
+/// Sends each line written to a log file until nobody is listening.
+async fn follow(path: PathBuf, lines: mpsc::UnboundedSender<String>) {
+    let file = match tokio::fs::File::open(&path).await {
+        Ok(file) => file,
+        Err(why) => {
+            log::error!("Could not follow {}: {:?}", path.display(), why);
+            return;
+        }
+    };
+    let mut reader = BufReader::new(file);
+    let mut line = String::new();
+    loop {
+        match reader.read_line(&mut line).await {
+            Ok(0) => {
+                if lines.is_closed() {
+                    return;
+                }
+                tokio::time::sleep(FOLLOW_INTERVAL).await;
+            }
+            // Wait for the rest of a line that is still being written.
+            Ok(_) if !line.ends_with('\n') => {}
+            Ok(_) => {
+                if lines.send(line.trim_end().to_string()).is_err() {
+                    return;
+                }
+                line.clear();
+            }
+            Err(why) => {
+                log::error!("Could not follow {}: {:?}", path.display(), why);
+                return;
+            }
+        }
+    }
+}
@@ -0,0 +1,121 @@
+use lazy_static::lazy_static;
+use regex::Regex;
+use std::{
+    fs::File,
+    io::{self, Read, Seek, SeekFrom},
+    path::Path,
+};
+
+lazy_static! {
+    static ref MAP_MARKER: Regex = Regex::new(r"^(MAP\d\d|E\dM\d)$").unwrap();
+}
+
+/// Reads the lump directory of a WAD and returns the names of the maps in it.
+pub fn map_names(path: &Path) -> io::Result<Vec<String>> {
+    let mut file = File::open(path)?;
+
+    let mut header = [0u8; 12];
+    file.read_exact(&mut header)?;
+    if &header[0..4] != b"IWAD" && &header[0..4] != b"PWAD" {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "Not a WAD file"));
+    }
+    let num_lumps = u32::from_le_bytes([header[4], header[5], header[6], header[7]]);
+    let directory = u32::from_le_bytes([header[8], header[9], header[10], header[11]]);
+
+    // The header comes from a downloaded file, so make sure the directory fits
+    // in it before allocating room for it.
+    let length = file.metadata()?.len();
+    let size = (num_lumps as u64)
+        .checked_mul(16)
+        .filter(|size| {
+            (directory as u64)
+                .checked_add(*size)
+                .map_or(false, |end| end <= length)
+        })
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Lump directory goes past the end of the file",
+            )
+        })?;
+
+    let mut entries = vec![0u8; size as usize];
+    file.seek(SeekFrom::Start(directory as u64))?;
+    file.read_exact(&mut entries)?;
+
+    // Each directory entry is a 4 byte offset, a 4 byte size and an 8 byte
+    // name padded with NULs.
+    let maps = entries
+        .chunks(16)
+        .map(|entry| {
+            let name = &entry[8..16];
+            let end = name.iter().position(|&c| c == 0).unwrap_or(name.len());
+            String::from_utf8_lossy(&name[..end]).to_uppercase()
+        })
+        .filter(|name| MAP_MARKER.is_match(name))
+        .collect();
+
+    Ok(maps)
+}
+
+/// Collects the maps from every WAD, skipping any that cannot be read.
+pub fn collect_maps<P: AsRef<Path>>(paths: &[P]) -> Vec<String> {
+    let mut maps: Vec<String> = paths
+        .iter()
+        .filter_map(|path| match map_names(path.as_ref()) {
+            Ok(maps) => Some(maps),
+            Err(why) => {
+                log::warn!(
+                    "Could not read maps from {}: {:?}",
+                    path.as_ref().display(),
+                    why
+                );
+                None
+            }
+        })
+        .flatten()
+        .collect();
+    maps.sort();
+    maps.dedup();
+    maps
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{fs, path::PathBuf};
+
+    fn write_wad(name: &str, num_lumps: u32, directory: u32, lumps: &[&[u8; 8]]) -> PathBuf {
+        let mut bytes = b"PWAD".to_vec();
+        bytes.extend_from_slice(&num_lumps.to_le_bytes());
+        bytes.extend_from_slice(&directory.to_le_bytes());
+        for lump in lumps {
+            bytes.extend_from_slice(&[0; 8]);
+            bytes.extend_from_slice(*lump);
+        }
+        let path =
+            std::env::temp_dir().join(format!("cantdrown-{}-{}.wad", name, std::process::id()));
+        fs::write(&path, bytes).unwrap();
+        path
+    }
+
+    #[test]
+    fn reads_map_names() {
+        let path = write_wad("maps", 2, 12, &[b"MAP01\0\0\0", b"THINGS\0\0"]);
+
+        let maps = map_names(&path);
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(maps.unwrap(), vec!["MAP01"]);
+    }
+
+    #[test]
+    fn rejects_a_directory_past_the_end() {
+        let path = write_wad("forged", u32::MAX, 12, &[b"MAP01\0\0\0"]);
+
+        let maps = map_names(&path);
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(maps.unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+}
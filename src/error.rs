@@ -0,0 +1,141 @@
+//! Errors from commands and how they are reported. User mistakes are replied
+//! to as is, anything else is logged with a short id that is also shown to the
+//! user so the operator can find it in the logs.
+
+use serenity::{
+    client::Context,
+    framework::standard::{macros::hook, CommandError as FrameworkError, DispatchError, Reason},
+    model::channel::Message,
+};
+use std::fmt;
+
+pub enum CommandError {
+    /// The user did something wrong. The message is shown to them.
+    User(String),
+    /// Something went wrong on our side. The details are only logged.
+    Internal(anyhow::Error),
+}
+
+impl CommandError {
+    pub fn user(message: impl Into<String>) -> Self {
+        CommandError::User(message.into())
+    }
+
+    pub fn internal(error: impl Into<anyhow::Error>) -> Self {
+        CommandError::Internal(error.into())
+    }
+}
+
+impl fmt::Debug for CommandError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CommandError::User(message) => write!(f, "User({:?})", message),
+            CommandError::Internal(error) => write!(f, "Internal({:?})", error),
+        }
+    }
+}
+
+impl fmt::Display for CommandError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CommandError::User(message) => write!(f, "{}", message),
+            CommandError::Internal(error) => write!(f, "{:#}", error),
+        }
+    }
+}
+
+impl std::error::Error for CommandError {}
+
+/// Where a failed command came from, for the logs.
+pub struct Origin<'a> {
+    pub command: &'a str,
+    pub user: &'a str,
+    pub guild: Option<u64>,
+    pub channel: u64,
+}
+
+impl fmt::Display for Origin<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} by {}", self.command, self.user)?;
+        match self.guild {
+            Some(guild) => write!(f, " in guild {} channel {}", guild, self.channel),
+            None => write!(f, " in DM {}", self.channel),
+        }
+    }
+}
+
+/// Logs a command's error and returns what to tell the user about it.
+pub fn report(origin: &Origin, error: &FrameworkError) -> String {
+    match error.downcast_ref::<CommandError>() {
+        Some(CommandError::User(message)) => {
+            log::info!("{}: {}", origin, message);
+            message.clone()
+        }
+        _ => {
+            let id = correlation_id();
+            log::error!("[{}] {} failed: {:?}", id, origin, error);
+            format!("Something went wrong (error `{}`)", id)
+        }
+    }
+}
+
+fn correlation_id() -> String {
+    format!("{:08x}", rand::random::<u32>())
+}
+
+/// Replies to and logs commands that returned an error.
+#[hook]
+pub async fn after(
+    ctx: &Context,
+    msg: &Message,
+    command_name: &str,
+    result: Result<(), FrameworkError>,
+) {
+    if let Err(error) = result {
+        let origin = Origin {
+            command: command_name,
+            user: &msg.author.tag(),
+            guild: msg.guild_id.map(|id| id.0),
+            channel: msg.channel_id.0,
+        };
+        let reply = report(&origin, &error);
+        if let Err(why) = msg.channel_id.say(&ctx.http, reply).await {
+            log::error!("Could not reply to {}: {:?}", origin, why);
+        }
+    }
+}
+
+/// Explains why a command wasn't run.
+#[hook]
+pub async fn on_dispatch_error(ctx: &Context, msg: &Message, error: DispatchError) {
+    let reply = match error {
+        DispatchError::CheckFailed(_, Reason::User(reason))
+        | DispatchError::CheckFailed(_, Reason::UserAndLog { user: reason, .. }) => reason,
+        DispatchError::CheckFailed(..) => String::from("You can't use this command here"),
+        DispatchError::OnlyForGuilds => String::from("This command only works in servers"),
+        DispatchError::OnlyForDM => String::from("This command only works in DMs"),
+        DispatchError::OnlyForOwners => String::from("Only the bot's owner can do that"),
+        DispatchError::LackingRole => String::from("You don't have the role needed for that"),
+        DispatchError::LackingPermissions(permissions) => format!(
+            "You need these permissions for that: {}",
+            permissions.get_permission_names().join(", ")
+        ),
+        DispatchError::NotEnoughArguments { min, given } => format!(
+            "Not enough arguments, expected at least {} but got {}",
+            min, given
+        ),
+        DispatchError::TooManyArguments { max, given } => format!(
+            "Too many arguments, expected at most {} but got {}",
+            max, given
+        ),
+        DispatchError::CommandDisabled(_) => String::from("That command is disabled"),
+        error => {
+            log::debug!("Ignored message from {}: {:?}", msg.author.tag(), error);
+            return;
+        }
+    };
+
+    if let Err(why) = msg.channel_id.say(&ctx.http, reply).await {
+        log::error!("Could not reply to {}: {:?}", msg.author.tag(), why);
+    }
+}
@@ -0,0 +1,728 @@
+//! Slash command versions of the Song, Role and Doom groups. The commands
+//! route through the same functions as the prefix commands.
+
+use crate::{
+    cooldown,
+    error::{self, CommandError},
+    pages, picker,
+    services::{
+        discord::{
+            doom_service, now_playing_embed, queue_embed, role_service, search_embed, song_service,
+            InteractionMessenger,
+        },
+        doom::{matching_targets, MAX_EXTEND_MINUTES},
+        Invocation, Messenger,
+    },
+};
+use serenity::{
+    builder::CreateEmbed,
+    framework::standard::CommandResult,
+    model::{
+        id::{ChannelId, GuildId},
+        interactions::{
+            application_command::{
+                ApplicationCommand, ApplicationCommandInteraction,
+                ApplicationCommandInteractionDataOption, ApplicationCommandOptionType,
+            },
+            autocomplete::AutocompleteInteraction,
+            Interaction, InteractionResponseType,
+        },
+        user::User,
+    },
+    prelude::*,
+};
+
+/// Discord allows at most 25 autocomplete choices.
+const MAX_CHOICES: usize = 25;
+
+/// Registers the slash commands globally, replacing any that were there.
+pub async fn register(ctx: &Context) -> serenity::Result<()> {
+    ApplicationCommand::set_global_application_commands(&ctx.http, |commands| {
+        commands
+            .create_application_command(|command| {
+                command
+                    .name("song")
+                    .description("Play music in voice channels")
+                    .create_option(|o| {
+                        o.name("join")
+                            .description("Join your voice channel")
+                            .kind(ApplicationCommandOptionType::SubCommand)
+                    })
+                    .create_option(|o| {
+                        o.name("leave")
+                            .description("Leave the voice channel")
+                            .kind(ApplicationCommandOptionType::SubCommand)
+                    })
+                    .create_option(|o| {
+                        o.name("move")
+                            .description("Move to your voice channel, keeping the queue")
+                            .kind(ApplicationCommandOptionType::SubCommand)
+                    })
+                    .create_option(|o| {
+                        o.name("play")
+                            .description("Play a song in the voice channel")
+                            .kind(ApplicationCommandOptionType::SubCommand)
+                            .create_sub_option(|o| {
+                                o.name("query")
+                                    .description("URL to a video or audio")
+                                    .kind(ApplicationCommandOptionType::String)
+                                    .required(true)
+                            })
+                    })
+                    .create_option(|o| {
+                        o.name("search")
+                            .description("Search for songs and pick which ones to play")
+                            .kind(ApplicationCommandOptionType::SubCommand)
+                            .create_sub_option(|o| {
+                                o.name("query")
+                                    .description("What to search for")
+                                    .kind(ApplicationCommandOptionType::String)
+                                    .required(true)
+                            })
+                    })
+                    .create_option(|o| {
+                        o.name("skip")
+                            .description("Skip the current song in the queue")
+                            .kind(ApplicationCommandOptionType::SubCommand)
+                    })
+                    .create_option(|o| {
+                        o.name("stop")
+                            .description("Stop playing music in the voice channel")
+                            .kind(ApplicationCommandOptionType::SubCommand)
+                    })
+                    .create_option(|o| {
+                        o.name("current")
+                            .description("Show the song that's playing and what's next")
+                            .kind(ApplicationCommandOptionType::SubCommand)
+                    })
+                    .create_option(|o| {
+                        o.name("queue")
+                            .description("Show the songs in the queue")
+                            .kind(ApplicationCommandOptionType::SubCommand)
+                            .create_sub_option(|o| {
+                                o.name("page")
+                                    .description("Page to start on")
+                                    .kind(ApplicationCommandOptionType::Integer)
+                            })
+                    })
+                    .create_option(|o| {
+                        o.name("pause")
+                            .description("Pause the current song")
+                            .kind(ApplicationCommandOptionType::SubCommand)
+                    })
+                    .create_option(|o| {
+                        o.name("resume")
+                            .description("Keep playing the paused song")
+                            .kind(ApplicationCommandOptionType::SubCommand)
+                    })
+                    .create_option(|o| {
+                        o.name("seek")
+                            .description(
+                                "Jump to a time in the current song, or skip ahead or back",
+                            )
+                            .kind(ApplicationCommandOptionType::SubCommand)
+                            .create_sub_option(|o| {
+                                o.name("time")
+                                    .description("A time like 1:30, or +30s or -30s")
+                                    .kind(ApplicationCommandOptionType::String)
+                                    .required(true)
+                            })
+                    })
+                    .create_option(|o| {
+                        o.name("volume")
+                            .description("Show or change the volume")
+                            .kind(ApplicationCommandOptionType::SubCommand)
+                            .create_sub_option(|o| {
+                                o.name("volume")
+                                    .description("From 0 to 150")
+                                    .kind(ApplicationCommandOptionType::Integer)
+                            })
+                    })
+                    .create_option(|o| {
+                        o.name("loop")
+                            .description("Loop the current song or the whole queue")
+                            .kind(ApplicationCommandOptionType::SubCommand)
+                            .create_sub_option(|o| {
+                                o.name("mode")
+                                    .description("What to loop")
+                                    .kind(ApplicationCommandOptionType::String)
+                                    .add_string_choice("track", "track")
+                                    .add_string_choice("queue", "queue")
+                                    .add_string_choice("off", "off")
+                            })
+                    })
+                    .create_option(|o| {
+                        o.name("remove")
+                            .description("Remove a song, or a range of songs, from the queue")
+                            .kind(ApplicationCommandOptionType::SubCommand)
+                            .create_sub_option(|o| {
+                                o.name("position")
+                                    .description("Position of the song, or the start of the range")
+                                    .kind(ApplicationCommandOptionType::Integer)
+                                    .required(true)
+                            })
+                            .create_sub_option(|o| {
+                                o.name("end")
+                                    .description("Last position of the range")
+                                    .kind(ApplicationCommandOptionType::Integer)
+                            })
+                    })
+                    .create_option(|o| {
+                        o.name("reorder")
+                            .description("Move a song to another position in the queue")
+                            .kind(ApplicationCommandOptionType::SubCommand)
+                            .create_sub_option(|o| {
+                                o.name("from")
+                                    .description("Position of the song")
+                                    .kind(ApplicationCommandOptionType::Integer)
+                                    .required(true)
+                            })
+                            .create_sub_option(|o| {
+                                o.name("to")
+                                    .description("Position to move it to")
+                                    .kind(ApplicationCommandOptionType::Integer)
+                                    .required(true)
+                            })
+                    })
+                    .create_option(|o| {
+                        o.name("shuffle")
+                            .description("Shuffle the songs after the current one")
+                            .kind(ApplicationCommandOptionType::SubCommand)
+                    })
+                    .create_option(|o| {
+                        o.name("clear")
+                            .description("Empty the queue but keep playing the current song")
+                            .kind(ApplicationCommandOptionType::SubCommand)
+                    })
+                    .create_option(|o| {
+                        o.name("jump")
+                            .description("Skip straight to a song in the queue")
+                            .kind(ApplicationCommandOptionType::SubCommand)
+                            .create_sub_option(|o| {
+                                o.name("position")
+                                    .description("Position of the song")
+                                    .kind(ApplicationCommandOptionType::Integer)
+                                    .required(true)
+                            })
+                    })
+            })
+            .create_application_command(|command| {
+                command
+                    .name("role")
+                    .description("Add roles and assign roles to yourself")
+                    .create_option(|o| {
+                        o.name("add")
+                            .description("Add a role")
+                            .kind(ApplicationCommandOptionType::SubCommand)
+                            .create_sub_option(|o| {
+                                o.name("name")
+                                    .description("Name of the role")
+                                    .kind(ApplicationCommandOptionType::String)
+                                    .required(true)
+                            })
+                    })
+                    .create_option(|o| {
+                        o.name("assign")
+                            .description("Assign yourself a role")
+                            .kind(ApplicationCommandOptionType::SubCommand)
+                            .create_sub_option(|o| {
+                                o.name("name")
+                                    .description("Name of the role")
+                                    .kind(ApplicationCommandOptionType::String)
+                                    .required(true)
+                                    .set_autocomplete(true)
+                            })
+                    })
+            })
+            .create_application_command(|command| {
+                command
+                    .name("doom")
+                    .description("Host Zandronum servers")
+                    .create_option(|o| {
+                        o.name("host")
+                            .description("Host a Zandronum server")
+                            .kind(ApplicationCommandOptionType::SubCommand)
+                            .create_sub_option(|o| {
+                                o.name("iwad")
+                                    .description("IWAD to play, or a preset")
+                                    .kind(ApplicationCommandOptionType::String)
+                                    .required(true)
+                                    .set_autocomplete(true)
+                            })
+                            .create_sub_option(|o| {
+                                o.name("url")
+                                    .description("URL to the WADs, unless playing a preset")
+                                    .kind(ApplicationCommandOptionType::String)
+                            })
+                    })
+                    .create_option(|o| {
+                        o.name("extend")
+                            .description("Extend the time left on the server you are hosting")
+                            .kind(ApplicationCommandOptionType::SubCommand)
+                            .create_sub_option(|o| {
+                                o.name("minutes")
+                                    .description("How long to extend it by")
+                                    .kind(ApplicationCommandOptionType::Integer)
+                                    .min_int_value(1)
+                                    .max_int_value(MAX_EXTEND_MINUTES)
+                            })
+                    })
+                    .create_option(|o| {
+                        o.name("maps")
+                            .description("List the maps on the server hosted in this channel")
+                            .kind(ApplicationCommandOptionType::SubCommand)
+                    })
+                    .create_option(|o| {
+                        o.name("vote")
+                            .description("Start a vote on the server hosted in this channel")
+                            .kind(ApplicationCommandOptionType::SubCommandGroup)
+                            .create_sub_option(|o| {
+                                o.name("map")
+                                    .description("Vote to change the map")
+                                    .kind(ApplicationCommandOptionType::SubCommand)
+                                    .create_sub_option(|o| {
+                                        o.name("map")
+                                            .description("Map to change to")
+                                            .kind(ApplicationCommandOptionType::String)
+                                            .required(true)
+                                    })
+                            })
+                    })
+                    .create_option(|o| {
+                        o.name("nextmap")
+                            .description("Vote to skip to the next map")
+                            .kind(ApplicationCommandOptionType::SubCommand)
+                    })
+                    .create_option(|o| {
+                        o.name("schedule")
+                            .description("Book a server for later")
+                            .kind(ApplicationCommandOptionType::SubCommand)
+                            .create_sub_option(|o| {
+                                o.name("time")
+                                    .description(
+                                        "`HH:MM`, `YYYY-MM-DDTHH:MM` or relative like `+1h30m`",
+                                    )
+                                    .kind(ApplicationCommandOptionType::String)
+                                    .required(true)
+                            })
+                            .create_sub_option(|o| {
+                                o.name("iwad")
+                                    .description("IWAD to play, or a preset")
+                                    .kind(ApplicationCommandOptionType::String)
+                                    .required(true)
+                                    .set_autocomplete(true)
+                            })
+                            .create_sub_option(|o| {
+                                o.name("url")
+                                    .description("URL to the WADs, unless playing a preset")
+                                    .kind(ApplicationCommandOptionType::String)
+                            })
+                    })
+                    .create_option(|o| {
+                        o.name("schedules")
+                            .description("List the scheduled servers")
+                            .kind(ApplicationCommandOptionType::SubCommand)
+                    })
+                    .create_option(|o| {
+                        o.name("cancel")
+                            .description("Cancel a scheduled server you booked")
+                            .kind(ApplicationCommandOptionType::SubCommand)
+                            .create_sub_option(|o| {
+                                o.name("id")
+                                    .description("Number of the scheduled server")
+                                    .kind(ApplicationCommandOptionType::Integer)
+                                    .required(true)
+                                    .min_int_value(1)
+                            })
+                    })
+                    .create_option(|o| {
+                        o.name("history")
+                            .description("Show the servers hosted recently")
+                            .kind(ApplicationCommandOptionType::SubCommand)
+                    })
+                    .create_option(|o| {
+                        o.name("top")
+                            .description("Show the most popular WADs")
+                            .kind(ApplicationCommandOptionType::SubCommand)
+                    })
+            })
+    })
+    .await?;
+
+    Ok(())
+}
+
+/// Handles an interaction from Discord.
+pub async fn handle(ctx: &Context, interaction: Interaction) {
+    match interaction {
+        Interaction::ApplicationCommand(command) => {
+            let messenger = InteractionMessenger::new(ctx, &command);
+            if let Err(error) = run_command(ctx, &command, &messenger).await {
+                let name = command_name(&command);
+                let origin = error::Origin {
+                    command: &name,
+                    user: &command.user.tag(),
+                    guild: command.guild_id.map(|id| id.0),
+                    channel: command.channel_id.0,
+                };
+                let reply = error::report(&origin, &error);
+                if let Err(why) = messenger.say(&reply).await {
+                    log::error!("Could not reply to {}: {:?}", origin, why);
+                }
+            }
+        }
+        Interaction::Autocomplete(autocomplete) => {
+            if let Err(why) = autocomplete_choices(ctx, &autocomplete).await {
+                log::error!("Autocomplete failed: {:?}", why);
+            }
+        }
+        _ => {}
+    }
+}
+
+async fn run_command(
+    ctx: &Context,
+    command: &ApplicationCommandInteraction,
+    messenger: &InteractionMessenger<'_>,
+) -> CommandResult {
+    // Hosting can take a while to download the WADs, so defer everything and
+    // edit the response once the command is done.
+    command
+        .create_interaction_response(&ctx.http, |r| {
+            r.kind(InteractionResponseType::DeferredChannelMessageWithSource)
+        })
+        .await?;
+
+    let invocation = invocation(command.guild_id, command.channel_id, &command.user);
+    let subcommand = match command.data.options.first() {
+        Some(subcommand) => subcommand,
+        None => return Ok(()),
+    };
+
+    crate::access::check(
+        ctx,
+        &invocation,
+        command.data.name.as_str(),
+        subcommand.name.as_str(),
+    )
+    .await?;
+
+    let name = command.data.name.as_str();
+    let run = dispatch(ctx, messenger, &invocation, name, subcommand);
+    // `search` counts towards the cooldown once something is picked.
+    match (name, subcommand.name.as_str()) {
+        ("doom", "host") => cooldown::limit(ctx, cooldown::HOST, &invocation, run).await?,
+        ("song", "play") => cooldown::limit(ctx, cooldown::PLAY, &invocation, run).await?,
+        ("role", "add") => cooldown::limit(ctx, cooldown::ROLE_ADD, &invocation, run).await?,
+        _ => run.await?,
+    }
+
+    messenger.finish().await
+}
+
+/// Runs a slash command's subcommand.
+async fn dispatch(
+    ctx: &Context,
+    messenger: &InteractionMessenger<'_>,
+    invocation: &Invocation,
+    name: &str,
+    subcommand: &ApplicationCommandInteractionDataOption,
+) -> CommandResult {
+    let options = &subcommand.options;
+    match (name, subcommand.name.as_str()) {
+        ("song", "join") => song_service(ctx).join(invocation).await?,
+        ("song", "leave") => song_service(ctx).leave(invocation).await?,
+        ("song", "move") => song_service(ctx).follow(invocation).await?,
+        ("song", "play") => {
+            let settings = crate::settings::for_guild(ctx, invocation.guild_id).await?;
+            let query = string(options, "query").unwrap_or("");
+            song_service(ctx)
+                .play(messenger, invocation, &settings, query)
+                .await?
+        }
+        ("song", "search") => {
+            let query = string(options, "query").unwrap_or("");
+            let service = song_service(ctx);
+            let results = service.search(invocation, query).await?;
+            let mut embed = CreateEmbed::default();
+            search_embed(&mut embed, query, &results);
+            let message = messenger.send_embed(embed).await?;
+            if let Some(picks) =
+                picker::pick(ctx, &message, invocation.user_id, results.len()).await?
+            {
+                let settings = crate::settings::for_guild(ctx, invocation.guild_id).await?;
+                let play = service.play_tracks(
+                    messenger,
+                    invocation,
+                    &settings,
+                    picks.into_iter().map(|i| results[i].clone()).collect(),
+                );
+                cooldown::limit(ctx, cooldown::PLAY, invocation, play).await?
+            }
+        }
+        ("song", "skip") => song_service(ctx).skip(messenger, invocation).await?,
+        ("song", "stop") => song_service(ctx).stop(invocation).await?,
+        ("song", "current") => {
+            let now_playing = song_service(ctx).now_playing(invocation).await?;
+            let mut embed = CreateEmbed::default();
+            now_playing_embed(&mut embed, &now_playing);
+            let message = messenger.send_embed(embed).await?;
+            if let Some(guild_id) = invocation.guild_id {
+                crate::music::show_now_playing(ctx, guild_id, &message).await?;
+            }
+        }
+        ("song", "queue") => {
+            let listing = song_service(ctx).queue(invocation).await?;
+            let page = integer(options, "page")
+                .map_or(1, |page| page.max(1) as usize)
+                .min(listing.page_count());
+            let mut embed = CreateEmbed::default();
+            queue_embed(&mut embed, &listing, page);
+            let message = messenger.send_embed(embed).await?;
+            pages::paginate(
+                ctx,
+                message,
+                invocation.user_id,
+                page,
+                listing.page_count(),
+                |e, page| queue_embed(e, &listing, page),
+            )
+            .await?
+        }
+        ("song", "pause") => song_service(ctx).pause(messenger, invocation).await?,
+        ("song", "resume") => song_service(ctx).resume(messenger, invocation).await?,
+        ("song", "seek") => {
+            let time = string(options, "time").unwrap_or("");
+            song_service(ctx).seek(messenger, invocation, time).await?
+        }
+        ("song", "volume") => {
+            let settings = crate::settings::for_guild(ctx, invocation.guild_id).await?;
+            // Anything out of range is turned away by the song service.
+            let volume = integer(options, "volume").map(|volume| {
+                if volume < 0 {
+                    u16::MAX
+                } else {
+                    volume.min(i64::from(u16::MAX)) as u16
+                }
+            });
+            song_service(ctx)
+                .volume(messenger, invocation, &settings, volume)
+                .await?;
+            if let (Some(guild_id), Some(volume)) = (invocation.guild_id, volume) {
+                crate::settings::modify(ctx, guild_id, |settings| settings.volume = Some(volume))
+                    .await?;
+            }
+        }
+        ("song", "loop") => {
+            let mode = string(options, "mode");
+            song_service(ctx)
+                .set_loop(messenger, invocation, mode)
+                .await?
+        }
+        ("song", "remove") => {
+            let start = position(options, "position");
+            let end = integer(options, "end").map(|_| position(options, "end"));
+            song_service(ctx)
+                .remove(messenger, invocation, start, end)
+                .await?
+        }
+        ("song", "reorder") => {
+            let (from, to) = (position(options, "from"), position(options, "to"));
+            song_service(ctx)
+                .reorder(messenger, invocation, from, to)
+                .await?
+        }
+        ("song", "shuffle") => song_service(ctx).shuffle(messenger, invocation).await?,
+        ("song", "clear") => song_service(ctx).clear(messenger, invocation).await?,
+        ("song", "jump") => {
+            let position = position(options, "position");
+            song_service(ctx)
+                .jump(messenger, invocation, position)
+                .await?
+        }
+        ("role", "add") => {
+            let config = crate::config::get(ctx).await;
+            let name = string(options, "name").unwrap_or("");
+            role_service(ctx)
+                .add(messenger, invocation, &config.roles, name)
+                .await?
+        }
+        ("role", "assign") => {
+            let settings = crate::settings::for_guild(ctx, invocation.guild_id).await?;
+            let name = string(options, "name").unwrap_or("");
+            role_service(ctx)
+                .assign(messenger, invocation, &settings, name)
+                .await?
+        }
+        ("doom", "host") => {
+            let config = crate::config::doom::get(ctx).await;
+            let settings = crate::settings::for_guild(ctx, invocation.guild_id).await?;
+            doom_service(ctx)
+                .await
+                .host(
+                    messenger,
+                    invocation,
+                    config.as_ref(),
+                    &settings,
+                    string(options, "iwad"),
+                    string(options, "url"),
+                )
+                .await?
+        }
+        ("doom", "extend") => {
+            let config = crate::config::doom::get(ctx).await;
+            let minutes = integer(options, "minutes").map(|minutes| minutes.max(1) as u64);
+            doom_service(ctx)
+                .await
+                .extend(messenger, invocation, config.as_ref(), minutes)
+                .await?
+        }
+        ("doom", "maps") => doom_service(ctx).await.maps(messenger, invocation).await?,
+        ("doom", "vote") => {
+            // A group, so it reads like the prefix `vote map`.
+            let config = crate::config::doom::get(ctx).await;
+            let map = options
+                .iter()
+                .find(|option| option.name == "map")
+                .and_then(|vote| string(&vote.options, "map"));
+            doom_service(ctx)
+                .await
+                .vote_map(messenger, invocation, config.as_ref(), map)
+                .await?
+        }
+        ("doom", "nextmap") => {
+            let config = crate::config::doom::get(ctx).await;
+            doom_service(ctx)
+                .await
+                .next_map(messenger, invocation, config.as_ref())
+                .await?
+        }
+        ("doom", "schedule") => {
+            let config = crate::config::doom::get(ctx).await;
+            let settings = crate::settings::for_guild(ctx, invocation.guild_id).await?;
+            doom_service(ctx)
+                .await
+                .schedule(
+                    messenger,
+                    invocation,
+                    config.as_ref(),
+                    &settings,
+                    string(options, "time"),
+                    string(options, "iwad"),
+                    string(options, "url"),
+                )
+                .await?
+        }
+        ("doom", "schedules") => {
+            let config = crate::config::doom::get(ctx).await;
+            doom_service(ctx)
+                .await
+                .schedules(messenger, invocation, config.as_ref())
+                .await?
+        }
+        ("doom", "cancel") => {
+            let id = integer(options, "id").map(|id| id.max(0) as u64);
+            doom_service(ctx)
+                .await
+                .cancel(messenger, invocation, id)
+                .await?
+        }
+        ("doom", "history") => {
+            doom_service(ctx)
+                .await
+                .history(messenger, invocation)
+                .await?
+        }
+        ("doom", "top") => doom_service(ctx).await.top(messenger, invocation).await?,
+        _ => return Err(CommandError::user("Unknown command").into()),
+    }
+
+    Ok(())
+}
+
+/// Suggests role names for `/role` and presets or IWADs for `/doom`.
+async fn autocomplete_choices(
+    ctx: &Context,
+    autocomplete: &AutocompleteInteraction,
+) -> CommandResult {
+    let focused = autocomplete
+        .data
+        .options
+        .iter()
+        .flat_map(|subcommand| subcommand.options.iter())
+        .find(|option| option.focused);
+    let partial = match focused {
+        Some(option) => option.value.as_ref().and_then(|v| v.as_str()).unwrap_or(""),
+        None => return Ok(()),
+    };
+
+    let invocation = invocation(
+        autocomplete.guild_id,
+        autocomplete.channel_id,
+        &autocomplete.user,
+    );
+    let settings = crate::settings::for_guild(ctx, invocation.guild_id).await?;
+    let names = match autocomplete.data.name.as_str() {
+        "doom" => match crate::config::doom::get(ctx).await {
+            Some(config) => matching_targets(&config, &settings, partial),
+            None => Vec::new(),
+        },
+        _ => {
+            role_service(ctx)
+                .matching(&invocation, &settings, partial)
+                .await
+        }
+    };
+
+    autocomplete
+        .create_autocomplete_response(&ctx.http, |r| {
+            for name in names.iter().take(MAX_CHOICES) {
+                r.add_string_choice(name, name);
+            }
+            r
+        })
+        .await?;
+
+    Ok(())
+}
+
+/// The full name of a slash command, like `song play`.
+fn command_name(command: &ApplicationCommandInteraction) -> String {
+    match command.data.options.first() {
+        Some(subcommand) => format!("{} {}", command.data.name, subcommand.name),
+        None => command.data.name.clone(),
+    }
+}
+
+fn invocation(guild_id: Option<GuildId>, channel_id: ChannelId, user: &User) -> Invocation {
+    Invocation {
+        guild_id,
+        channel_id,
+        user_id: user.id,
+    }
+}
+
+fn string<'a>(
+    options: &'a [ApplicationCommandInteractionDataOption],
+    name: &str,
+) -> Option<&'a str> {
+    options
+        .iter()
+        .find(|option| option.name == name)
+        .and_then(|option| option.value.as_ref())
+        .and_then(|value| value.as_str())
+}
+
+/// A position in the song queue. Anything below 1 becomes 0, which the song
+/// service rejects.
+fn position(options: &[ApplicationCommandInteractionDataOption], name: &str) -> usize {
+    integer(options, name).map_or(0, |position| position.max(0) as usize)
+}
+
+fn integer(options: &[ApplicationCommandInteractionDataOption], name: &str) -> Option<i64> {
+    options
+        .iter()
+        .find(|option| option.name == name)
+        .and_then(|option| option.value.as_ref())
+        .and_then(|value| value.as_i64())
+}
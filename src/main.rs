@@ -4,31 +4,80 @@ use serenity::{
     async_trait,
     framework::standard::{
         help_commands,
-        macros::{group, help},
+        macros::{group, help, hook},
         Args, CommandGroup, CommandResult, HelpOptions, StandardFramework,
     },
     http::Http,
-    model::{channel::Message, event::ResumedEvent, gateway::Ready, id::UserId},
+    model::{
+        channel::Message,
+        event::ResumedEvent,
+        gateway::Ready,
+        id::{GuildId, UserId},
+        interactions::Interaction,
+        voice::VoiceState,
+    },
     prelude::*,
 };
 use songbird::SerenityInit;
-use std::{collections::HashSet, env};
+use std::{collections::HashSet, env, sync::Arc};
 
+mod access;
 mod commands;
 mod config;
+mod cooldown;
+mod db;
+mod doom;
+mod error;
+mod interactions;
+mod music;
+mod pages;
+mod picker;
+mod playlists;
+mod services;
+mod settings;
+mod shutdown;
 mod util;
 
-use commands::{doom::*, meta::*, role::*, song::*};
-use config::doom::DoomConfigInit;
+use access::{DOOMACCESS_CHECK, PLAYLISTACCESS_CHECK, ROLEACCESS_CHECK, SONGACCESS_CHECK};
+use commands::{access::*, doom::*, meta::*, playlist::*, role::*, settings::*, song::*};
+use config::{doom::DoomConfigInit, ConfigKey};
+use cooldown::{Cooldowns, CooldownsKey};
+use db::DbKey;
+use doom::server::{Servers, ServersKey};
+use music::{Players, PlayersKey};
 use util::LavalinkKey;
 
 struct Handler;
-struct LavalinkHandler;
+struct LavalinkHandler {
+    http: Arc<Http>,
+    players: Players,
+}
 
 #[async_trait]
 impl EventHandler for Handler {
-    async fn ready(&self, _: Context, ready: Ready) {
+    async fn ready(&self, ctx: Context, ready: Ready) {
         log::info!("Connected as {}", ready.user.name);
+
+        if let Err(why) = interactions::register(&ctx).await {
+            log::error!("Could not register slash commands: {:?}", why);
+        }
+        music::watch(ctx);
+    }
+
+    async fn voice_state_update(
+        &self,
+        ctx: Context,
+        guild_id: Option<GuildId>,
+        _old: Option<VoiceState>,
+        _new: VoiceState,
+    ) {
+        if let Some(guild_id) = guild_id {
+            music::voice_state_changed(&ctx, guild_id).await;
+        }
+    }
+
+    async fn interaction_create(&self, ctx: Context, interaction: Interaction) {
+        interactions::handle(&ctx, interaction).await;
     }
 
     async fn resume(&self, _: Context, _: ResumedEvent) {
@@ -38,33 +87,156 @@ impl EventHandler for Handler {
 
 #[async_trait]
 impl LavalinkEventHandler for LavalinkHandler {
-    async fn track_start(&self, _client: LavalinkClient, event: lavalink_rs::model::TrackStart) {
+    async fn track_start(&self, client: LavalinkClient, event: lavalink_rs::model::TrackStart) {
         log::info!("Track started at guild: {}", event.guild_id);
+        let guild_id = GuildId(event.guild_id.0);
+        if let Err(why) = music::track_started(&client, &self.http, &self.players, guild_id).await {
+            log::error!(
+                "Could not update now playing at guild {}: {:?}",
+                guild_id,
+                why
+            );
+        }
     }
 
-    async fn track_finish(&self, _client: LavalinkClient, event: lavalink_rs::model::TrackFinish) {
+    async fn track_finish(&self, client: LavalinkClient, event: lavalink_rs::model::TrackFinish) {
         log::info!("Track ended at guild: {}", event.guild_id);
+        let guild_id = GuildId(event.guild_id.0);
+        if let Err(why) = music::track_finished(
+            &client,
+            &self.players,
+            guild_id,
+            &event.track,
+            &event.reason,
+        )
+        .await
+        {
+            log::error!("Could not loop track at guild {}: {:?}", guild_id, why);
+        }
+    }
+
+    async fn track_exception(
+        &self,
+        client: LavalinkClient,
+        event: lavalink_rs::model::TrackException,
+    ) {
+        log::warn!("Track failed at guild {}: {}", event.guild_id, event.error);
+        let guild_id = GuildId(event.guild_id.0);
+        let failed = music::track_failed(
+            &client,
+            &self.http,
+            &self.players,
+            guild_id,
+            &event.track,
+            &event.error,
+            false,
+        );
+        if let Err(why) = failed.await {
+            log::error!(
+                "Could not report failed track at guild {}: {:?}",
+                guild_id,
+                why
+            );
+        }
+    }
+
+    async fn track_stuck(&self, client: LavalinkClient, event: lavalink_rs::model::TrackStuck) {
+        log::warn!("Track stuck at guild {}", event.guild_id);
+        let guild_id = GuildId(event.guild_id.0);
+        let failed = music::track_failed(
+            &client,
+            &self.http,
+            &self.players,
+            guild_id,
+            &event.track,
+            "it got stuck",
+            true,
+        );
+        if let Err(why) = failed.await {
+            log::error!(
+                "Could not skip stuck track at guild {}: {:?}",
+                guild_id,
+                why
+            );
+        }
+    }
+
+    async fn player_update(
+        &self,
+        _client: LavalinkClient,
+        event: lavalink_rs::model::PlayerUpdate,
+    ) {
+        self.players.update(GuildId(event.guild_id.0), |player| {
+            player.position = event.state.position.max(0) as u64
+        });
     }
 }
 
 #[group]
-#[commands(drown, host)]
+#[commands(drown, host, reload, prefix)]
 struct General;
 
 #[group]
-#[description("Play music in voice channels\nUse 'join' to have cantdrown join your channel first, then use 'play'")]
-#[commands(join, leave, play, skip, stop, current)]
+#[description("Host Zandronum servers")]
+#[commands(
+    host, extend, maps, vote, nextmap, schedule, schedules, cancel, history, top
+)]
+#[prefix("doom")]
+#[checks(DoomAccess)]
+struct Doom;
+
+#[group]
+#[description("Play music in voice channels\nUse 'play' and cantdrown joins your channel, or 'move' to bring it along")]
+#[commands(
+    join, leave, move_voice, play, search, skip, stop, current, queue, pause, resume, seek, volume,
+    loop_mode, remove, reorder, shuffle, clear, jump
+)]
 #[default_command(current)]
 #[prefix("song")]
+#[checks(SongAccess)]
 struct Song;
 
+#[group]
+#[description("Save the queue as a playlist and play it again later\nPut 'guild' before a name to share the playlist with the server")]
+#[commands(
+    save_playlist,
+    load_playlist,
+    list_playlists,
+    delete_playlist,
+    add_to_playlist
+)]
+#[default_command(list_playlists)]
+#[prefix("playlist")]
+#[only_in(guilds)]
+#[checks(PlaylistAccess)]
+struct Playlist;
+
 #[group]
 #[description("Add roles and assign roles to yourself")]
 #[commands(add, assign)]
 #[default_command(assign)]
 #[prefix("role")]
+#[checks(RoleAccess)]
 struct Role;
 
+#[group("Settings")]
+#[description("Change how cantdrown works in this server")]
+#[commands(get_setting, set_setting, unset_setting, list_settings)]
+#[default_command(list_settings)]
+#[prefix("config")]
+#[only_in(guilds)]
+#[required_permissions("ADMINISTRATOR")]
+struct GuildConfig;
+
+#[group]
+#[description("Choose who can use each group or command, by role or permission")]
+#[commands(list_access, allow_access, revoke_access, open_access, reset_access)]
+#[default_command(list_access)]
+#[prefix("access")]
+#[only_in(guilds)]
+#[required_permissions("ADMINISTRATOR")]
+struct Access;
+
 #[help]
 async fn my_help(
     context: &Context,
@@ -78,19 +250,47 @@ async fn my_help(
     Ok(())
 }
 
+#[hook]
+async fn dynamic_prefix(ctx: &Context, msg: &Message) -> Option<String> {
+    Some(settings::prefix(ctx, msg.guild_id).await)
+}
+
 #[tokio::main]
 async fn main() {
     dotenv::dotenv().ok();
 
-    let log_path = env::var("CANTDROWN_LOG_DIR").unwrap_or(String::from("logs"));
-    Logger::with_env_or_str("info")
+    let loaded = match config::load() {
+        Ok(loaded) => loaded,
+        Err(why) => {
+            eprintln!("{}", why);
+            std::process::exit(1);
+        }
+    };
+    let config = loaded.config;
+
+    if env::args().any(|arg| arg == "--print-config") {
+        print!("{}", config.to_redacted_string());
+        if let Some(why) = loaded.doom_error {
+            eprintln!("Doom hosting is disabled: {}", why);
+        }
+        return;
+    }
+
+    Logger::with_env_or_str(&config.logging.level)
         .log_to_file()
-        .directory(log_path)
+        .directory(&config.logging.directory)
         .duplicate_to_stderr(Duplicate::Warn)
         .start()
         .expect("Failed to initialize logger");
 
-    let token = env::var("DISCORD_TOKEN").expect("Expected a token in the environment");
+    if let Some(why) = loaded.doom_error {
+        log::error!("Doom hosting is disabled: {}", why);
+    }
+
+    let token = config.bot.token.clone();
+    if token.is_empty() {
+        panic!("Expected a token in the config or the environment");
+    }
 
     let http = Http::new_with_token(&token);
     let (owners, bot_id) = match http.get_current_application_info().await {
@@ -104,23 +304,49 @@ async fn main() {
     };
 
     let framework = StandardFramework::new()
-        .configure(|c| c.owners(owners).prefix("!"))
+        .configure(|c| {
+            c.owners(owners)
+                .on_mention(Some(bot_id))
+                .prefix("")
+                .dynamic_prefix(dynamic_prefix)
+        })
+        .after(error::after)
+        .on_dispatch_error(error::on_dispatch_error)
         .help(&MY_HELP)
         .group(&GENERAL_GROUP)
+        .group(&DOOM_GROUP)
         .group(&SONG_GROUP)
-        .group(&ROLE_GROUP);
+        .group(&PLAYLIST_GROUP)
+        .group(&ROLE_GROUP)
+        .group(&GUILDCONFIG_GROUP)
+        .group(&ACCESS_GROUP);
+
+    let db = db::open().expect("Could not open database");
+
+    let players = Players::default();
 
     let mut client = Client::builder(&token)
         .framework(framework)
         .event_handler(Handler)
+        .application_id(*bot_id.as_u64())
+        .type_map_insert::<ServersKey>(Servers::new(db.clone()))
+        .type_map_insert::<DbKey>(db)
+        .type_map_insert::<CooldownsKey>(Cooldowns::default())
+        .type_map_insert::<PlayersKey>(players.clone())
         .register_songbird()
-        .register_doom()
+        .register_doom(config.doom.clone())
+        .type_map_insert::<ConfigKey>(config.clone())
         .await
         .expect("Error creating client");
 
     let lavalink_client = LavalinkClient::builder(bot_id)
-        .set_password(env::var("LAVALINK_PASSWORD").unwrap_or(String::from("youshallnotpass")))
-        .build(LavalinkHandler)
+        .set_host(&config.lavalink.host)
+        .set_port(config.lavalink.port)
+        .set_password(&config.lavalink.password)
+        .build(LavalinkHandler {
+            http: client.cache_and_http.http.clone(),
+            players,
+        })
         .await
         .expect("Couldn't create lavalink client");
 
@@ -129,7 +355,24 @@ async fn main() {
         data.insert::<LavalinkKey>(lavalink_client);
     }
 
+    doom::schedule::restore(client.cache_and_http.http.clone(), client.data.clone()).await;
+
+    if let Err(why) = config::watch::watch(client.data.clone()) {
+        log::error!("Could not watch config directory: {:?}", why);
+    }
+
+    let shard_manager = client.shard_manager.clone();
+    let data = client.data.clone();
+    tokio::spawn(async move {
+        shutdown::wait_for_signal().await;
+        log::info!("Shutting down");
+        shutdown::cleanup(&data).await;
+        shard_manager.lock().await.shutdown_all().await;
+    });
+
     if let Err(why) = client.start().await {
         log::error!("Client error: {:?}", why);
     }
+
+    log::logger().flush();
 }
@@ -0,0 +1,417 @@
+//! What Lavalink doesn't keep track of for each guild's player, filled in from
+//! its events.
+
+use crate::{
+    services::{
+        discord::{now_playing_embed, LavalinkVoice},
+        song::{format_duration, NowPlaying},
+        LoopMode, QueuedTrack, VoiceBackend,
+    },
+    util::LavalinkKey,
+};
+use lavalink_rs::LavalinkClient;
+use serenity::{
+    client::Context,
+    http::Http,
+    model::{
+        channel::Message,
+        id::{ChannelId, GuildId, MessageId},
+    },
+    prelude::TypeMapKey,
+};
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+/// How often players are checked for being left alone or idle.
+const CHECK_INTERVAL: Duration = Duration::from_secs(15);
+
+static WATCHING: AtomicBool = AtomicBool::new(false);
+
+pub struct PlayersKey;
+
+impl TypeMapKey for PlayersKey {
+    type Value = Players;
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Player {
+    /// How far into the current track Lavalink last said it was, in milliseconds.
+    pub position: u64,
+    pub paused: bool,
+    pub loop_mode: LoopMode,
+    /// The track that last started, kept so it can be queued again when it ends.
+    pub current: Option<QueuedTrack>,
+    /// The `song current` message to update when the track changes.
+    pub now_playing_message: Option<(ChannelId, MessageId)>,
+    /// Where the session was started from, and where songs are announced.
+    pub text_channel: Option<ChannelId>,
+    /// When everyone else left the voice channel.
+    pub alone_since: Option<Instant>,
+    /// When the last song stopped or was paused.
+    pub idle_since: Option<Instant>,
+}
+
+/// The players of every guild, shared with the Lavalink event handler.
+#[derive(Clone, Default)]
+pub struct Players {
+    players: Arc<Mutex<HashMap<GuildId, Player>>>,
+}
+
+impl Players {
+    pub fn get(&self, guild_id: GuildId) -> Player {
+        self.players
+            .lock()
+            .unwrap()
+            .get(&guild_id)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    pub fn update(&self, guild_id: GuildId, change: impl FnOnce(&mut Player)) {
+        change(self.players.lock().unwrap().entry(guild_id).or_default());
+    }
+
+    pub fn remove(&self, guild_id: GuildId) {
+        self.players.lock().unwrap().remove(&guild_id);
+    }
+
+    pub fn contains(&self, guild_id: GuildId) -> bool {
+        self.players.lock().unwrap().contains_key(&guild_id)
+    }
+
+    pub fn guilds(&self) -> Vec<GuildId> {
+        self.players.lock().unwrap().keys().copied().collect()
+    }
+}
+
+pub async fn get(ctx: &Context) -> Players {
+    let data = ctx.data.read().await;
+    data.get::<PlayersKey>()
+        .expect("Players are not in TypeMap")
+        .clone()
+}
+
+/// Makes `message` the guild's live now playing message if `live_now_playing`
+/// is on, otherwise stops updating the previous one.
+pub async fn show_now_playing(
+    ctx: &Context,
+    guild_id: GuildId,
+    message: &Message,
+) -> anyhow::Result<()> {
+    let settings = crate::settings::for_guild(ctx, Some(guild_id)).await?;
+    let live = if settings.live_now_playing {
+        Some((message.channel_id, message.id))
+    } else {
+        None
+    };
+    get(ctx)
+        .await
+        .update(guild_id, |player| player.now_playing_message = live);
+    Ok(())
+}
+
+/// Remembers the track that started playing and announces it, either by
+/// updating the guild's live now playing message or in the session's channel.
+pub async fn track_started(
+    lavalink: &LavalinkClient,
+    http: &Http,
+    players: &Players,
+    guild_id: GuildId,
+) -> anyhow::Result<()> {
+    let (current, next) = match lavalink.nodes().await.get(guild_id.as_u64()) {
+        Some(node) => (
+            node.now_playing.as_ref().map(QueuedTrack::from),
+            node.queue.get(1).map(QueuedTrack::from),
+        ),
+        None => (None, None),
+    };
+    let previous = players.get(guild_id).current;
+    players.update(guild_id, |player| {
+        player.position = 0;
+        player.current = current.clone();
+    });
+
+    let current = match current {
+        Some(current) => current,
+        None => return Ok(()),
+    };
+    let player = players.get(guild_id);
+    if let Some((channel_id, message_id)) = player.now_playing_message {
+        let now_playing = NowPlaying {
+            current,
+            next,
+            position: 0,
+            paused: player.paused,
+            loop_mode: player.loop_mode,
+        };
+        let edited = channel_id
+            .edit_message(http, message_id, |m| {
+                m.embed(|e| now_playing_embed(e, &now_playing))
+            })
+            .await;
+        if let Err(why) = edited {
+            // Most likely deleted, stop trying to update it.
+            players.update(guild_id, |player| player.now_playing_message = None);
+            return Err(why.into());
+        }
+        return Ok(());
+    }
+
+    // Looping a single song would announce it over and over.
+    let repeated = player.loop_mode == LoopMode::Track
+        && previous.map_or(false, |previous| {
+            previous.track.encoded == current.track.encoded
+        });
+    if let (Some(text_channel), false) = (player.text_channel, repeated) {
+        let track = &current.track;
+        let length = if track.is_stream {
+            String::from("live")
+        } else {
+            format_duration(track.length)
+        };
+        text_channel
+            .say(
+                http,
+                format!("Now playing: **{}** `{}`", track.title, length),
+            )
+            .await?;
+    }
+    Ok(())
+}
+
+/// Tells the session's channel that a song couldn't be played. Stuck songs are
+/// skipped here; songs that failed end by themselves and the queue moves on.
+pub async fn track_failed(
+    lavalink: &LavalinkClient,
+    http: &Http,
+    players: &Players,
+    guild_id: GuildId,
+    encoded: &str,
+    why: &str,
+    skip: bool,
+) -> anyhow::Result<()> {
+    let title = lavalink
+        .nodes()
+        .await
+        .get(guild_id.as_u64())
+        .and_then(|node| {
+            node.queue
+                .iter()
+                .find(|queued| queued.track.track == encoded)
+                .and_then(|queued| queued.track.info.as_ref())
+                .map(|info| info.title.clone())
+        })
+        .unwrap_or_else(|| String::from("a song"));
+
+    if skip {
+        lavalink.skip(guild_id).await;
+    }
+
+    if let Some(text_channel) = players.get(guild_id).text_channel {
+        text_channel
+            .say(
+                http,
+                format!("Could not play **{}** ({}), skipping it", title, why),
+            )
+            .await?;
+    }
+    Ok(())
+}
+
+/// Queues the track that finished again if the guild is looping, to play next
+/// for a looped track or at the back for a looped queue. Tracks that were
+/// skipped or stopped aren't looped.
+pub async fn track_finished(
+    lavalink: &LavalinkClient,
+    players: &Players,
+    guild_id: GuildId,
+    encoded: &str,
+    reason: &str,
+) -> anyhow::Result<()> {
+    let player = players.get(guild_id);
+    let current = match (player.loop_mode, player.current) {
+        (LoopMode::Off, _) | (_, None) => return Ok(()),
+        (_, Some(current)) if reason != "FINISHED" || current.track.encoded != encoded => {
+            return Ok(())
+        }
+        (_, Some(current)) => current,
+    };
+
+    if player.loop_mode == LoopMode::Track {
+        // Lavalink removes the finished track from the front of the queue
+        // once this returns, so a copy right behind it is what plays next.
+        let nodes = lavalink.nodes().await;
+        if let Some(mut node) = nodes.get_mut(guild_id.as_u64()) {
+            if replay_next(&mut node.queue, |queued| queued.track.track == encoded) {
+                return Ok(());
+            }
+        }
+    }
+
+    let play = lavalink.play(guild_id, current.track.to_lavalink());
+    match current.requester {
+        Some(requester) => play.requester(requester).queue().await?,
+        None => play.queue().await?,
+    }
+    Ok(())
+}
+
+/// Copies the finished track at the front of `queue` to right after it,
+/// leaving the rest of the queue as it was. Returns whether it was there.
+fn replay_next<T: Clone>(queue: &mut Vec<T>, finished: impl Fn(&T) -> bool) -> bool {
+    match queue.first() {
+        Some(first) if finished(first) => {
+            let copy = first.clone();
+            queue.insert(1, copy);
+            true
+        }
+        _ => false,
+    }
+}
+
+/// Notes when cantdrown has been left alone in its voice channel, so it can
+/// leave once `music.empty_timeout` has passed.
+pub async fn voice_state_changed(ctx: &Context, guild_id: GuildId) {
+    let players = get(ctx).await;
+    if !players.contains(guild_id) {
+        return;
+    }
+
+    let alone = is_alone(ctx, guild_id).await;
+    players.update(guild_id, |player| match (alone, player.alone_since) {
+        (true, None) => player.alone_since = Some(Instant::now()),
+        (false, Some(_)) => player.alone_since = None,
+        _ => {}
+    });
+}
+
+async fn is_alone(ctx: &Context, guild_id: GuildId) -> bool {
+    let bot_id = ctx.cache.current_user_id().await;
+    let guild = match ctx.cache.guild(guild_id).await {
+        Some(guild) => guild,
+        None => return false,
+    };
+    let channel_id = match guild
+        .voice_states
+        .get(&bot_id)
+        .and_then(|state| state.channel_id)
+    {
+        Some(channel_id) => channel_id,
+        None => return false,
+    };
+
+    !guild.voice_states.values().any(|state| {
+        state.channel_id == Some(channel_id)
+            && state.user_id != bot_id
+            && !state
+                .member
+                .as_ref()
+                .map_or(false, |member| member.user.bot)
+    })
+}
+
+/// Starts checking for players to disconnect. Only the first call does
+/// anything, since `ready` fires again after reconnecting.
+pub fn watch(ctx: Context) {
+    if WATCHING.swap(true, Ordering::SeqCst) {
+        return;
+    }
+
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(CHECK_INTERVAL);
+        loop {
+            interval.tick().await;
+            leave_inactive(&ctx).await;
+        }
+    });
+}
+
+async fn leave_inactive(ctx: &Context) {
+    let config = crate::config::get(ctx).await.music;
+    let players = get(ctx).await;
+    let lavalink = {
+        let data = ctx.data.read().await;
+        match data.get::<LavalinkKey>() {
+            Some(lavalink) => lavalink.clone(),
+            None => return,
+        }
+    };
+    let voice = LavalinkVoice::new(ctx);
+
+    for guild_id in players.guilds() {
+        if !voice.is_connected(guild_id).await {
+            players.remove(guild_id);
+            continue;
+        }
+
+        let playing = lavalink
+            .nodes()
+            .await
+            .get(guild_id.as_u64())
+            .map_or(false, |node| node.now_playing.is_some());
+        players.update(guild_id, |player| {
+            if playing && !player.paused {
+                player.idle_since = None;
+            } else if player.idle_since.is_none() {
+                player.idle_since = Some(Instant::now());
+            }
+        });
+
+        let player = players.get(guild_id);
+        let reason = if expired(player.alone_since, config.empty_timeout) {
+            "everyone else left"
+        } else if expired(player.idle_since, config.idle_timeout) {
+            "nothing has played in a while"
+        } else {
+            continue;
+        };
+
+        log::info!("Leaving voice at guild {} since {}", guild_id, reason);
+        if let Err(why) = voice.leave(guild_id).await {
+            log::error!("Could not leave voice at guild {}: {:?}", guild_id, why);
+            continue;
+        }
+        if let Some(text_channel) = player.text_channel {
+            let said = text_channel
+                .say(
+                    &ctx.http,
+                    format!("Leaving the voice channel since {}", reason),
+                )
+                .await;
+            if let Err(why) = said {
+                log::error!("Could not say why voice was left: {:?}", why);
+            }
+        }
+    }
+}
+
+fn expired(since: Option<Instant>, timeout: u64) -> bool {
+    match since {
+        Some(since) if timeout > 0 => since.elapsed() >= Duration::from_secs(timeout),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn looped_tracks_play_next() {
+        let mut queue = vec!["intro", "second", "third"];
+        assert!(replay_next(&mut queue, |track| *track == "intro"));
+        assert_eq!(queue, vec!["intro", "intro", "second", "third"]);
+
+        // Once the finished track is gone there is nothing to copy.
+        let mut queue = vec!["second", "third"];
+        assert!(!replay_next(&mut queue, |track| *track == "intro"));
+        assert_eq!(queue, vec!["second", "third"]);
+        assert!(!replay_next(&mut Vec::<&str>::new(), |_| true));
+    }
+}
@@ -0,0 +1,62 @@
+//! Embeds with more than one page, turned with reactions.
+
+use serenity::{
+    builder::CreateEmbed,
+    client::Context,
+    framework::standard::CommandResult,
+    model::{channel::Message, channel::ReactionType, id::UserId},
+};
+use std::time::Duration;
+
+const PREVIOUS: &str = "\u{25c0}";
+const NEXT: &str = "\u{25b6}";
+
+/// How long the pages keep turning after the last reaction.
+const TIMEOUT: Duration = Duration::from_secs(120);
+
+/// Lets `user_id` page through `message` by reacting to it, drawing pages with
+/// `render`. Pages count from 1. Both adding and removing a reaction turn the
+/// page, so this works without permission to remove other users' reactions.
+pub async fn paginate<F>(
+    ctx: &Context,
+    mut message: Message,
+    user_id: UserId,
+    mut page: usize,
+    page_count: usize,
+    render: F,
+) -> CommandResult
+where
+    F: Fn(&mut CreateEmbed, usize) -> &mut CreateEmbed,
+{
+    if page_count <= 1 {
+        return Ok(());
+    }
+
+    for emoji in [PREVIOUS, NEXT].iter() {
+        message
+            .react(ctx, ReactionType::Unicode(emoji.to_string()))
+            .await?;
+    }
+
+    while let Some(action) = message
+        .await_reaction(ctx)
+        .author_id(user_id)
+        .removed(true)
+        .timeout(TIMEOUT)
+        .await
+    {
+        let next = match &action.as_inner_ref().emoji {
+            ReactionType::Unicode(emoji) if emoji == PREVIOUS => page.saturating_sub(1).max(1),
+            ReactionType::Unicode(emoji) if emoji == NEXT => (page + 1).min(page_count),
+            _ => continue,
+        };
+        if next != page {
+            page = next;
+            message.edit(ctx, |m| m.embed(|e| render(e, page))).await?;
+        }
+    }
+
+    // Only works with Manage Messages, the arrows just stop doing anything otherwise.
+    let _ = message.delete_reactions(ctx).await;
+    Ok(())
+}
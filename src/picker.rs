@@ -0,0 +1,85 @@
+//! Numbered choices, picked by replying with numbers or reacting to them.
+
+use crate::services::song::parse_picks;
+use serenity::{
+    client::Context,
+    framework::standard::CommandResult,
+    model::{channel::Message, channel::ReactionType, id::UserId},
+};
+use std::time::{Duration, Instant};
+
+const NUMBERS: &[&str] = &[
+    "1\u{fe0f}\u{20e3}",
+    "2\u{fe0f}\u{20e3}",
+    "3\u{fe0f}\u{20e3}",
+    "4\u{fe0f}\u{20e3}",
+    "5\u{fe0f}\u{20e3}",
+    "6\u{fe0f}\u{20e3}",
+    "7\u{fe0f}\u{20e3}",
+    "8\u{fe0f}\u{20e3}",
+    "9\u{fe0f}\u{20e3}",
+];
+const CANCEL: &str = "\u{274c}";
+
+/// How long to wait for a pick.
+const TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Waits for `user_id` to pick from the `count` choices listed in `message`,
+/// returning their indexes. A reply like `1 3` picks several, a reaction picks
+/// one. `None` if they cancelled or didn't pick in time.
+pub async fn pick(
+    ctx: &Context,
+    message: &Message,
+    user_id: UserId,
+    count: usize,
+) -> CommandResult<Option<Vec<usize>>> {
+    let count = count.min(NUMBERS.len());
+    for emoji in NUMBERS[..count].iter().chain([CANCEL].iter()) {
+        message
+            .react(ctx, ReactionType::Unicode(emoji.to_string()))
+            .await?;
+    }
+
+    let deadline = Instant::now() + TIMEOUT;
+    let picks = loop {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining == Duration::ZERO {
+            break None;
+        }
+
+        tokio::select! {
+            reply = message
+                .channel_id
+                .await_reply(ctx)
+                .author_id(user_id)
+                .timeout(remaining) => match reply {
+                    Some(reply) if reply.content.trim().eq_ignore_ascii_case("cancel") => break None,
+                    Some(reply) => match parse_picks(&reply.content, count) {
+                        Some(picks) => break Some(picks),
+                        None => continue,
+                    },
+                    None => break None,
+                },
+            reaction = message
+                .await_reaction(ctx)
+                .author_id(user_id)
+                .timeout(remaining) => match reaction {
+                    Some(reaction) => match &reaction.as_inner_ref().emoji {
+                        ReactionType::Unicode(emoji) if emoji == CANCEL => break None,
+                        ReactionType::Unicode(emoji) => {
+                            match NUMBERS[..count].iter().position(|number| number == emoji) {
+                                Some(index) => break Some(vec![index]),
+                                None => continue,
+                            }
+                        }
+                        _ => continue,
+                    },
+                    None => break None,
+                },
+        }
+    };
+
+    // Only works with Manage Messages, like the page arrows.
+    let _ = message.delete_reactions(ctx).await;
+    Ok(picks)
+}
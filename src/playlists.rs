@@ -0,0 +1,140 @@
+//! Playlists saved by members for themselves or for their whole guild. The
+//! tracks are kept as Lavalink loaded them, so they play without searching.
+
+use crate::services::Track;
+use serde::{Deserialize, Serialize};
+use serenity::model::id::{GuildId, UserId};
+
+const TREE: &str = "playlists";
+
+/// Most songs a playlist can hold.
+pub const MAX_TRACKS: usize = 500;
+
+/// Whose playlist it is.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Owner {
+    User(UserId),
+    Guild(GuildId),
+}
+
+impl Owner {
+    fn prefix(self) -> Vec<u8> {
+        let (kind, id) = match self {
+            Owner::User(user_id) => (b'u', user_id.0),
+            Owner::Guild(guild_id) => (b'g', guild_id.0),
+        };
+        let mut prefix = vec![kind];
+        prefix.extend_from_slice(&id.to_be_bytes());
+        prefix
+    }
+
+    fn key(self, name: &str) -> Vec<u8> {
+        let mut key = self.prefix();
+        key.extend_from_slice(name.to_lowercase().as_bytes());
+        key
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Playlist {
+    pub name: String,
+    /// Who saved it, the only one who can change a guild's playlist.
+    pub created_by: UserId,
+    pub tracks: Vec<Track>,
+}
+
+impl Playlist {
+    /// Anyone can change their own playlists, but only the member who saved a
+    /// guild's playlist can change that.
+    pub fn can_change(&self, owner: Owner, user_id: UserId) -> bool {
+        match owner {
+            Owner::User(_) => true,
+            Owner::Guild(_) => self.created_by == user_id,
+        }
+    }
+
+    /// Milliseconds it takes to play, not counting streams.
+    pub fn length(&self) -> u64 {
+        self.tracks
+            .iter()
+            .filter(|track| !track.is_stream)
+            .map(|track| track.length)
+            .sum()
+    }
+}
+
+/// Finds a playlist by name, ignoring case.
+pub fn get(db: &sled::Db, owner: Owner, name: &str) -> anyhow::Result<Option<Playlist>> {
+    crate::db::get_value(&db.open_tree(TREE)?, owner.key(name))
+}
+
+/// Saves a playlist, replacing any with the same name.
+pub fn save(db: &sled::Db, owner: Owner, playlist: &Playlist) -> anyhow::Result<()> {
+    crate::db::insert(&db.open_tree(TREE)?, owner.key(&playlist.name), playlist)
+}
+
+/// Deletes a playlist, returning whether there was one.
+pub fn delete(db: &sled::Db, owner: Owner, name: &str) -> anyhow::Result<bool> {
+    Ok(db.open_tree(TREE)?.remove(owner.key(name))?.is_some())
+}
+
+/// Every playlist an owner has, by name.
+pub fn list(db: &sled::Db, owner: Owner) -> anyhow::Result<Vec<Playlist>> {
+    db.open_tree(TREE)?
+        .scan_prefix(owner.prefix())
+        .values()
+        .map(|bytes| Ok(serde_json::from_slice(&bytes?)?))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn playlist(name: &str, titles: &[&str]) -> Playlist {
+        let track = |title: &&str| Track {
+            encoded: format!("encoded:{}", title),
+            identifier: title.to_lowercase(),
+            title: title.to_string(),
+            author: String::from("Someone"),
+            uri: format!("https://example.com/{}", title.to_lowercase()),
+            length: 60_000,
+            is_stream: false,
+            is_seekable: true,
+        };
+        Playlist {
+            name: name.to_string(),
+            created_by: UserId(4),
+            tracks: titles.iter().map(track).collect(),
+        }
+    }
+
+    #[test]
+    fn playlists_are_kept_per_owner() {
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        let user = Owner::User(UserId(4));
+        let guild = Owner::Guild(GuildId(1));
+
+        save(&db, user, &playlist("Mine", &["One", "Two"])).unwrap();
+        save(&db, guild, &playlist("Ours", &["Three"])).unwrap();
+
+        let mine = get(&db, user, "mine").unwrap().unwrap();
+        assert_eq!(mine, playlist("Mine", &["One", "Two"]));
+        assert_eq!(mine.length(), 120_000);
+        assert!(get(&db, guild, "Mine").unwrap().is_none());
+        let names: Vec<String> = list(&db, guild)
+            .unwrap()
+            .into_iter()
+            .map(|playlist| playlist.name)
+            .collect();
+        assert_eq!(names, vec!["Ours"]);
+
+        assert!(mine.can_change(user, UserId(5)));
+        assert!(mine.can_change(guild, UserId(4)));
+        assert!(!mine.can_change(guild, UserId(5)));
+
+        assert!(delete(&db, user, "MINE").unwrap());
+        assert!(!delete(&db, user, "Mine").unwrap());
+        assert!(list(&db, user).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,834 @@
+//! The service traits implemented with serenity, songbird and Lavalink.
+
+use super::{
+    doom::DoomService,
+    playlist::{self, PlaylistListing, PlaylistService},
+    role::RoleService,
+    song::{format_duration, progress_bar, NowPlaying, QueueListing, SongService},
+    DoomRecords, GuildDirectory, HostError, LoopMode, Messenger, PlaylistStore, QueuedTrack,
+    RoleInfo, ServerRegistry, ServerSummary, Track, VoiceBackend,
+};
+use crate::{
+    config::doom::DoomConfig,
+    doom::{
+        self,
+        download::download_wads,
+        history::{self, HostRecord},
+        schedule::{self, Schedule},
+        server::{Servers, ServersKey},
+        Origin,
+    },
+    music::Players,
+    playlists::{self, Owner, Playlist},
+    util::LavalinkKey,
+};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use lavalink_rs::{model, LavalinkClient};
+use serenity::{
+    builder::CreateEmbed,
+    client::Context,
+    framework::standard::CommandResult,
+    http::Http,
+    model::{
+        channel::{Message, ReactionType},
+        id::{ChannelId, GuildId, RoleId, UserId},
+        interactions::application_command::ApplicationCommandInteraction,
+        misc::Mentionable,
+    },
+    prelude::{RwLock, TypeMap},
+};
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+pub fn song_service(ctx: &Context) -> SongService {
+    SongService::new(
+        Arc::new(LavalinkVoice::new(ctx)),
+        Arc::new(CachedGuilds { ctx: ctx.clone() }),
+    )
+}
+
+pub async fn playlist_service(ctx: &Context) -> PlaylistService {
+    PlaylistService::new(
+        song_service(ctx),
+        Arc::new(SavedPlaylists {
+            db: crate::db::get(ctx).await,
+        }),
+    )
+}
+
+pub fn role_service(ctx: &Context) -> RoleService {
+    RoleService::new(Arc::new(CachedGuilds { ctx: ctx.clone() }))
+}
+
+pub async fn doom_service(ctx: &Context) -> DoomService {
+    let servers = {
+        let data = ctx.data.read().await;
+        data.get::<ServersKey>()
+            .expect("Doom servers are not in TypeMap")
+            .clone()
+    };
+    DoomService::new(
+        Arc::new(DoomServers {
+            http: ctx.http.clone(),
+            servers,
+        }),
+        Arc::new(DoomDatabase {
+            http: ctx.http.clone(),
+            data: ctx.data.clone(),
+            db: crate::db::get(ctx).await,
+        }),
+    )
+}
+
+/// Replies by sending messages to a channel.
+pub struct ChannelMessenger {
+    http: Arc<Http>,
+    channel_id: ChannelId,
+}
+
+impl ChannelMessenger {
+    pub fn new(ctx: &Context, channel_id: ChannelId) -> Self {
+        ChannelMessenger {
+            http: ctx.http.clone(),
+            channel_id,
+        }
+    }
+}
+
+#[async_trait]
+impl Messenger for ChannelMessenger {
+    async fn say(&self, content: &str) -> CommandResult {
+        self.channel_id.say(&self.http, content).await?;
+        Ok(())
+    }
+
+    async fn say_quietly(&self, content: &str) -> CommandResult {
+        self.channel_id
+            .send_message(&self.http, |m| {
+                m.content(content).allowed_mentions(|am| am.empty_parse())
+            })
+            .await?;
+        Ok(())
+    }
+
+    async fn poll(&self, question: &str, seconds: u64) -> CommandResult<(usize, usize)> {
+        let message = self
+            .channel_id
+            .say(&self.http, poll_text(question, seconds))
+            .await?;
+        Ok(tally(&self.http, &message, seconds).await?)
+    }
+}
+
+const VOTE_YES: &str = "\u{2705}";
+const VOTE_NO: &str = "\u{274c}";
+
+fn poll_text(question: &str, seconds: u64) -> String {
+    format!(
+        "{} React with {} or {} within {} seconds",
+        question, VOTE_YES, VOTE_NO, seconds
+    )
+}
+
+/// Reacts to a poll with the choices and counts everyone else's reactions
+/// once `seconds` are up.
+async fn tally(http: &Arc<Http>, poll: &Message, seconds: u64) -> serenity::Result<(usize, usize)> {
+    poll.react(http, ReactionType::Unicode(VOTE_YES.to_string()))
+        .await?;
+    poll.react(http, ReactionType::Unicode(VOTE_NO.to_string()))
+        .await?;
+
+    tokio::time::sleep(Duration::from_secs(seconds)).await;
+
+    let mut votes = [0; 2];
+    for (count, emoji) in votes.iter_mut().zip([VOTE_YES, VOTE_NO].iter()) {
+        let users = poll
+            .reaction_users(
+                http,
+                ReactionType::Unicode(emoji.to_string()),
+                Some(100),
+                None,
+            )
+            .await?;
+        *count = users.iter().filter(|user| !user.bot).count();
+    }
+    Ok((votes[0], votes[1]))
+}
+
+/// Replies to a deferred slash command. The first reply fills in the deferred
+/// response and any after that are sent as follow-ups.
+pub struct InteractionMessenger<'a> {
+    http: Arc<Http>,
+    command: &'a ApplicationCommandInteraction,
+    replied: AtomicBool,
+}
+
+impl<'a> InteractionMessenger<'a> {
+    pub fn new(ctx: &Context, command: &'a ApplicationCommandInteraction) -> Self {
+        InteractionMessenger {
+            http: ctx.http.clone(),
+            command,
+            replied: AtomicBool::new(false),
+        }
+    }
+
+    /// Replies with an embed, returning the message it was sent in.
+    pub async fn send_embed(&self, embed: CreateEmbed) -> serenity::Result<Message> {
+        if self.replied.swap(true, Ordering::SeqCst) {
+            self.command
+                .create_followup_message(&self.http, |f| f.add_embed(embed))
+                .await
+        } else {
+            self.command
+                .edit_original_interaction_response(&self.http, |r| r.add_embed(embed))
+                .await
+        }
+    }
+
+    async fn reply(&self, content: &str, quiet: bool) -> serenity::Result<Message> {
+        if self.replied.swap(true, Ordering::SeqCst) {
+            self.command
+                .create_followup_message(&self.http, |f| {
+                    if quiet {
+                        f.allowed_mentions(|am| am.empty_parse());
+                    }
+                    f.content(content)
+                })
+                .await
+        } else {
+            self.command
+                .edit_original_interaction_response(&self.http, |r| {
+                    if quiet {
+                        r.allowed_mentions(|am| am.empty_parse());
+                    }
+                    r.content(content)
+                })
+                .await
+        }
+    }
+
+    /// Fills in the deferred response if the command didn't say anything.
+    pub async fn finish(&self) -> CommandResult {
+        if !self.replied.load(Ordering::SeqCst) {
+            self.say("Done").await?;
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Messenger for InteractionMessenger<'_> {
+    async fn say(&self, content: &str) -> CommandResult {
+        self.reply(content, false).await?;
+        Ok(())
+    }
+
+    async fn say_quietly(&self, content: &str) -> CommandResult {
+        self.reply(content, true).await?;
+        Ok(())
+    }
+
+    async fn poll(&self, question: &str, seconds: u64) -> CommandResult<(usize, usize)> {
+        let message = self.reply(&poll_text(question, seconds), false).await?;
+        Ok(tally(&self.http, &message, seconds).await?)
+    }
+}
+
+/// Draws `playlist list`.
+pub fn playlists_embed<'a>(
+    e: &'a mut CreateEmbed,
+    listing: &PlaylistListing,
+) -> &'a mut CreateEmbed {
+    e.title("Playlists")
+        .field("Yours", playlist::describe(&listing.mine), false)
+        .field("This server's", playlist::describe(&listing.shared), false)
+}
+
+/// Draws one page of `song queue`.
+pub fn queue_embed<'a>(
+    e: &'a mut CreateEmbed,
+    listing: &QueueListing,
+    page: usize,
+) -> &'a mut CreateEmbed {
+    let mut description = format!(
+        "**Now playing:** {} `{}`",
+        describe(&listing.now_playing),
+        format_duration(listing.position)
+    );
+    if listing.upcoming.is_empty() {
+        description.push_str("\n\nNothing else is queued");
+    } else {
+        description.push('\n');
+    }
+    for (position, queued) in listing.page(page) {
+        description.push_str(&format!("\n`{}.` {}", position, describe(queued)));
+    }
+
+    let mut footer = format!(
+        "Page {}/{} | {} upcoming | {} remaining",
+        page,
+        listing.page_count(),
+        listing.upcoming.len(),
+        format_duration(listing.remaining)
+    );
+    if listing.loop_mode != LoopMode::Off {
+        footer.push_str(&format!(" | {}", listing.loop_mode.describe()));
+    }
+
+    e.title("Queue")
+        .description(description)
+        .footer(|f| f.text(footer))
+}
+
+/// Draws the track that's playing for `song current`.
+pub fn now_playing_embed<'a>(
+    e: &'a mut CreateEmbed,
+    now_playing: &NowPlaying,
+) -> &'a mut CreateEmbed {
+    let track = &now_playing.current.track;
+    let progress = if track.is_stream {
+        String::from("\u{1f534} Live")
+    } else {
+        format!(
+            "{} `{}/{}`",
+            progress_bar(now_playing.position, track.length, 16),
+            format_duration(now_playing.position),
+            format_duration(track.length)
+        )
+    };
+
+    e.author(|a| {
+        a.name(if now_playing.paused {
+            "Paused"
+        } else {
+            "Now playing"
+        })
+    })
+    .title(&track.title)
+    .url(&track.uri)
+    .description(progress)
+    .field("Author", &track.author, true);
+    if let Some(requester) = now_playing.current.requester {
+        e.field("Requested by", requester.mention(), true);
+    }
+    if let Some(thumbnail) = thumbnail(track) {
+        e.thumbnail(thumbnail);
+    }
+    match &now_playing.next {
+        Some(next) => e.field("Up next", describe(next), false),
+        None => e.field("Up next", "Nothing", false),
+    };
+    if now_playing.loop_mode != LoopMode::Off {
+        e.footer(|f| f.text(now_playing.loop_mode.describe()));
+    }
+    e
+}
+
+/// Lists the results of `song search` to pick from.
+pub fn search_embed<'a>(
+    e: &'a mut CreateEmbed,
+    query: &str,
+    results: &[Track],
+) -> &'a mut CreateEmbed {
+    let description = results
+        .iter()
+        .enumerate()
+        .map(|(i, track)| format!("`{}.` {}", i + 1, describe_track(track)))
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    e.title(format!("Results for \"{}\"", query))
+        .description(description)
+        .footer(|f| f.text("Reply with numbers like 1 3 or react to pick, or cancel"))
+}
+
+/// YouTube's thumbnail for a track, the only source that has a predictable one.
+fn thumbnail(track: &Track) -> Option<String> {
+    if track.uri.contains("youtube.com/") || track.uri.contains("youtu.be/") {
+        Some(format!(
+            "https://img.youtube.com/vi/{}/hqdefault.jpg",
+            track.identifier
+        ))
+    } else {
+        None
+    }
+}
+
+fn describe(queued: &QueuedTrack) -> String {
+    let mut line = describe_track(&queued.track);
+    if let Some(requester) = queued.requester {
+        line.push_str(&format!(" for {}", requester.mention()));
+    }
+    line
+}
+
+fn describe_track(track: &Track) -> String {
+    let length = if track.is_stream {
+        String::from("live")
+    } else {
+        format_duration(track.length)
+    };
+    format!(
+        "[{}]({}) by {} `{}`",
+        track.title, track.uri, track.author, length
+    )
+}
+
+/// Guilds as seen through the cache, changed over HTTP.
+pub struct CachedGuilds {
+    ctx: Context,
+}
+
+#[async_trait]
+impl GuildDirectory for CachedGuilds {
+    async fn voice_channel(&self, guild_id: GuildId, user_id: UserId) -> Option<ChannelId> {
+        let guild = guild_id.to_guild_cached(&self.ctx.cache).await?;
+        guild
+            .voice_states
+            .get(&user_id)
+            .and_then(|voice_state| voice_state.channel_id)
+    }
+
+    async fn roles(&self, guild_id: GuildId) -> Vec<RoleInfo> {
+        match guild_id.to_guild_cached(&self.ctx.cache).await {
+            Some(guild) => guild
+                .roles
+                .values()
+                .map(|role| RoleInfo {
+                    id: role.id,
+                    name: role.name.clone(),
+                    managed: role.managed,
+                })
+                .collect(),
+            None => {
+                log::error!("Could not get guild");
+                Vec::new()
+            }
+        }
+    }
+
+    async fn create_role(
+        &self,
+        guild_id: GuildId,
+        name: &str,
+        hoist: bool,
+        mentionable: bool,
+    ) -> CommandResult<RoleInfo> {
+        let role = guild_id
+            .create_role(&self.ctx.http, |r| {
+                r.name(name).hoist(hoist).mentionable(mentionable)
+            })
+            .await?;
+        Ok(RoleInfo {
+            id: role.id,
+            name: role.name,
+            managed: role.managed,
+        })
+    }
+
+    async fn member_roles(&self, guild_id: GuildId, user_id: UserId) -> CommandResult<Vec<RoleId>> {
+        let member = guild_id.member(&self.ctx, user_id).await?;
+        Ok(member.roles)
+    }
+
+    async fn add_role(&self, guild_id: GuildId, user_id: UserId, role_id: RoleId) -> CommandResult {
+        self.ctx
+            .http
+            .add_member_role(guild_id.0, user_id.0, role_id.0)
+            .await?;
+        Ok(())
+    }
+
+    async fn remove_role(
+        &self,
+        guild_id: GuildId,
+        user_id: UserId,
+        role_id: RoleId,
+    ) -> CommandResult {
+        self.ctx
+            .http
+            .remove_member_role(guild_id.0, user_id.0, role_id.0)
+            .await?;
+        Ok(())
+    }
+}
+
+/// Voice through songbird with the audio coming from Lavalink.
+pub struct LavalinkVoice {
+    ctx: Context,
+}
+
+impl LavalinkVoice {
+    pub fn new(ctx: &Context) -> Self {
+        LavalinkVoice { ctx: ctx.clone() }
+    }
+
+    async fn lavalink(&self) -> LavalinkClient {
+        let data = self.ctx.data.read().await;
+        data.get::<LavalinkKey>().unwrap().clone()
+    }
+
+    async fn players(&self) -> Players {
+        crate::music::get(&self.ctx).await
+    }
+
+    async fn songbird(&self) -> Arc<songbird::Songbird> {
+        songbird::get(&self.ctx)
+            .await
+            .expect("Couldn't get Songbird voice client")
+    }
+}
+
+#[async_trait]
+impl VoiceBackend for LavalinkVoice {
+    async fn join(
+        &self,
+        guild_id: GuildId,
+        channel_id: ChannelId,
+        text_channel_id: ChannelId,
+    ) -> CommandResult {
+        let (_, handler) = self
+            .songbird()
+            .await
+            .join_gateway(guild_id, channel_id)
+            .await;
+        let connection_info = handler?;
+        self.lavalink()
+            .await
+            .create_session_with_songbird(&connection_info)
+            .await?;
+        self.players().await.update(guild_id, |player| {
+            player.text_channel = Some(text_channel_id)
+        });
+        Ok(())
+    }
+
+    async fn is_connected(&self, guild_id: GuildId) -> bool {
+        self.songbird().await.get(guild_id).is_some()
+    }
+
+    async fn current_channel(&self, guild_id: GuildId) -> Option<ChannelId> {
+        let call = self.songbird().await.get(guild_id)?;
+        let channel_id = call.lock().await.current_channel();
+        channel_id.map(|channel_id| ChannelId(channel_id.0))
+    }
+
+    async fn leave(&self, guild_id: GuildId) -> CommandResult {
+        self.songbird().await.remove(guild_id).await?;
+        self.lavalink().await.destroy(guild_id).await?;
+        self.players().await.remove(guild_id);
+        Ok(())
+    }
+
+    async fn search(&self, query: &str) -> CommandResult<Vec<Track>> {
+        let tracks = self.lavalink().await.auto_search_tracks(query).await?;
+        Ok(tracks.tracks.into_iter().map(Track::from).collect())
+    }
+
+    async fn queue_len(&self, guild_id: GuildId) -> usize {
+        self.lavalink()
+            .await
+            .nodes()
+            .await
+            .get(guild_id.as_u64())
+            .map_or(0, |node| node.queue.len())
+    }
+
+    async fn queue(&self, guild_id: GuildId) -> Vec<QueuedTrack> {
+        self.lavalink()
+            .await
+            .nodes()
+            .await
+            .get(guild_id.as_u64())
+            .map(|node| node.queue.iter().map(QueuedTrack::from).collect())
+            .unwrap_or_default()
+    }
+
+    async fn position(&self, guild_id: GuildId) -> u64 {
+        self.players().await.get(guild_id).position
+    }
+
+    async fn enqueue(&self, guild_id: GuildId, track: &Track, requester: UserId) -> CommandResult {
+        self.lavalink()
+            .await
+            .play(guild_id, track.to_lavalink())
+            .requester(requester)
+            .queue()
+            .await?;
+        Ok(())
+    }
+
+    async fn reorder_upcoming(&self, guild_id: GuildId, order: &[usize]) {
+        let nodes = self.lavalink().await.nodes().await;
+        if let Some(mut node) = nodes.get_mut(guild_id.as_u64()) {
+            if node.queue.is_empty() {
+                return;
+            }
+            let mut upcoming: Vec<_> = node.queue.split_off(1).into_iter().map(Some).collect();
+            let reordered: Vec<_> = order
+                .iter()
+                .filter_map(|&i| upcoming.get_mut(i).and_then(Option::take))
+                .collect();
+            node.queue.extend(reordered);
+        }
+    }
+
+    async fn skip(&self, guild_id: GuildId) -> Option<Track> {
+        self.lavalink()
+            .await
+            .skip(guild_id)
+            .await
+            .map(|queued| Track::from(queued.track))
+    }
+
+    async fn stop(&self, guild_id: GuildId) -> CommandResult {
+        self.lavalink().await.stop(guild_id).await?;
+        Ok(())
+    }
+
+    async fn now_playing(&self, guild_id: GuildId) -> Option<Track> {
+        self.lavalink()
+            .await
+            .nodes()
+            .await
+            .get(guild_id.as_u64())
+            .and_then(|node| {
+                node.now_playing
+                    .as_ref()
+                    .map(|queued| Track::from(queued.track.clone()))
+            })
+    }
+
+    async fn loop_mode(&self, guild_id: GuildId) -> LoopMode {
+        self.players().await.get(guild_id).loop_mode
+    }
+
+    async fn set_loop_mode(&self, guild_id: GuildId, mode: LoopMode) {
+        self.players()
+            .await
+            .update(guild_id, |player| player.loop_mode = mode);
+    }
+
+    async fn is_paused(&self, guild_id: GuildId) -> bool {
+        self.players().await.get(guild_id).paused
+    }
+
+    async fn set_paused(&self, guild_id: GuildId, paused: bool) -> CommandResult {
+        self.lavalink().await.set_pause(guild_id, paused).await?;
+        self.players()
+            .await
+            .update(guild_id, |player| player.paused = paused);
+        Ok(())
+    }
+
+    async fn seek(&self, guild_id: GuildId, position: u64) -> CommandResult {
+        self.lavalink()
+            .await
+            .seek(guild_id, Duration::from_millis(position))
+            .await?;
+        self.players()
+            .await
+            .update(guild_id, |player| player.position = position);
+        Ok(())
+    }
+
+    async fn set_volume(&self, guild_id: GuildId, volume: u16) -> CommandResult {
+        self.lavalink().await.volume(guild_id, volume).await?;
+        Ok(())
+    }
+}
+
+impl From<model::Track> for Track {
+    fn from(track: model::Track) -> Self {
+        let info = track.info.unwrap_or_else(|| model::Info {
+            identifier: String::new(),
+            is_seekable: false,
+            author: String::new(),
+            length: 0,
+            is_stream: false,
+            position: 0,
+            title: String::from("Unknown"),
+            uri: String::new(),
+        });
+        Track {
+            encoded: track.track,
+            identifier: info.identifier,
+            title: info.title,
+            author: info.author,
+            uri: info.uri,
+            length: info.length,
+            is_stream: info.is_stream,
+            is_seekable: info.is_seekable,
+        }
+    }
+}
+
+impl From<&model::TrackQueue> for QueuedTrack {
+    fn from(queued: &model::TrackQueue) -> Self {
+        QueuedTrack {
+            track: Track::from(queued.track.clone()),
+            requester: queued.requester.map(|id| UserId(id.0)),
+        }
+    }
+}
+
+impl Track {
+    pub fn to_lavalink(&self) -> model::Track {
+        model::Track {
+            track: self.encoded.clone(),
+            info: Some(model::Info {
+                identifier: self.identifier.clone(),
+                is_seekable: self.is_seekable,
+                author: self.author.clone(),
+                length: self.length,
+                is_stream: self.is_stream,
+                position: 0,
+                title: self.title.clone(),
+                uri: self.uri.clone(),
+            }),
+        }
+    }
+}
+
+/// The real Zandronum servers.
+pub struct DoomServers {
+    http: Arc<Http>,
+    servers: Servers,
+}
+
+#[async_trait]
+impl ServerRegistry for DoomServers {
+    async fn host(
+        &self,
+        config: &DoomConfig,
+        iwad_path: &str,
+        url: &str,
+        origin: Origin,
+    ) -> Result<String, HostError> {
+        let wads = download_wads(url, &config.idgames_mirror)
+            .await
+            .map_err(HostError::Download)?;
+        let server = doom::host(
+            self.http.clone(),
+            &self.servers,
+            config,
+            iwad_path,
+            &wads,
+            origin,
+        )
+        .await
+        .map_err(HostError::Launch)?;
+        Ok(server.name)
+    }
+
+    async fn hosted_by(&self, user_id: UserId) -> Option<ServerSummary> {
+        self.servers.find_by_host(user_id).await.map(summary)
+    }
+
+    async fn in_channel(&self, channel_id: ChannelId) -> Option<ServerSummary> {
+        self.servers.find_by_channel(channel_id).await.map(summary)
+    }
+
+    async fn extend(&self, id: u32, duration: Duration, latest: Instant) -> Option<Instant> {
+        self.servers.extend(id, duration, latest).await
+    }
+
+    async fn console(&self, id: u32, command: &str) -> bool {
+        self.servers.console(id, command).await
+    }
+}
+
+/// Schedules and history kept in the database. Schedules start waiting for
+/// their time as soon as they are added.
+pub struct DoomDatabase {
+    http: Arc<Http>,
+    data: Arc<RwLock<TypeMap>>,
+    db: sled::Db,
+}
+
+#[async_trait]
+impl DoomRecords for DoomDatabase {
+    async fn add_schedule(
+        &self,
+        origin: Origin,
+        time: DateTime<Utc>,
+        iwad: String,
+        url: String,
+    ) -> CommandResult<Schedule> {
+        let schedule = schedule::add(&self.db, origin, time, iwad, url)?;
+        schedule::spawn(self.http.clone(), self.data.clone(), schedule.clone());
+        Ok(schedule)
+    }
+
+    async fn schedules(&self) -> CommandResult<Vec<Schedule>> {
+        Ok(schedule::list(&self.db)?)
+    }
+
+    async fn schedule(&self, id: u64) -> CommandResult<Option<Schedule>> {
+        Ok(schedule::get(&self.db, id)?)
+    }
+
+    async fn remove_schedule(&self, id: u64) -> CommandResult {
+        schedule::remove(&self.db, id)?;
+        Ok(())
+    }
+
+    async fn recent(
+        &self,
+        guild_id: Option<GuildId>,
+        limit: usize,
+    ) -> CommandResult<Vec<HostRecord>> {
+        Ok(history::recent(&self.db, guild_id, limit)?)
+    }
+
+    async fn top(
+        &self,
+        guild_id: Option<GuildId>,
+        limit: usize,
+    ) -> CommandResult<Vec<(String, usize)>> {
+        Ok(history::top(&self.db, guild_id, limit)?)
+    }
+}
+
+fn summary(server: doom::server::ServerInfo) -> ServerSummary {
+    ServerSummary {
+        id: server.id,
+        has_console: server.has_console(),
+        started: server.started,
+        deadline: server.deadline,
+        name: server.name,
+        maps: server.maps,
+    }
+}
+
+/// Playlists kept in the database.
+pub struct SavedPlaylists {
+    db: sled::Db,
+}
+
+#[async_trait]
+impl PlaylistStore for SavedPlaylists {
+    async fn get(&self, owner: Owner, name: &str) -> CommandResult<Option<Playlist>> {
+        Ok(playlists::get(&self.db, owner, name)?)
+    }
+
+    async fn save(&self, owner: Owner, playlist: &Playlist) -> CommandResult {
+        playlists::save(&self.db, owner, playlist)?;
+        Ok(())
+    }
+
+    async fn delete(&self, owner: Owner, name: &str) -> CommandResult {
+        playlists::delete(&self.db, owner, name)?;
+        Ok(())
+    }
+
+    async fn list(&self, owner: Owner) -> CommandResult<Vec<Playlist>> {
+        Ok(playlists::list(&self.db, owner)?)
+    }
+}
@@ -0,0 +1,1069 @@
+use super::{DoomRecords, HostError, Invocation, Messenger, ServerRegistry, ServerSummary};
+use crate::{
+    config::doom::DoomConfig,
+    doom::{self, schedule, Origin},
+    error::CommandError,
+    settings::GuildSettings,
+    util::{fit_lines, MAX_MESSAGE_LENGTH},
+};
+use chrono::{DateTime, FixedOffset, Utc};
+use serenity::framework::standard::CommandResult;
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+pub const NOT_CONFIGURED: &str = "Doom hosting is not configured";
+pub const NO_SERVER: &str = "There is no server running from this channel";
+/// How long after starting a server can be kept running by extending it, and
+/// the upper bound for the slash command's `minutes`.
+pub const MAX_EXTEND_MINUTES: u64 = 24 * 60;
+/// How many servers `history` and `top` show.
+const HISTORY_LENGTH: usize = 10;
+
+/// The Doom commands that manage servers.
+pub struct DoomService {
+    servers: Arc<dyn ServerRegistry>,
+    records: Arc<dyn DoomRecords>,
+}
+
+impl DoomService {
+    pub fn new(servers: Arc<dyn ServerRegistry>, records: Arc<dyn DoomRecords>) -> Self {
+        DoomService { servers, records }
+    }
+
+    /// Hosts a server for a preset, or an IWAD and URL.
+    pub async fn host(
+        &self,
+        messenger: &dyn Messenger,
+        invocation: &Invocation,
+        config: Option<&DoomConfig>,
+        settings: &GuildSettings,
+        target: Option<&str>,
+        url: Option<&str>,
+    ) -> CommandResult {
+        let config = match config {
+            Some(config) => config,
+            None => return Err(CommandError::user(NOT_CONFIGURED).into()),
+        };
+        let (iwad, url) =
+            resolve_target(config, settings, target, url).map_err(CommandError::user)?;
+        let iwad =
+            doom::resolve_iwad(config, &iwad).ok_or_else(|| CommandError::user("Invalid IWAD"))?;
+
+        match self
+            .servers
+            .host(config, &iwad, &url, origin(invocation))
+            .await
+        {
+            Ok(name) => {
+                messenger
+                    .say(&format!("Created Zandronum server \"{}\", have fun!", name))
+                    .await
+            }
+            Err(HostError::Download(e)) => {
+                log::warn!("Could not download {}: {:#}", url, e);
+                Err(CommandError::user("Could not download the wad").into())
+            }
+            Err(HostError::Launch(e)) => Err(CommandError::internal(e).into()),
+        }
+    }
+
+    /// Pushes back the shutdown of the server the invoking user is hosting.
+    pub async fn extend(
+        &self,
+        messenger: &dyn Messenger,
+        invocation: &Invocation,
+        config: Option<&DoomConfig>,
+        minutes: Option<u64>,
+    ) -> CommandResult {
+        let config = match config {
+            Some(config) => config,
+            None => return Err(CommandError::user(NOT_CONFIGURED).into()),
+        };
+        // A server can't be extended by more than it is allowed to run for.
+        let longest = (config.timeout / 60).clamp(1, MAX_EXTEND_MINUTES);
+        let duration = match minutes {
+            Some(0) => return Err(CommandError::user("Must extend by at least 1 minute").into()),
+            Some(minutes) if minutes > longest => {
+                return Err(CommandError::user(format!(
+                    "Servers can be extended by at most {} minutes at a time",
+                    longest
+                ))
+                .into())
+            }
+            Some(minutes) => Duration::from_secs(minutes * 60),
+            None => Duration::from_secs(config.extend_time),
+        };
+
+        let server = match self.servers.hosted_by(invocation.user_id).await {
+            Some(server) => server,
+            None => return Err(CommandError::user("You are not hosting a server").into()),
+        };
+
+        // Otherwise extending over and over would keep it running forever.
+        let latest = server.started + Duration::from_secs(MAX_EXTEND_MINUTES * 60);
+        if server.deadline >= latest {
+            return Err(CommandError::user(format!(
+                "\"{}\" can't be extended any further",
+                server.name
+            ))
+            .into());
+        }
+
+        if let Some(deadline) = self.servers.extend(server.id, duration, latest).await {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            messenger
+                .say(&format!(
+                    "Extended \"{}\", it will shut down in {} minutes",
+                    server.name,
+                    remaining.as_secs() / 60
+                ))
+                .await?;
+        }
+        Ok(())
+    }
+
+    pub async fn maps(&self, messenger: &dyn Messenger, invocation: &Invocation) -> CommandResult {
+        let server = match self.servers.in_channel(invocation.channel_id).await {
+            Some(server) => server,
+            None => return Err(CommandError::user(NO_SERVER).into()),
+        };
+
+        if server.maps.is_empty() {
+            messenger
+                .say("The maps for this server are not known")
+                .await
+        } else {
+            messenger
+                .say(&format!(
+                    "Maps on \"{}\": {}",
+                    server.name,
+                    server.maps.join(", ")
+                ))
+                .await
+        }
+    }
+
+    /// Votes on changing the map of the server hosted in the channel.
+    pub async fn vote_map(
+        &self,
+        messenger: &dyn Messenger,
+        invocation: &Invocation,
+        config: Option<&DoomConfig>,
+        name: Option<&str>,
+    ) -> CommandResult {
+        let name = name.ok_or_else(|| CommandError::user("Must provide a map"))?;
+        let config = config.ok_or_else(|| CommandError::user(NOT_CONFIGURED))?;
+        let server = self.channel_server(invocation).await?;
+
+        let map = server
+            .maps
+            .iter()
+            .find(|map| map.eq_ignore_ascii_case(name))
+            .cloned()
+            .ok_or_else(|| {
+                CommandError::user(format!("\"{}\" is not a map on this server", name))
+            })?;
+
+        self.run_vote(
+            messenger,
+            config,
+            &server,
+            &format!("Change the map to {}?", map),
+            &format!("map {}", map),
+            &format!("Changing map to {}", map),
+        )
+        .await
+    }
+
+    /// Votes on skipping to the next map of the server hosted in the channel.
+    pub async fn next_map(
+        &self,
+        messenger: &dyn Messenger,
+        invocation: &Invocation,
+        config: Option<&DoomConfig>,
+    ) -> CommandResult {
+        let config = config.ok_or_else(|| CommandError::user(NOT_CONFIGURED))?;
+        let server = self.channel_server(invocation).await?;
+
+        self.run_vote(
+            messenger,
+            config,
+            &server,
+            "Skip to the next map?",
+            "nextmap",
+            "Skipping to the next map",
+        )
+        .await
+    }
+
+    /// Books a server for a preset, or an IWAD and URL, at `time`.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn schedule(
+        &self,
+        messenger: &dyn Messenger,
+        invocation: &Invocation,
+        config: Option<&DoomConfig>,
+        settings: &GuildSettings,
+        time: Option<&str>,
+        target: Option<&str>,
+        url: Option<&str>,
+    ) -> CommandResult {
+        let config = config.ok_or_else(|| CommandError::user(NOT_CONFIGURED))?;
+        let offset = offset(config);
+
+        let time = time.ok_or_else(|| CommandError::user("Must provide a time"))?;
+        let now = Utc::now();
+        let time = match schedule::parse_time(time, now, offset) {
+            Some(time) if time > now => time,
+            _ => {
+                return Err(CommandError::user(
+                    "Could not understand the time, or it is in the past",
+                )
+                .into())
+            }
+        };
+        let (iwad, url) =
+            resolve_target(config, settings, target, url).map_err(CommandError::user)?;
+
+        let schedule = self
+            .records
+            .add_schedule(origin(invocation), time, iwad, url)
+            .await?;
+        messenger
+            .say(&format!(
+                "Scheduled server #{} for {}",
+                schedule.id,
+                format_time(schedule.time, offset)
+            ))
+            .await
+    }
+
+    /// Lists the servers booked in the channel.
+    pub async fn schedules(
+        &self,
+        messenger: &dyn Messenger,
+        invocation: &Invocation,
+        config: Option<&DoomConfig>,
+    ) -> CommandResult {
+        let config = config.ok_or_else(|| CommandError::user(NOT_CONFIGURED))?;
+        let offset = offset(config);
+
+        let lines: Vec<String> = self
+            .records
+            .schedules()
+            .await?
+            .iter()
+            .filter(|schedule| schedule.channel_id == invocation.channel_id)
+            .map(|schedule| {
+                format!(
+                    "#{}: {} on {} ({}), booked by <@{}>",
+                    schedule.id,
+                    schedule.url,
+                    schedule.iwad,
+                    format_time(schedule.time, offset),
+                    schedule.host
+                )
+            })
+            .collect();
+
+        if lines.is_empty() {
+            messenger.say("No servers are scheduled").await
+        } else {
+            // Soonest first, so the ones cut off are the furthest away.
+            messenger
+                .say_quietly(&fit_lines(&lines, MAX_MESSAGE_LENGTH))
+                .await
+        }
+    }
+
+    /// Cancels a server the invoking user booked.
+    pub async fn cancel(
+        &self,
+        messenger: &dyn Messenger,
+        invocation: &Invocation,
+        id: Option<u64>,
+    ) -> CommandResult {
+        let id = id.ok_or_else(|| CommandError::user("Must provide the schedule's id"))?;
+
+        match self.records.schedule(id).await? {
+            Some(schedule) if schedule.host == invocation.user_id => {
+                self.records.remove_schedule(id).await?;
+                messenger
+                    .say(&format!("Cancelled scheduled server #{}", id))
+                    .await
+            }
+            Some(_) => {
+                Err(CommandError::user("Only the person who booked it can cancel it").into())
+            }
+            None => Err(CommandError::user("Could not find that schedule").into()),
+        }
+    }
+
+    /// Lists the servers hosted recently in the guild.
+    pub async fn history(
+        &self,
+        messenger: &dyn Messenger,
+        invocation: &Invocation,
+    ) -> CommandResult {
+        let records = self
+            .records
+            .recent(invocation.guild_id, HISTORY_LENGTH)
+            .await?;
+        if records.is_empty() {
+            return messenger.say("No servers have been hosted yet").await;
+        }
+
+        let lines: Vec<String> = records
+            .iter()
+            .map(|record| {
+                format!(
+                    "{}: {} ({}) hosted by <@{}> for {} minutes, {}, peak of {} players",
+                    record.started.format("%Y-%m-%d"),
+                    record.wads.join(", "),
+                    record.iwad,
+                    record.host,
+                    record.duration / 60,
+                    record.exit_reason,
+                    record.peak_players
+                )
+            })
+            .collect();
+        messenger.say_quietly(&lines.join("\n")).await
+    }
+
+    /// Lists the WADs hosted most often in the guild.
+    pub async fn top(&self, messenger: &dyn Messenger, invocation: &Invocation) -> CommandResult {
+        let wads = self
+            .records
+            .top(invocation.guild_id, HISTORY_LENGTH)
+            .await?;
+        if wads.is_empty() {
+            return messenger.say("No servers have been hosted yet").await;
+        }
+
+        let lines: Vec<String> = wads
+            .iter()
+            .enumerate()
+            .map(|(i, (wad, count))| format!("{}. {} ({} times)", i + 1, wad, count))
+            .collect();
+        // WAD names come from whatever was uploaded, so they can't ping anyone.
+        messenger.say_quietly(&lines.join("\n")).await
+    }
+
+    async fn channel_server(&self, invocation: &Invocation) -> Result<ServerSummary, CommandError> {
+        self.servers
+            .in_channel(invocation.channel_id)
+            .await
+            .ok_or_else(|| CommandError::user(NO_SERVER))
+    }
+
+    /// Runs `command` on the server if more people vote for it than against.
+    async fn run_vote(
+        &self,
+        messenger: &dyn Messenger,
+        config: &DoomConfig,
+        server: &ServerSummary,
+        question: &str,
+        command: &str,
+        announcement: &str,
+    ) -> CommandResult {
+        // No point asking if the result can't be carried out.
+        if !server.has_console {
+            return Err(CommandError::user(format!(
+                "\"{}\" can't be controlled from here, so there is nothing to vote on",
+                server.name
+            ))
+            .into());
+        }
+
+        let (yes, no) = messenger.poll(question, config.vote_time).await?;
+        if yes <= no {
+            messenger
+                .say(&format!("Vote failed ({} to {})", yes, no))
+                .await
+        } else if self.servers.console(server.id, command).await {
+            messenger.say(announcement).await
+        } else {
+            messenger
+                .say(&format!("Could not reach \"{}\"", server.name))
+                .await
+        }
+    }
+}
+
+fn offset(config: &DoomConfig) -> FixedOffset {
+    FixedOffset::east_opt(config.utc_offset * 3600).unwrap_or_else(|| FixedOffset::east(0))
+}
+
+fn format_time(time: DateTime<Utc>, offset: FixedOffset) -> String {
+    time.with_timezone(&offset)
+        .format("%Y-%m-%d %H:%M %:z")
+        .to_string()
+}
+
+pub fn origin(invocation: &Invocation) -> Origin {
+    Origin {
+        host: invocation.user_id,
+        guild_id: invocation.guild_id,
+        channel_id: invocation.channel_id,
+    }
+}
+
+/// Looks up either a preset name or an IWAD and URL. The guild's presets
+/// take priority over the ones in the config.
+pub fn resolve_target(
+    config: &DoomConfig,
+    settings: &GuildSettings,
+    target: Option<&str>,
+    url: Option<&str>,
+) -> Result<(String, String), &'static str> {
+    let target = target.ok_or("Must provide the IWAD")?;
+
+    if let Some(preset) = settings
+        .presets
+        .get(target)
+        .or_else(|| config.presets.get(target))
+    {
+        return Ok((preset.iwad.clone(), preset.url.clone()));
+    }
+
+    if doom::resolve_iwad(config, target).is_none() {
+        return Err("Invalid IWAD");
+    }
+    let url = url.ok_or("Must provide a url")?;
+    Ok((target.to_string(), url.to_string()))
+}
+
+/// Preset and IWAD names that start with `partial`, for autocompletion. The
+/// guild's presets come first, like when they are looked up.
+pub fn matching_targets(
+    config: &DoomConfig,
+    settings: &GuildSettings,
+    partial: &str,
+) -> Vec<String> {
+    let mut presets: Vec<&String> = config.presets.keys().collect();
+    presets.sort();
+
+    let partial = partial.to_lowercase();
+    let mut names: Vec<String> = Vec::new();
+    for name in settings
+        .presets
+        .keys()
+        .chain(presets)
+        .map(String::as_str)
+        .chain(doom::IWADS.iter().copied())
+    {
+        if name.to_lowercase().starts_with(&partial) && !names.iter().any(|n| n == name) {
+            names.push(name.to_string());
+        }
+    }
+    names
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        config::doom::Preset,
+        doom::{history::HostRecord, server::ExitReason},
+        services::fakes::{self, FakeMessenger, FakeRecords, FakeServers},
+    };
+    use serenity::model::id::UserId;
+
+    fn config() -> DoomConfig {
+        let mut config = DoomConfig::default();
+        config.iwads.doom2 = String::from("/iwads/doom2.wad");
+        config.presets.insert(
+            String::from("scythe"),
+            Preset {
+                iwad: String::from("doom2"),
+                url: String::from("https://example.com/scythe.zip"),
+            },
+        );
+        config
+    }
+
+    #[test]
+    fn resolves_iwad_and_url() {
+        let target = resolve_target(
+            &config(),
+            &GuildSettings::default(),
+            Some("doom2"),
+            Some("https://example.com/a.zip"),
+        );
+        assert_eq!(
+            target,
+            Ok((
+                String::from("doom2"),
+                String::from("https://example.com/a.zip")
+            ))
+        );
+    }
+
+    #[test]
+    fn guild_presets_come_first() {
+        let mut settings = GuildSettings::default();
+        settings.presets.insert(
+            String::from("scythe"),
+            Preset {
+                iwad: String::from("doom2"),
+                url: String::from("https://example.com/scythe2.zip"),
+            },
+        );
+
+        let target = resolve_target(&config(), &settings, Some("scythe"), None);
+        assert_eq!(
+            target,
+            Ok((
+                String::from("doom2"),
+                String::from("https://example.com/scythe2.zip")
+            ))
+        );
+    }
+
+    #[test]
+    fn target_errors() {
+        let settings = GuildSettings::default();
+        assert_eq!(
+            resolve_target(&config(), &settings, None, None),
+            Err("Must provide the IWAD")
+        );
+        assert_eq!(
+            resolve_target(&config(), &settings, Some("quake"), None),
+            Err("Invalid IWAD")
+        );
+        assert_eq!(
+            resolve_target(&config(), &settings, Some("doom2"), None),
+            Err("Must provide a url")
+        );
+    }
+
+    #[test]
+    fn matches_presets_then_iwads() {
+        let mut settings = GuildSettings::default();
+        settings.presets.insert(
+            String::from("doomworld"),
+            Preset {
+                iwad: String::from("doom2"),
+                url: String::from("https://example.com/dw.zip"),
+            },
+        );
+
+        assert_eq!(
+            matching_targets(&config(), &settings, "DO"),
+            vec!["doomworld", "doom", "doom2"]
+        );
+        assert_eq!(
+            matching_targets(&config(), &settings, ""),
+            vec!["doomworld", "scythe", "doom", "doom2", "tnt", "plutonia"]
+        );
+    }
+
+    #[tokio::test]
+    async fn host_without_config() {
+        let (servers, messenger) = (FakeServers::new(), FakeMessenger::new());
+
+        let result = DoomService::new(servers, FakeRecords::new())
+            .host(
+                &messenger,
+                &fakes::invocation(),
+                None,
+                &GuildSettings::default(),
+                Some("scythe"),
+                None,
+            )
+            .await;
+
+        assert_eq!(fakes::user_error(result), NOT_CONFIGURED);
+    }
+
+    #[tokio::test]
+    async fn host_a_preset() {
+        let (servers, messenger) = (FakeServers::new(), FakeMessenger::new());
+        let config = config();
+
+        DoomService::new(servers.clone(), FakeRecords::new())
+            .host(
+                &messenger,
+                &fakes::invocation(),
+                Some(&config),
+                &GuildSettings::default(),
+                Some("scythe"),
+                None,
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(
+            messenger.replies(),
+            vec!["Created Zandronum server \"cantdrown (scythe.zip)\", have fun!"]
+        );
+        assert_eq!(
+            servers.hosted(),
+            vec![(
+                String::from("/iwads/doom2.wad"),
+                String::from("https://example.com/scythe.zip")
+            )]
+        );
+    }
+
+    #[tokio::test]
+    async fn host_reports_download_failures() {
+        let (servers, messenger) = (FakeServers::new(), FakeMessenger::new());
+        servers.fail_downloads();
+        let config = config();
+
+        let result = DoomService::new(servers, FakeRecords::new())
+            .host(
+                &messenger,
+                &fakes::invocation(),
+                Some(&config),
+                &GuildSettings::default(),
+                Some("scythe"),
+                None,
+            )
+            .await;
+
+        assert_eq!(fakes::user_error(result), "Could not download the wad");
+    }
+
+    #[tokio::test]
+    async fn extend_requires_a_server() {
+        let (servers, messenger) = (FakeServers::new(), FakeMessenger::new());
+        let config = config();
+
+        let result = DoomService::new(servers, FakeRecords::new())
+            .extend(&messenger, &fakes::invocation(), Some(&config), None)
+            .await;
+
+        assert_eq!(fakes::user_error(result), "You are not hosting a server");
+    }
+
+    #[tokio::test]
+    async fn extend_pushes_back_the_deadline() {
+        let (servers, messenger) = (FakeServers::new(), FakeMessenger::new());
+        servers.add_server("cantdrown", fakes::USER, fakes::CHANNEL, &[]);
+        let config = config();
+
+        DoomService::new(servers, FakeRecords::new())
+            .extend(&messenger, &fakes::invocation(), Some(&config), Some(45))
+            .await
+            .unwrap();
+
+        let replies = messenger.replies();
+        assert_eq!(replies.len(), 1);
+        // A little time passes between extending and replying.
+        assert!(
+            replies[0] == "Extended \"cantdrown\", it will shut down in 45 minutes"
+                || replies[0] == "Extended \"cantdrown\", it will shut down in 44 minutes"
+        );
+    }
+
+    #[tokio::test]
+    async fn extend_is_capped() {
+        let (servers, messenger) = (FakeServers::new(), FakeMessenger::new());
+        servers.add_server("cantdrown", fakes::USER, fakes::CHANNEL, &[]);
+        let config = config();
+
+        let result = DoomService::new(servers, FakeRecords::new())
+            .extend(
+                &messenger,
+                &fakes::invocation(),
+                Some(&config),
+                Some(u64::MAX),
+            )
+            .await;
+
+        assert_eq!(
+            fakes::user_error(result),
+            "Servers can be extended by at most 180 minutes at a time"
+        );
+    }
+
+    #[tokio::test]
+    async fn extend_needs_a_minute() {
+        let (servers, messenger) = (FakeServers::new(), FakeMessenger::new());
+        servers.add_server("cantdrown", fakes::USER, fakes::CHANNEL, &[]);
+        let config = config();
+
+        let result = DoomService::new(servers, FakeRecords::new())
+            .extend(&messenger, &fakes::invocation(), Some(&config), Some(0))
+            .await;
+
+        assert_eq!(
+            fakes::user_error(result),
+            "Must extend by at least 1 minute"
+        );
+        assert!(messenger.replies().is_empty());
+    }
+
+    #[tokio::test]
+    async fn short_timeouts_can_still_be_extended() {
+        let (servers, messenger) = (FakeServers::new(), FakeMessenger::new());
+        servers.add_server("cantdrown", fakes::USER, fakes::CHANNEL, &[]);
+        let mut config = config();
+        config.timeout = 30;
+
+        let service = DoomService::new(servers, FakeRecords::new());
+        service
+            .extend(&messenger, &fakes::invocation(), Some(&config), Some(1))
+            .await
+            .unwrap();
+        let result = service
+            .extend(&messenger, &fakes::invocation(), Some(&config), Some(2))
+            .await;
+
+        assert_eq!(
+            fakes::user_error(result),
+            "Servers can be extended by at most 1 minutes at a time"
+        );
+    }
+
+    #[tokio::test]
+    async fn repeated_extensions_are_capped() {
+        let (servers, messenger) = (FakeServers::new(), FakeMessenger::new());
+        servers.add_server("cantdrown", fakes::USER, fakes::CHANNEL, &[]);
+        let config = config();
+        let service = DoomService::new(servers, FakeRecords::new());
+
+        // 180 minutes at a time reaches the cap on the eighth.
+        for _ in 0..8 {
+            service
+                .extend(&messenger, &fakes::invocation(), Some(&config), Some(180))
+                .await
+                .unwrap();
+        }
+        let result = service
+            .extend(&messenger, &fakes::invocation(), Some(&config), Some(180))
+            .await;
+
+        assert_eq!(
+            fakes::user_error(result),
+            "\"cantdrown\" can't be extended any further"
+        );
+    }
+
+    #[tokio::test]
+    async fn maps_lists_the_channels_server() {
+        let (servers, messenger) = (FakeServers::new(), FakeMessenger::new());
+        let service = DoomService::new(servers.clone(), FakeRecords::new());
+
+        let result = service.maps(&messenger, &fakes::invocation()).await;
+        assert_eq!(fakes::user_error(result), NO_SERVER);
+        servers.add_server("cantdrown", fakes::USER, fakes::CHANNEL, &[]);
+        service
+            .maps(&messenger, &fakes::invocation())
+            .await
+            .unwrap();
+        servers.add_server(
+            "cantdrown (av.zip)",
+            fakes::USER,
+            fakes::CHANNEL,
+            &["MAP01", "MAP02"],
+        );
+        service
+            .maps(&messenger, &fakes::invocation())
+            .await
+            .unwrap();
+
+        assert_eq!(
+            messenger.replies(),
+            vec![
+                "The maps for this server are not known",
+                "Maps on \"cantdrown (av.zip)\": MAP01, MAP02",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn map_votes_need_a_known_map() {
+        let (servers, messenger) = (FakeServers::new(), FakeMessenger::new());
+        servers.add_server("cantdrown", fakes::USER, fakes::CHANNEL, &["MAP01"]);
+        let config = config();
+
+        let result = DoomService::new(servers, FakeRecords::new())
+            .vote_map(
+                &messenger,
+                &fakes::invocation(),
+                Some(&config),
+                Some("MAP99"),
+            )
+            .await;
+
+        assert_eq!(
+            fakes::user_error(result),
+            "\"MAP99\" is not a map on this server"
+        );
+    }
+
+    #[tokio::test]
+    async fn passed_votes_change_the_map() {
+        let (servers, messenger) = (FakeServers::new(), FakeMessenger::new());
+        servers.add_server(
+            "cantdrown",
+            fakes::USER,
+            fakes::CHANNEL,
+            &["MAP01", "MAP02"],
+        );
+        messenger.set_votes(2, 1);
+        let config = config();
+
+        DoomService::new(servers.clone(), FakeRecords::new())
+            .vote_map(
+                &messenger,
+                &fakes::invocation(),
+                Some(&config),
+                Some("map02"),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(
+            messenger.replies(),
+            vec!["Change the map to MAP02?", "Changing map to MAP02"]
+        );
+        assert_eq!(servers.commands(), vec![(1, String::from("map MAP02"))]);
+    }
+
+    #[tokio::test]
+    async fn tied_votes_fail() {
+        let (servers, messenger) = (FakeServers::new(), FakeMessenger::new());
+        servers.add_server("cantdrown", fakes::USER, fakes::CHANNEL, &[]);
+        messenger.set_votes(1, 1);
+        let config = config();
+
+        DoomService::new(servers.clone(), FakeRecords::new())
+            .next_map(&messenger, &fakes::invocation(), Some(&config))
+            .await
+            .unwrap();
+
+        assert_eq!(
+            messenger.replies(),
+            vec!["Skip to the next map?", "Vote failed (1 to 1)"]
+        );
+        assert!(servers.commands().is_empty());
+    }
+
+    #[tokio::test]
+    async fn votes_need_a_console() {
+        let (servers, messenger) = (FakeServers::new(), FakeMessenger::new());
+        servers.add_server("cantdrown", fakes::USER, fakes::CHANNEL, &[]);
+        servers.remove_consoles();
+        messenger.set_votes(2, 0);
+        let config = config();
+
+        let result = DoomService::new(servers.clone(), FakeRecords::new())
+            .next_map(&messenger, &fakes::invocation(), Some(&config))
+            .await;
+
+        assert_eq!(
+            fakes::user_error(result),
+            "\"cantdrown\" can't be controlled from here, so there is nothing to vote on"
+        );
+        // The poll was never posted.
+        assert!(messenger.replies().is_empty());
+        assert!(servers.commands().is_empty());
+    }
+
+    #[tokio::test]
+    async fn schedule_books_a_preset() {
+        let (records, messenger) = (FakeRecords::new(), FakeMessenger::new());
+        let service = DoomService::new(FakeServers::new(), records.clone());
+        let config = config();
+
+        let result = service
+            .schedule(
+                &messenger,
+                &fakes::invocation(),
+                Some(&config),
+                &GuildSettings::default(),
+                Some("yesterday"),
+                Some("scythe"),
+                None,
+            )
+            .await;
+        assert_eq!(
+            fakes::user_error(result),
+            "Could not understand the time, or it is in the past"
+        );
+
+        service
+            .schedule(
+                &messenger,
+                &fakes::invocation(),
+                Some(&config),
+                &GuildSettings::default(),
+                Some("+1h"),
+                Some("scythe"),
+                None,
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(records.scheduled(), vec![1]);
+        let replies = messenger.replies();
+        assert_eq!(replies.len(), 1);
+        assert!(replies[0].starts_with("Scheduled server #1 for "));
+    }
+
+    #[tokio::test]
+    async fn only_the_booker_can_cancel() {
+        let (records, messenger) = (FakeRecords::new(), FakeMessenger::new());
+        let service = DoomService::new(FakeServers::new(), records.clone());
+        let mut origin = origin(&fakes::invocation());
+        records
+            .add_schedule(origin, Utc::now(), String::from("doom2"), String::new())
+            .await
+            .unwrap();
+        origin.host = UserId(5);
+        records
+            .add_schedule(origin, Utc::now(), String::from("doom2"), String::new())
+            .await
+            .unwrap();
+
+        let result = service
+            .cancel(&messenger, &fakes::invocation(), Some(2))
+            .await;
+        assert_eq!(
+            fakes::user_error(result),
+            "Only the person who booked it can cancel it"
+        );
+        let result = service
+            .cancel(&messenger, &fakes::invocation(), Some(3))
+            .await;
+        assert_eq!(fakes::user_error(result), "Could not find that schedule");
+        service
+            .cancel(&messenger, &fakes::invocation(), Some(1))
+            .await
+            .unwrap();
+
+        assert_eq!(records.scheduled(), vec![2]);
+        assert_eq!(messenger.replies(), vec!["Cancelled scheduled server #1"]);
+    }
+
+    #[tokio::test]
+    async fn schedules_lists_the_channels_bookings() {
+        let (records, messenger) = (FakeRecords::new(), FakeMessenger::new());
+        let service = DoomService::new(FakeServers::new(), records.clone());
+        let config = config();
+
+        service
+            .schedules(&messenger, &fakes::invocation(), Some(&config))
+            .await
+            .unwrap();
+        let time = DateTime::parse_from_rfc3339("2030-01-02T20:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        records
+            .add_schedule(
+                origin(&fakes::invocation()),
+                time,
+                String::from("doom2"),
+                String::from("https://example.com/av.zip"),
+            )
+            .await
+            .unwrap();
+        service
+            .schedules(&messenger, &fakes::invocation(), Some(&config))
+            .await
+            .unwrap();
+
+        assert_eq!(
+            messenger.replies(),
+            vec![
+                "No servers are scheduled",
+                "#1: https://example.com/av.zip on doom2 (2030-01-02 20:00 +00:00), booked by <@4>",
+            ]
+        );
+        assert_eq!(messenger.quiet_replies().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn long_schedules_fit_in_a_message() {
+        let (records, messenger) = (FakeRecords::new(), FakeMessenger::new());
+        let service = DoomService::new(FakeServers::new(), records.clone());
+        let config = config();
+        let time = DateTime::parse_from_rfc3339("2030-01-02T20:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        for _ in 0..50 {
+            records
+                .add_schedule(
+                    origin(&fakes::invocation()),
+                    time,
+                    String::from("doom2"),
+                    String::from("https://example.com/av.zip"),
+                )
+                .await
+                .unwrap();
+        }
+
+        service
+            .schedules(&messenger, &fakes::invocation(), Some(&config))
+            .await
+            .unwrap();
+
+        let reply = &messenger.replies()[0];
+        assert!(reply.len() <= MAX_MESSAGE_LENGTH);
+        assert!(reply.starts_with("#1: https://example.com/av.zip"));
+        assert!(reply.ends_with("more"));
+    }
+
+    fn record(wads: &[&str]) -> HostRecord {
+        HostRecord {
+            name: String::from("cantdrown"),
+            host: fakes::USER,
+            guild_id: Some(fakes::GUILD),
+            iwad: String::from("doom2"),
+            wads: wads.iter().map(|wad| wad.to_string()).collect(),
+            started: Utc::now(),
+            duration: 3600,
+            exit_reason: ExitReason::Idle,
+            peak_players: 3,
+        }
+    }
+
+    #[tokio::test]
+    async fn top_wads_ping_no_one() {
+        let (records, messenger) = (FakeRecords::new(), FakeMessenger::new());
+        let service = DoomService::new(FakeServers::new(), records.clone());
+
+        service.top(&messenger, &fakes::invocation()).await.unwrap();
+        records.add_record(record(&["@everyone.wad"]));
+        records.add_record(record(&["av.wad", "@everyone.wad"]));
+        service.top(&messenger, &fakes::invocation()).await.unwrap();
+
+        assert_eq!(
+            messenger.replies(),
+            vec![
+                "No servers have been hosted yet",
+                "1. @everyone.wad (2 times)\n2. av.wad (1 times)",
+            ]
+        );
+        assert_eq!(
+            messenger.quiet_replies(),
+            vec!["1. @everyone.wad (2 times)\n2. av.wad (1 times)"]
+        );
+    }
+
+    #[tokio::test]
+    async fn history_lists_the_newest_first() {
+        let (records, messenger) = (FakeRecords::new(), FakeMessenger::new());
+        records.add_record(record(&["scythe.wad"]));
+        records.add_record(record(&["av.wad"]));
+
+        DoomService::new(FakeServers::new(), records)
+            .history(&messenger, &fakes::invocation())
+            .await
+            .unwrap();
+
+        let replies = messenger.quiet_replies();
+        assert_eq!(replies.len(), 1);
+        let lines: Vec<&str> = replies[0].lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].contains(": av.wad (doom2) hosted by <@4> for 60 minutes"));
+        assert!(lines[1].contains(": scythe.wad (doom2)"));
+    }
+}
@@ -0,0 +1,729 @@
+//! In-memory implementations of the service traits for tests.
+
+use super::{
+    DoomRecords, GuildDirectory, HostError, Invocation, LoopMode, Messenger, QueuedTrack, RoleInfo,
+    ServerRegistry, ServerSummary, Track, VoiceBackend,
+};
+use crate::{
+    config::doom::DoomConfig,
+    doom::{history::HostRecord, schedule::Schedule, Origin},
+    error::CommandError,
+    playlists::{Owner, Playlist},
+};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serenity::{
+    framework::standard::CommandResult,
+    model::id::{ChannelId, GuildId, RoleId, UserId},
+};
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+pub const GUILD: GuildId = GuildId(1);
+pub const CHANNEL: ChannelId = ChannelId(2);
+pub const VOICE_CHANNEL: ChannelId = ChannelId(3);
+pub const USER: UserId = UserId(4);
+
+/// A command run by `USER` in `CHANNEL`.
+pub fn invocation() -> Invocation {
+    Invocation {
+        guild_id: Some(GUILD),
+        channel_id: CHANNEL,
+        user_id: USER,
+    }
+}
+
+/// The message of a user error, panicking on anything else.
+pub fn user_error(result: CommandResult) -> String {
+    match result {
+        Err(error) => match error.downcast_ref::<CommandError>() {
+            Some(CommandError::User(message)) => message.clone(),
+            _ => panic!("expected a user error, got {:?}", error),
+        },
+        Ok(()) => panic!("expected a user error, got Ok"),
+    }
+}
+
+pub fn track(title: &str, seconds: u64) -> Track {
+    Track {
+        encoded: format!("encoded:{}", title),
+        identifier: title.to_lowercase(),
+        title: title.to_string(),
+        author: String::from("Someone"),
+        uri: format!("https://example.com/{}", title.to_lowercase()),
+        length: seconds * 1000,
+        is_stream: false,
+        is_seekable: true,
+    }
+}
+
+/// Records everything that is said. Polls are said like any other reply and
+/// end with the votes given to `set_votes`.
+#[derive(Default)]
+pub struct FakeMessenger {
+    replies: Mutex<Vec<String>>,
+    quiet: Mutex<Vec<String>>,
+    votes: Mutex<(usize, usize)>,
+}
+
+impl FakeMessenger {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn replies(&self) -> Vec<String> {
+        self.replies.lock().unwrap().clone()
+    }
+
+    /// The replies that were said without pinging anyone.
+    pub fn quiet_replies(&self) -> Vec<String> {
+        self.quiet.lock().unwrap().clone()
+    }
+
+    pub fn set_votes(&self, yes: usize, no: usize) {
+        *self.votes.lock().unwrap() = (yes, no);
+    }
+}
+
+#[async_trait]
+impl Messenger for FakeMessenger {
+    async fn say(&self, content: &str) -> CommandResult {
+        self.replies.lock().unwrap().push(content.to_string());
+        Ok(())
+    }
+
+    async fn say_quietly(&self, content: &str) -> CommandResult {
+        self.quiet.lock().unwrap().push(content.to_string());
+        self.say(content).await
+    }
+
+    async fn poll(&self, question: &str, _seconds: u64) -> CommandResult<(usize, usize)> {
+        self.say(question).await?;
+        Ok(*self.votes.lock().unwrap())
+    }
+}
+
+/// A single guild, `GUILD`, with its roles and members.
+#[derive(Default)]
+pub struct FakeGuilds {
+    state: Mutex<GuildState>,
+}
+
+#[derive(Default)]
+struct GuildState {
+    voice: HashMap<UserId, ChannelId>,
+    roles: Vec<RoleInfo>,
+    members: HashMap<UserId, HashSet<RoleId>>,
+}
+
+impl FakeGuilds {
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self::default())
+    }
+
+    pub fn put_in_voice(&self, user_id: UserId, channel_id: ChannelId) {
+        self.state.lock().unwrap().voice.insert(user_id, channel_id);
+    }
+
+    pub fn add_guild_role(&self, name: &str, managed: bool) -> RoleId {
+        let mut state = self.state.lock().unwrap();
+        let id = RoleId(100 + state.roles.len() as u64);
+        state.roles.push(RoleInfo {
+            id,
+            name: name.to_string(),
+            managed,
+        });
+        id
+    }
+
+    pub fn has_role(&self, user_id: UserId, role_id: RoleId) -> bool {
+        self.state
+            .lock()
+            .unwrap()
+            .members
+            .get(&user_id)
+            .map_or(false, |roles| roles.contains(&role_id))
+    }
+}
+
+#[async_trait]
+impl GuildDirectory for FakeGuilds {
+    async fn voice_channel(&self, _guild_id: GuildId, user_id: UserId) -> Option<ChannelId> {
+        self.state.lock().unwrap().voice.get(&user_id).copied()
+    }
+
+    async fn roles(&self, _guild_id: GuildId) -> Vec<RoleInfo> {
+        self.state.lock().unwrap().roles.clone()
+    }
+
+    async fn create_role(
+        &self,
+        _guild_id: GuildId,
+        name: &str,
+        _hoist: bool,
+        _mentionable: bool,
+    ) -> CommandResult<RoleInfo> {
+        let id = self.add_guild_role(name, false);
+        Ok(RoleInfo {
+            id,
+            name: name.to_string(),
+            managed: false,
+        })
+    }
+
+    async fn member_roles(
+        &self,
+        _guild_id: GuildId,
+        user_id: UserId,
+    ) -> CommandResult<Vec<RoleId>> {
+        let state = self.state.lock().unwrap();
+        let roles = state.members.get(&user_id).cloned().unwrap_or_default();
+        Ok(roles.into_iter().collect())
+    }
+
+    async fn add_role(
+        &self,
+        _guild_id: GuildId,
+        user_id: UserId,
+        role_id: RoleId,
+    ) -> CommandResult {
+        let mut state = self.state.lock().unwrap();
+        state.members.entry(user_id).or_default().insert(role_id);
+        Ok(())
+    }
+
+    async fn remove_role(
+        &self,
+        _guild_id: GuildId,
+        user_id: UserId,
+        role_id: RoleId,
+    ) -> CommandResult {
+        let mut state = self.state.lock().unwrap();
+        if let Some(roles) = state.members.get_mut(&user_id) {
+            roles.remove(&role_id);
+        }
+        Ok(())
+    }
+}
+
+/// Voice connections and queues without Lavalink. The first queued track is
+/// the one playing.
+#[derive(Default)]
+pub struct FakeVoice {
+    state: Mutex<VoiceState>,
+}
+
+#[derive(Default)]
+struct VoiceState {
+    connections: HashMap<GuildId, ChannelId>,
+    text_channels: HashMap<GuildId, ChannelId>,
+    queues: HashMap<GuildId, VecDeque<QueuedTrack>>,
+    positions: HashMap<GuildId, u64>,
+    paused: HashSet<GuildId>,
+    volumes: HashMap<GuildId, u16>,
+    loop_modes: HashMap<GuildId, LoopMode>,
+    results: HashMap<String, Vec<Track>>,
+}
+
+impl FakeVoice {
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self::default())
+    }
+
+    pub fn connect(&self, guild_id: GuildId) {
+        self.state
+            .lock()
+            .unwrap()
+            .connections
+            .insert(guild_id, VOICE_CHANNEL);
+    }
+
+    pub fn channel(&self, guild_id: GuildId) -> Option<ChannelId> {
+        self.state
+            .lock()
+            .unwrap()
+            .connections
+            .get(&guild_id)
+            .copied()
+    }
+
+    /// Where songs are announced.
+    pub fn text_channel(&self, guild_id: GuildId) -> Option<ChannelId> {
+        self.state
+            .lock()
+            .unwrap()
+            .text_channels
+            .get(&guild_id)
+            .copied()
+    }
+
+    /// Makes `track` one of the results when searching for `query`.
+    pub fn add_result(&self, query: &str, track: Track) {
+        self.state
+            .lock()
+            .unwrap()
+            .results
+            .entry(query.to_string())
+            .or_default()
+            .push(track);
+    }
+
+    /// Titles of the queued tracks, starting with the one playing.
+    pub fn queued(&self, guild_id: GuildId) -> Vec<String> {
+        self.state
+            .lock()
+            .unwrap()
+            .queues
+            .get(&guild_id)
+            .map(|queue| {
+                queue
+                    .iter()
+                    .map(|queued| queued.track.title.clone())
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    pub fn volume(&self, guild_id: GuildId) -> Option<u16> {
+        self.state.lock().unwrap().volumes.get(&guild_id).copied()
+    }
+
+    /// Moves the player `ms` into the current track.
+    pub fn set_position(&self, guild_id: GuildId, ms: u64) {
+        self.state.lock().unwrap().positions.insert(guild_id, ms);
+    }
+}
+
+#[async_trait]
+impl VoiceBackend for FakeVoice {
+    async fn join(
+        &self,
+        guild_id: GuildId,
+        channel_id: ChannelId,
+        text_channel_id: ChannelId,
+    ) -> CommandResult {
+        let mut state = self.state.lock().unwrap();
+        state.connections.insert(guild_id, channel_id);
+        state.text_channels.insert(guild_id, text_channel_id);
+        Ok(())
+    }
+
+    async fn is_connected(&self, guild_id: GuildId) -> bool {
+        self.channel(guild_id).is_some()
+    }
+
+    async fn current_channel(&self, guild_id: GuildId) -> Option<ChannelId> {
+        self.channel(guild_id)
+    }
+
+    async fn leave(&self, guild_id: GuildId) -> CommandResult {
+        let mut state = self.state.lock().unwrap();
+        state.connections.remove(&guild_id);
+        state.text_channels.remove(&guild_id);
+        state.queues.remove(&guild_id);
+        Ok(())
+    }
+
+    async fn search(&self, query: &str) -> CommandResult<Vec<Track>> {
+        let state = self.state.lock().unwrap();
+        Ok(state.results.get(query).cloned().unwrap_or_default())
+    }
+
+    async fn queue_len(&self, guild_id: GuildId) -> usize {
+        self.queued(guild_id).len()
+    }
+
+    async fn queue(&self, guild_id: GuildId) -> Vec<QueuedTrack> {
+        self.state
+            .lock()
+            .unwrap()
+            .queues
+            .get(&guild_id)
+            .map(|queue| queue.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    async fn position(&self, guild_id: GuildId) -> u64 {
+        let state = self.state.lock().unwrap();
+        state.positions.get(&guild_id).copied().unwrap_or_default()
+    }
+
+    async fn enqueue(&self, guild_id: GuildId, track: &Track, requester: UserId) -> CommandResult {
+        self.state
+            .lock()
+            .unwrap()
+            .queues
+            .entry(guild_id)
+            .or_default()
+            .push_back(QueuedTrack {
+                track: track.clone(),
+                requester: Some(requester),
+            });
+        Ok(())
+    }
+
+    async fn reorder_upcoming(&self, guild_id: GuildId, order: &[usize]) {
+        let mut state = self.state.lock().unwrap();
+        if let Some(queue) = state
+            .queues
+            .get_mut(&guild_id)
+            .filter(|queue| !queue.is_empty())
+        {
+            let upcoming: Vec<QueuedTrack> = queue.drain(1..).collect();
+            queue.extend(order.iter().filter_map(|&i| upcoming.get(i).cloned()));
+        }
+    }
+
+    async fn skip(&self, guild_id: GuildId) -> Option<Track> {
+        let mut state = self.state.lock().unwrap();
+        state.positions.remove(&guild_id);
+        state
+            .queues
+            .get_mut(&guild_id)?
+            .pop_front()
+            .map(|queued| queued.track)
+    }
+
+    async fn stop(&self, guild_id: GuildId) -> CommandResult {
+        self.state.lock().unwrap().queues.remove(&guild_id);
+        Ok(())
+    }
+
+    async fn now_playing(&self, guild_id: GuildId) -> Option<Track> {
+        self.state
+            .lock()
+            .unwrap()
+            .queues
+            .get(&guild_id)?
+            .front()
+            .map(|queued| queued.track.clone())
+    }
+
+    async fn loop_mode(&self, guild_id: GuildId) -> LoopMode {
+        let state = self.state.lock().unwrap();
+        state.loop_modes.get(&guild_id).copied().unwrap_or_default()
+    }
+
+    async fn set_loop_mode(&self, guild_id: GuildId, mode: LoopMode) {
+        self.state.lock().unwrap().loop_modes.insert(guild_id, mode);
+    }
+
+    async fn is_paused(&self, guild_id: GuildId) -> bool {
+        self.state.lock().unwrap().paused.contains(&guild_id)
+    }
+
+    async fn set_paused(&self, guild_id: GuildId, paused: bool) -> CommandResult {
+        let mut state = self.state.lock().unwrap();
+        if paused {
+            state.paused.insert(guild_id);
+        } else {
+            state.paused.remove(&guild_id);
+        }
+        Ok(())
+    }
+
+    async fn seek(&self, guild_id: GuildId, position: u64) -> CommandResult {
+        self.set_position(guild_id, position);
+        Ok(())
+    }
+
+    async fn set_volume(&self, guild_id: GuildId, volume: u16) -> CommandResult {
+        self.state.lock().unwrap().volumes.insert(guild_id, volume);
+        Ok(())
+    }
+}
+
+/// Servers that are never actually started.
+#[derive(Default)]
+pub struct FakeServers {
+    state: Mutex<ServerState>,
+}
+
+#[derive(Default)]
+struct ServerState {
+    servers: Vec<FakeServer>,
+    hosted: Vec<(String, String)>,
+    commands: Vec<(u32, String)>,
+    fail_downloads: bool,
+}
+
+struct FakeServer {
+    summary: ServerSummary,
+    host: UserId,
+    channel_id: ChannelId,
+}
+
+impl FakeServers {
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self::default())
+    }
+
+    pub fn add_server(&self, name: &str, host: UserId, channel_id: ChannelId, maps: &[&str]) {
+        let mut state = self.state.lock().unwrap();
+        let id = state.servers.len() as u32 + 1;
+        let now = Instant::now();
+        state.servers.push(FakeServer {
+            summary: ServerSummary {
+                id,
+                name: name.to_string(),
+                maps: maps.iter().map(|map| map.to_string()).collect(),
+                has_console: true,
+                started: now,
+                deadline: now,
+            },
+            host,
+            channel_id,
+        });
+    }
+
+    /// Makes every server unreachable from its console.
+    pub fn remove_consoles(&self) {
+        let mut state = self.state.lock().unwrap();
+        for server in state.servers.iter_mut() {
+            server.summary.has_console = false;
+        }
+    }
+
+    pub fn fail_downloads(&self) {
+        self.state.lock().unwrap().fail_downloads = true;
+    }
+
+    /// The IWAD path and URL of every server that was hosted.
+    pub fn hosted(&self) -> Vec<(String, String)> {
+        self.state.lock().unwrap().hosted.clone()
+    }
+
+    /// Every console command that was run and the server it was run on.
+    pub fn commands(&self) -> Vec<(u32, String)> {
+        self.state.lock().unwrap().commands.clone()
+    }
+}
+
+#[async_trait]
+impl ServerRegistry for FakeServers {
+    async fn host(
+        &self,
+        config: &DoomConfig,
+        iwad_path: &str,
+        url: &str,
+        origin: Origin,
+    ) -> Result<String, HostError> {
+        if self.state.lock().unwrap().fail_downloads {
+            return Err(HostError::Download(anyhow::anyhow!("download failed")));
+        }
+
+        let file = url.rsplit('/').next().unwrap_or(url);
+        let name = format!("{} ({})", config.base_name, file);
+        self.add_server(&name, origin.host, origin.channel_id, &[]);
+        self.state
+            .lock()
+            .unwrap()
+            .hosted
+            .push((iwad_path.to_string(), url.to_string()));
+        Ok(name)
+    }
+
+    async fn hosted_by(&self, user_id: UserId) -> Option<ServerSummary> {
+        let state = self.state.lock().unwrap();
+        state
+            .servers
+            .iter()
+            .rev()
+            .find(|server| server.host == user_id)
+            .map(|server| server.summary.clone())
+    }
+
+    async fn in_channel(&self, channel_id: ChannelId) -> Option<ServerSummary> {
+        let state = self.state.lock().unwrap();
+        state
+            .servers
+            .iter()
+            .rev()
+            .find(|server| server.channel_id == channel_id)
+            .map(|server| server.summary.clone())
+    }
+
+    async fn extend(&self, id: u32, duration: Duration, latest: Instant) -> Option<Instant> {
+        let mut state = self.state.lock().unwrap();
+        let server = state
+            .servers
+            .iter_mut()
+            .find(|server| server.summary.id == id)?;
+        let deadline = &mut server.summary.deadline;
+        *deadline = deadline
+            .checked_add(duration)
+            .map_or(latest, |extended| extended.min(latest));
+        Some(*deadline)
+    }
+
+    async fn console(&self, id: u32, command: &str) -> bool {
+        let mut state = self.state.lock().unwrap();
+        if !state
+            .servers
+            .iter()
+            .any(|server| server.summary.id == id && server.summary.has_console)
+        {
+            return false;
+        }
+        state.commands.push((id, command.to_string()));
+        true
+    }
+}
+
+/// Schedules that never start and history that is filled in by the tests.
+#[derive(Default)]
+pub struct FakeRecords {
+    state: Mutex<RecordState>,
+}
+
+#[derive(Default)]
+struct RecordState {
+    schedules: Vec<Schedule>,
+    history: Vec<HostRecord>,
+}
+
+impl FakeRecords {
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self::default())
+    }
+
+    pub fn add_record(&self, record: HostRecord) {
+        self.state.lock().unwrap().history.push(record);
+    }
+
+    /// Ids of the pending schedules.
+    pub fn scheduled(&self) -> Vec<u64> {
+        let state = self.state.lock().unwrap();
+        state.schedules.iter().map(|schedule| schedule.id).collect()
+    }
+}
+
+#[async_trait]
+impl DoomRecords for FakeRecords {
+    async fn add_schedule(
+        &self,
+        origin: Origin,
+        time: DateTime<Utc>,
+        iwad: String,
+        url: String,
+    ) -> CommandResult<Schedule> {
+        let mut state = self.state.lock().unwrap();
+        let schedule = Schedule {
+            id: state.schedules.len() as u64 + 1,
+            host: origin.host,
+            guild_id: origin.guild_id,
+            channel_id: origin.channel_id,
+            time,
+            iwad,
+            url,
+        };
+        state.schedules.push(schedule.clone());
+        Ok(schedule)
+    }
+
+    async fn schedules(&self) -> CommandResult<Vec<Schedule>> {
+        let mut schedules = self.state.lock().unwrap().schedules.clone();
+        schedules.sort_by_key(|schedule| schedule.time);
+        Ok(schedules)
+    }
+
+    async fn schedule(&self, id: u64) -> CommandResult<Option<Schedule>> {
+        let state = self.state.lock().unwrap();
+        Ok(state
+            .schedules
+            .iter()
+            .find(|schedule| schedule.id == id)
+            .cloned())
+    }
+
+    async fn remove_schedule(&self, id: u64) -> CommandResult {
+        let mut state = self.state.lock().unwrap();
+        state.schedules.retain(|schedule| schedule.id != id);
+        Ok(())
+    }
+
+    async fn recent(
+        &self,
+        guild_id: Option<GuildId>,
+        limit: usize,
+    ) -> CommandResult<Vec<HostRecord>> {
+        let state = self.state.lock().unwrap();
+        Ok(state
+            .history
+            .iter()
+            .rev()
+            .filter(|record| record.guild_id == guild_id)
+            .take(limit)
+            .cloned()
+            .collect())
+    }
+
+    async fn top(
+        &self,
+        guild_id: Option<GuildId>,
+        limit: usize,
+    ) -> CommandResult<Vec<(String, usize)>> {
+        let mut counts: HashMap<String, usize> = HashMap::new();
+        for record in self.recent(guild_id, usize::MAX).await? {
+            for wad in record.wads {
+                *counts.entry(wad).or_default() += 1;
+            }
+        }
+
+        let mut counts: Vec<(String, usize)> = counts.into_iter().collect();
+        counts.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        counts.truncate(limit);
+        Ok(counts)
+    }
+}
+
+/// Playlists kept in memory.
+#[derive(Default)]
+pub struct FakePlaylists {
+    playlists: Mutex<Vec<(Owner, Playlist)>>,
+}
+
+impl FakePlaylists {
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self::default())
+    }
+}
+
+#[async_trait]
+impl PlaylistStore for FakePlaylists {
+    async fn get(&self, owner: Owner, name: &str) -> CommandResult<Option<Playlist>> {
+        let playlists = self.playlists.lock().unwrap();
+        Ok(playlists
+            .iter()
+            .find(|(o, playlist)| *o == owner && playlist.name.eq_ignore_ascii_case(name))
+            .map(|(_, playlist)| playlist.clone()))
+    }
+
+    async fn save(&self, owner: Owner, playlist: &Playlist) -> CommandResult {
+        self.delete(owner, &playlist.name).await?;
+        let mut playlists = self.playlists.lock().unwrap();
+        playlists.push((owner, playlist.clone()));
+        playlists.sort_by_key(|(_, playlist)| playlist.name.to_lowercase());
+        Ok(())
+    }
+
+    async fn delete(&self, owner: Owner, name: &str) -> CommandResult {
+        self.playlists
+            .lock()
+            .unwrap()
+            .retain(|(o, playlist)| *o != owner || !playlist.name.eq_ignore_ascii_case(name));
+        Ok(())
+    }
+
+    async fn list(&self, owner: Owner) -> CommandResult<Vec<Playlist>> {
+        let playlists = self.playlists.lock().unwrap();
+        Ok(playlists
+            .iter()
+            .filter(|(o, _)| *o == owner)
+            .map(|(_, playlist)| playlist.clone())
+            .collect())
+    }
+}
@@ -0,0 +1,318 @@
+//! Command logic that doesn't depend on Discord. Services talk to the outside
+//! world through the traits in this module, which are implemented for
+//! serenity and Lavalink in `discord` and by in-memory fakes in the tests.
+
+pub mod discord;
+pub mod doom;
+pub mod playlist;
+pub mod role;
+pub mod song;
+
+#[cfg(test)]
+mod fakes;
+
+use crate::{
+    doom::{history::HostRecord, schedule::Schedule},
+    error::CommandError,
+    playlists::{Owner, Playlist},
+};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serenity::{
+    framework::standard::CommandResult,
+    model::{
+        channel::Message,
+        id::{ChannelId, GuildId, RoleId, UserId},
+    },
+};
+use std::time::{Duration, Instant};
+
+/// Where a command came from, so prefix and slash commands can share logic.
+#[derive(Clone, Copy)]
+pub struct Invocation {
+    pub guild_id: Option<GuildId>,
+    pub channel_id: ChannelId,
+    pub user_id: UserId,
+}
+
+impl Invocation {
+    /// The guild the command was used in, refusing commands sent in DMs.
+    pub fn guild(&self) -> Result<GuildId, CommandError> {
+        self.guild_id
+            .ok_or_else(|| CommandError::user("This command only works in a server"))
+    }
+}
+
+impl From<&Message> for Invocation {
+    fn from(msg: &Message) -> Self {
+        Invocation {
+            guild_id: msg.guild_id,
+            channel_id: msg.channel_id,
+            user_id: msg.author.id,
+        }
+    }
+}
+
+/// Sends replies back to whoever invoked a command.
+#[async_trait]
+pub trait Messenger: Send + Sync {
+    async fn say(&self, content: &str) -> CommandResult;
+
+    /// Says something without pinging anyone it mentions.
+    async fn say_quietly(&self, content: &str) -> CommandResult;
+
+    /// Asks a yes/no question and returns the yes and no votes once `seconds`
+    /// are up.
+    async fn poll(&self, question: &str, seconds: u64) -> CommandResult<(usize, usize)>;
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct RoleInfo {
+    pub id: RoleId,
+    pub name: String,
+    /// Whether the role belongs to an integration and can't be given out.
+    pub managed: bool,
+}
+
+/// Looks up and changes guilds, their roles and their members.
+#[async_trait]
+pub trait GuildDirectory: Send + Sync {
+    /// The voice channel a member is currently in.
+    async fn voice_channel(&self, guild_id: GuildId, user_id: UserId) -> Option<ChannelId>;
+
+    async fn roles(&self, guild_id: GuildId) -> Vec<RoleInfo>;
+
+    async fn role_by_name(&self, guild_id: GuildId, name: &str) -> Option<RoleInfo> {
+        self.roles(guild_id)
+            .await
+            .into_iter()
+            .find(|role| role.name == name)
+    }
+
+    async fn create_role(
+        &self,
+        guild_id: GuildId,
+        name: &str,
+        hoist: bool,
+        mentionable: bool,
+    ) -> CommandResult<RoleInfo>;
+
+    async fn member_roles(&self, guild_id: GuildId, user_id: UserId) -> CommandResult<Vec<RoleId>>;
+
+    async fn add_role(&self, guild_id: GuildId, user_id: UserId, role_id: RoleId) -> CommandResult;
+
+    async fn remove_role(
+        &self,
+        guild_id: GuildId,
+        user_id: UserId,
+        role_id: RoleId,
+    ) -> CommandResult;
+}
+
+/// A playable track, independent of the Lavalink types.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Track {
+    /// Lavalink's base64 encoding of the track.
+    pub encoded: String,
+    pub identifier: String,
+    pub title: String,
+    pub author: String,
+    pub uri: String,
+    /// Length in milliseconds.
+    pub length: u64,
+    pub is_stream: bool,
+    pub is_seekable: bool,
+}
+
+/// A track in a guild's queue and who asked for it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct QueuedTrack {
+    pub track: Track,
+    pub requester: Option<UserId>,
+}
+
+/// What happens when a track finishes playing.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LoopMode {
+    Off,
+    /// Play the same track again.
+    Track,
+    /// Put the track at the back of the queue.
+    Queue,
+}
+
+impl Default for LoopMode {
+    fn default() -> Self {
+        LoopMode::Off
+    }
+}
+
+impl LoopMode {
+    pub fn parse(input: &str) -> Option<LoopMode> {
+        match input.to_lowercase().as_str() {
+            "off" => Some(LoopMode::Off),
+            "track" | "song" => Some(LoopMode::Track),
+            "queue" => Some(LoopMode::Queue),
+            _ => None,
+        }
+    }
+
+    pub fn describe(self) -> &'static str {
+        match self {
+            LoopMode::Off => "Not looping",
+            LoopMode::Track => "Looping the current song",
+            LoopMode::Queue => "Looping the queue",
+        }
+    }
+}
+
+/// Voice connections and the per-guild music queue.
+#[async_trait]
+pub trait VoiceBackend: Send + Sync {
+    /// Joins a voice channel. Songs starting and failing are announced in
+    /// `text_channel_id`.
+    async fn join(
+        &self,
+        guild_id: GuildId,
+        channel_id: ChannelId,
+        text_channel_id: ChannelId,
+    ) -> CommandResult;
+
+    async fn is_connected(&self, guild_id: GuildId) -> bool;
+
+    /// The voice channel being played in.
+    async fn current_channel(&self, guild_id: GuildId) -> Option<ChannelId>;
+
+    async fn leave(&self, guild_id: GuildId) -> CommandResult;
+
+    async fn search(&self, query: &str) -> CommandResult<Vec<Track>>;
+
+    /// Number of tracks waiting in the queue, including the one playing.
+    async fn queue_len(&self, guild_id: GuildId) -> usize;
+
+    /// The queued tracks, starting with the one playing.
+    async fn queue(&self, guild_id: GuildId) -> Vec<QueuedTrack>;
+
+    /// How far into the current track the player is, in milliseconds.
+    async fn position(&self, guild_id: GuildId) -> u64;
+
+    async fn enqueue(&self, guild_id: GuildId, track: &Track, requester: UserId) -> CommandResult;
+
+    /// Rearranges the tracks after the one playing. `order` holds indexes into
+    /// the upcoming tracks, and any left out are dropped.
+    async fn reorder_upcoming(&self, guild_id: GuildId, order: &[usize]);
+
+    /// Skips the current track, returning it if there was one.
+    async fn skip(&self, guild_id: GuildId) -> Option<Track>;
+
+    async fn stop(&self, guild_id: GuildId) -> CommandResult;
+
+    async fn now_playing(&self, guild_id: GuildId) -> Option<Track>;
+
+    async fn loop_mode(&self, guild_id: GuildId) -> LoopMode;
+
+    async fn set_loop_mode(&self, guild_id: GuildId, mode: LoopMode);
+
+    async fn is_paused(&self, guild_id: GuildId) -> bool;
+
+    async fn set_paused(&self, guild_id: GuildId, paused: bool) -> CommandResult;
+
+    /// Jumps to `position` milliseconds into the current track.
+    async fn seek(&self, guild_id: GuildId, position: u64) -> CommandResult;
+
+    /// Sets the volume in percent.
+    async fn set_volume(&self, guild_id: GuildId, volume: u16) -> CommandResult;
+}
+
+/// What the Doom commands need to know about a running server.
+#[derive(Clone, Debug)]
+pub struct ServerSummary {
+    pub id: u32,
+    pub name: String,
+    pub maps: Vec<String>,
+    /// Whether console commands, like the ones votes run, can reach it.
+    pub has_console: bool,
+    pub started: Instant,
+    pub deadline: Instant,
+}
+
+/// Why a Doom server couldn't be hosted.
+#[derive(Debug)]
+pub enum HostError {
+    Download(anyhow::Error),
+    Launch(anyhow::Error),
+}
+
+/// Running Zandronum servers.
+#[async_trait]
+pub trait ServerRegistry: Send + Sync {
+    /// Downloads `url` and starts a server with it, returning the server's name.
+    async fn host(
+        &self,
+        config: &crate::config::doom::DoomConfig,
+        iwad_path: &str,
+        url: &str,
+        origin: crate::doom::Origin,
+    ) -> Result<String, HostError>;
+
+    async fn hosted_by(&self, user_id: UserId) -> Option<ServerSummary>;
+
+    async fn in_channel(&self, channel_id: ChannelId) -> Option<ServerSummary>;
+
+    /// Pushes back a server's deadline, but no later than `latest`, returning
+    /// the new one.
+    async fn extend(&self, id: u32, duration: Duration, latest: Instant) -> Option<Instant>;
+
+    /// Runs a command on a server's console, returning whether it got there.
+    async fn console(&self, id: u32, command: &str) -> bool;
+}
+
+/// Booked servers and the record of servers hosted before.
+#[async_trait]
+pub trait DoomRecords: Send + Sync {
+    /// Books a server and starts waiting for its time.
+    async fn add_schedule(
+        &self,
+        origin: crate::doom::Origin,
+        time: DateTime<Utc>,
+        iwad: String,
+        url: String,
+    ) -> CommandResult<Schedule>;
+
+    /// Every pending schedule, soonest first.
+    async fn schedules(&self) -> CommandResult<Vec<Schedule>>;
+
+    async fn schedule(&self, id: u64) -> CommandResult<Option<Schedule>>;
+
+    async fn remove_schedule(&self, id: u64) -> CommandResult;
+
+    /// The servers hosted most recently in a guild, newest first.
+    async fn recent(
+        &self,
+        guild_id: Option<GuildId>,
+        limit: usize,
+    ) -> CommandResult<Vec<HostRecord>>;
+
+    /// The WADs hosted most often in a guild and how many times they were.
+    async fn top(
+        &self,
+        guild_id: Option<GuildId>,
+        limit: usize,
+    ) -> CommandResult<Vec<(String, usize)>>;
+}
+
+/// Playlists saved by members and guilds.
+#[async_trait]
+pub trait PlaylistStore: Send + Sync {
+    /// Finds a playlist by name, ignoring case.
+    async fn get(&self, owner: Owner, name: &str) -> CommandResult<Option<Playlist>>;
+
+    /// Saves a playlist, replacing any with the same name.
+    async fn save(&self, owner: Owner, playlist: &Playlist) -> CommandResult;
+
+    async fn delete(&self, owner: Owner, name: &str) -> CommandResult;
+
+    /// Every playlist an owner has, by name.
+    async fn list(&self, owner: Owner) -> CommandResult<Vec<Playlist>>;
+}
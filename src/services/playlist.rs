@@ -0,0 +1,448 @@
+use super::{song::format_duration, song::SongService, Invocation, Messenger, PlaylistStore};
+use crate::{
+    error::CommandError,
+    playlists::{Owner, Playlist, MAX_TRACKS},
+    settings::GuildSettings,
+};
+use serenity::{
+    framework::standard::CommandResult,
+    model::{id::UserId, misc::Mentionable},
+};
+use std::sync::Arc;
+
+/// Longest a playlist's name can be.
+const MAX_NAME_LENGTH: usize = 100;
+
+/// Longest an embed field can be.
+const MAX_FIELD_LENGTH: usize = 1024;
+
+/// The invoker's playlists and their guild's.
+pub struct PlaylistListing {
+    pub mine: Vec<Playlist>,
+    pub shared: Vec<Playlist>,
+}
+
+/// The playlist commands. `shared` means the guild's playlist rather than the
+/// invoker's own.
+pub struct PlaylistService {
+    songs: SongService,
+    store: Arc<dyn PlaylistStore>,
+}
+
+impl PlaylistService {
+    pub fn new(songs: SongService, store: Arc<dyn PlaylistStore>) -> Self {
+        PlaylistService { songs, store }
+    }
+
+    /// Saves the song playing and the queue after it.
+    pub async fn save(
+        &self,
+        messenger: &dyn Messenger,
+        invocation: &Invocation,
+        shared: bool,
+        name: &str,
+    ) -> CommandResult {
+        let owner = owner(invocation, shared)?;
+        let name = check_name(name)?;
+        let mut tracks = self.songs.snapshot(invocation).await?;
+        tracks.truncate(MAX_TRACKS);
+
+        if let Some(existing) = self.store.get(owner, &name).await? {
+            check_can_change(&existing, owner, invocation.user_id)?;
+        }
+        let playlist = Playlist {
+            name,
+            created_by: invocation.user_id,
+            tracks,
+        };
+        self.store.save(owner, &playlist).await?;
+
+        messenger
+            .say(&format!(
+                "Saved **{}** with {} songs",
+                playlist.name,
+                playlist.tracks.len()
+            ))
+            .await
+    }
+
+    /// Queues a playlist. Unless `shared`, the invoker's own playlists are
+    /// looked in before the guild's.
+    pub async fn load(
+        &self,
+        messenger: &dyn Messenger,
+        invocation: &Invocation,
+        settings: &GuildSettings,
+        shared: bool,
+        name: &str,
+    ) -> CommandResult {
+        let owner = owner(invocation, shared)?;
+        let name = check_name(name)?;
+
+        let mut playlist = self.store.get(owner, &name).await?;
+        if let (None, false, Some(guild_id)) = (&playlist, shared, invocation.guild_id) {
+            playlist = self.store.get(Owner::Guild(guild_id), &name).await?;
+        }
+        let playlist = playlist.ok_or_else(|| missing(&name))?;
+        if playlist.tracks.is_empty() {
+            return Err(CommandError::user(format!("**{}** has no songs", playlist.name)).into());
+        }
+
+        self.songs
+            .play_tracks(messenger, invocation, settings, playlist.tracks)
+            .await
+    }
+
+    pub async fn list(&self, invocation: &Invocation) -> CommandResult<PlaylistListing> {
+        let mine = self.store.list(Owner::User(invocation.user_id)).await?;
+        let shared = match invocation.guild_id {
+            Some(guild_id) => self.store.list(Owner::Guild(guild_id)).await?,
+            None => Vec::new(),
+        };
+        Ok(PlaylistListing { mine, shared })
+    }
+
+    pub async fn delete(
+        &self,
+        messenger: &dyn Messenger,
+        invocation: &Invocation,
+        shared: bool,
+        name: &str,
+    ) -> CommandResult {
+        let owner = owner(invocation, shared)?;
+        let name = check_name(name)?;
+
+        let playlist = self
+            .store
+            .get(owner, &name)
+            .await?
+            .ok_or_else(|| missing(&name))?;
+        check_can_change(&playlist, owner, invocation.user_id)?;
+        self.store.delete(owner, &name).await?;
+
+        messenger
+            .say(&format!("Deleted **{}**", playlist.name))
+            .await
+    }
+
+    /// Adds what `query` finds to a playlist, or the song playing if it's
+    /// empty. The playlist is created if there isn't one.
+    pub async fn add(
+        &self,
+        messenger: &dyn Messenger,
+        invocation: &Invocation,
+        shared: bool,
+        name: &str,
+        query: &str,
+    ) -> CommandResult {
+        let owner = owner(invocation, shared)?;
+        let name = check_name(name)?;
+        let query = query.trim();
+
+        let tracks = if query.is_empty() {
+            vec![self.songs.now_playing(invocation).await?.current.track]
+        } else {
+            self.songs.find(query).await?
+        };
+
+        let mut playlist = match self.store.get(owner, &name).await? {
+            Some(playlist) => {
+                check_can_change(&playlist, owner, invocation.user_id)?;
+                playlist
+            }
+            None => Playlist {
+                name,
+                created_by: invocation.user_id,
+                tracks: Vec::new(),
+            },
+        };
+        let space = MAX_TRACKS.saturating_sub(playlist.tracks.len());
+        if space == 0 {
+            return Err(CommandError::user(format!(
+                "Playlists can hold at most {} songs",
+                MAX_TRACKS
+            ))
+            .into());
+        }
+        let added = tracks.len().min(space);
+        playlist.tracks.extend(tracks.into_iter().take(space));
+        self.store.save(owner, &playlist).await?;
+
+        messenger
+            .say(&format!(
+                "Added {} songs to **{}**, which now has {}",
+                added,
+                playlist.name,
+                playlist.tracks.len()
+            ))
+            .await
+    }
+}
+
+/// Lists playlists for an embed field, cutting the list short with how many
+/// were left out if it would be too long for one.
+pub fn describe(playlists: &[Playlist]) -> String {
+    if playlists.is_empty() {
+        return String::from("None yet");
+    }
+
+    let mut description = String::new();
+    for (shown, playlist) in playlists.iter().enumerate() {
+        let line = format!(
+            "**{}** {} songs `{}`",
+            playlist.name,
+            playlist.tracks.len(),
+            format_duration(playlist.length())
+        );
+        // Leave room to say how many are left out after this one.
+        let left = playlists.len() - shown - 1;
+        let reserved = if left == 0 { 0 } else { more(left).len() + 1 };
+        if description.len() + 1 + line.len() + reserved > MAX_FIELD_LENGTH {
+            description.push('\n');
+            description.push_str(&more(left + 1));
+            break;
+        }
+        description.push('\n');
+        description.push_str(&line);
+    }
+    description.trim_start().to_string()
+}
+
+fn more(count: usize) -> String {
+    format!("and {} more", count)
+}
+
+fn owner(invocation: &Invocation, shared: bool) -> Result<Owner, CommandError> {
+    if !shared {
+        return Ok(Owner::User(invocation.user_id));
+    }
+    invocation
+        .guild_id
+        .map(Owner::Guild)
+        .ok_or_else(|| CommandError::user("Only servers have shared playlists"))
+}
+
+fn check_name(input: &str) -> Result<String, CommandError> {
+    let name = input.trim().trim_matches('"').trim();
+    if name.is_empty() {
+        Err(CommandError::user("Must provide a playlist name"))
+    } else if name.chars().count() > MAX_NAME_LENGTH {
+        Err(CommandError::user(format!(
+            "Playlist names can be at most {} characters",
+            MAX_NAME_LENGTH
+        )))
+    } else {
+        Ok(name.to_string())
+    }
+}
+
+fn check_can_change(
+    playlist: &Playlist,
+    owner: Owner,
+    user_id: UserId,
+) -> Result<(), CommandError> {
+    if playlist.can_change(owner, user_id) {
+        Ok(())
+    } else {
+        Err(CommandError::user(format!(
+            "Only {} can change **{}**",
+            playlist.created_by.mention(),
+            playlist.name
+        )))
+    }
+}
+
+fn missing(name: &str) -> CommandError {
+    CommandError::user(format!("There is no playlist called **{}**", name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::{
+        fakes::{self, FakeGuilds, FakeMessenger, FakePlaylists, FakeVoice},
+        VoiceBackend,
+    };
+
+    fn service(voice: &Arc<FakeVoice>) -> PlaylistService {
+        let guilds = FakeGuilds::new();
+        guilds.put_in_voice(fakes::USER, fakes::VOICE_CHANNEL);
+        PlaylistService::new(
+            SongService::new(voice.clone(), guilds),
+            FakePlaylists::new(),
+        )
+    }
+
+    fn someone_else() -> Invocation {
+        Invocation {
+            user_id: UserId(5),
+            ..fakes::invocation()
+        }
+    }
+
+    #[tokio::test]
+    async fn saved_playlists_load_into_the_queue() {
+        let (voice, messenger) = (FakeVoice::new(), FakeMessenger::new());
+        voice.connect(fakes::GUILD);
+        for track in &[fakes::track("One", 60), fakes::track("Two", 90)] {
+            voice
+                .enqueue(fakes::GUILD, track, fakes::USER)
+                .await
+                .unwrap();
+        }
+        let service = service(&voice);
+
+        service
+            .save(&messenger, &fakes::invocation(), false, "Road trip")
+            .await
+            .unwrap();
+        voice.stop(fakes::GUILD).await.unwrap();
+        service
+            .load(
+                &messenger,
+                &fakes::invocation(),
+                &GuildSettings::default(),
+                false,
+                "\"road TRIP\"",
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(messenger.replies()[0], "Saved **Road trip** with 2 songs");
+        assert_eq!(voice.queued(fakes::GUILD), vec!["One", "Two"]);
+    }
+
+    #[tokio::test]
+    async fn load_falls_back_to_the_guilds_playlists() {
+        let (voice, messenger) = (FakeVoice::new(), FakeMessenger::new());
+        voice.connect(fakes::GUILD);
+        voice.add_result("one", fakes::track("One", 60));
+        let service = service(&voice);
+
+        service
+            .add(&messenger, &someone_else(), true, "Ours", "one")
+            .await
+            .unwrap();
+        let missing = service
+            .load(
+                &messenger,
+                &fakes::invocation(),
+                &GuildSettings::default(),
+                false,
+                "Mine",
+            )
+            .await;
+        service
+            .load(
+                &messenger,
+                &fakes::invocation(),
+                &GuildSettings::default(),
+                false,
+                "ours",
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(
+            fakes::user_error(missing),
+            "There is no playlist called **Mine**"
+        );
+        assert_eq!(
+            messenger.replies()[0],
+            "Added 1 songs to **Ours**, which now has 1"
+        );
+        assert_eq!(voice.queued(fakes::GUILD), vec!["One"]);
+    }
+
+    #[tokio::test]
+    async fn only_the_creator_changes_a_guilds_playlist() {
+        let (voice, messenger) = (FakeVoice::new(), FakeMessenger::new());
+        voice.connect(fakes::GUILD);
+        voice
+            .enqueue(fakes::GUILD, &fakes::track("One", 60), fakes::USER)
+            .await
+            .unwrap();
+        let service = service(&voice);
+
+        service
+            .add(&messenger, &fakes::invocation(), true, "Ours", "")
+            .await
+            .unwrap();
+        let added = service
+            .add(&messenger, &someone_else(), true, "Ours", "")
+            .await;
+        let deleted = service
+            .delete(&messenger, &someone_else(), true, "Ours")
+            .await;
+        service
+            .delete(&messenger, &fakes::invocation(), true, "ours")
+            .await
+            .unwrap();
+
+        assert_eq!(fakes::user_error(added), "Only <@4> can change **Ours**");
+        assert_eq!(fakes::user_error(deleted), "Only <@4> can change **Ours**");
+        assert_eq!(
+            messenger.replies(),
+            vec![
+                "Added 1 songs to **Ours**, which now has 1",
+                "Deleted **Ours**"
+            ]
+        );
+        let listing = service.list(&fakes::invocation()).await.unwrap();
+        assert!(listing.shared.is_empty());
+    }
+
+    #[tokio::test]
+    async fn names_are_checked() {
+        let (voice, messenger) = (FakeVoice::new(), FakeMessenger::new());
+        let service = service(&voice);
+
+        let empty = service
+            .delete(&messenger, &fakes::invocation(), false, " \"\" ")
+            .await;
+        let long = service
+            .delete(&messenger, &fakes::invocation(), false, &"a".repeat(101))
+            .await;
+        let direct = service
+            .delete(
+                &messenger,
+                &Invocation {
+                    guild_id: None,
+                    ..fakes::invocation()
+                },
+                true,
+                "Ours",
+            )
+            .await;
+
+        assert_eq!(fakes::user_error(empty), "Must provide a playlist name");
+        assert_eq!(
+            fakes::user_error(long),
+            "Playlist names can be at most 100 characters"
+        );
+        assert_eq!(
+            fakes::user_error(direct),
+            "Only servers have shared playlists"
+        );
+    }
+
+    #[test]
+    fn long_lists_are_cut_short() {
+        let playlists: Vec<Playlist> = (0..50)
+            .map(|i| Playlist {
+                name: format!("{:02}{}", i, "a".repeat(88)),
+                created_by: fakes::USER,
+                tracks: vec![fakes::track("One", 60)],
+            })
+            .collect();
+
+        let description = describe(&playlists);
+        let lines: Vec<&str> = description.lines().collect();
+
+        assert!(description.len() <= MAX_FIELD_LENGTH);
+        let shown = lines.len() - 1;
+        assert_eq!(lines[shown], format!("and {} more", 50 - shown));
+        assert_eq!(describe(&playlists[..1]).lines().count(), 1);
+        assert_eq!(describe(&[]), "None yet");
+    }
+}
@@ -0,0 +1,275 @@
+use super::{GuildDirectory, Invocation, Messenger};
+use crate::{config::RoleConfig, error::CommandError, settings::GuildSettings};
+use serenity::framework::standard::CommandResult;
+use std::sync::Arc;
+
+/// The role commands.
+pub struct RoleService {
+    guilds: Arc<dyn GuildDirectory>,
+}
+
+impl RoleService {
+    pub fn new(guilds: Arc<dyn GuildDirectory>) -> Self {
+        RoleService { guilds }
+    }
+
+    /// Creates a role called `name` unless it already exists.
+    pub async fn add(
+        &self,
+        messenger: &dyn Messenger,
+        invocation: &Invocation,
+        config: &RoleConfig,
+        name: &str,
+    ) -> CommandResult {
+        let guild_id = invocation.guild()?;
+
+        if let Some(role) = self.guilds.role_by_name(guild_id, name).await {
+            return Err(CommandError::user(format!("\"{}\" already exists.", role.name)).into());
+        }
+
+        let role = self
+            .guilds
+            .create_role(guild_id, name, config.hoist, config.mentionable)
+            .await?;
+        messenger
+            .say(&format!("<@&{}> has been created!", role.id))
+            .await
+    }
+
+    /// Gives the invoking user the role called `name`, or takes it away if they
+    /// already have it.
+    pub async fn assign(
+        &self,
+        messenger: &dyn Messenger,
+        invocation: &Invocation,
+        settings: &GuildSettings,
+        name: &str,
+    ) -> CommandResult {
+        let guild_id = invocation.guild()?;
+
+        let role = match self.guilds.role_by_name(guild_id, name).await {
+            Some(role) => role,
+            None => return Err(CommandError::user("Could not find role").into()),
+        };
+
+        if !settings.can_assign(&role.name) {
+            return Err(CommandError::user(format!(
+                "\"{}\" can't be assigned to yourself",
+                role.name
+            ))
+            .into());
+        }
+
+        let roles = self
+            .guilds
+            .member_roles(guild_id, invocation.user_id)
+            .await?;
+
+        if roles.contains(&role.id) {
+            self.guilds
+                .remove_role(guild_id, invocation.user_id, role.id)
+                .await?;
+            messenger
+                .say(&format!(
+                    "<@{}>: You no longer have the \"{}\" role",
+                    invocation.user_id, role.name
+                ))
+                .await
+        } else {
+            self.guilds
+                .add_role(guild_id, invocation.user_id, role.id)
+                .await?;
+            messenger
+                .say(&format!(
+                    "<@{}>: You now have the \"{}\" role",
+                    invocation.user_id, role.name
+                ))
+                .await
+        }
+    }
+
+    /// Names of the assignable roles that start with `partial`, for autocompletion.
+    pub async fn matching(
+        &self,
+        invocation: &Invocation,
+        settings: &GuildSettings,
+        partial: &str,
+    ) -> Vec<String> {
+        let guild_id = match invocation.guild_id {
+            Some(guild_id) => guild_id,
+            None => return Vec::new(),
+        };
+
+        let partial = partial.to_lowercase();
+        let mut names: Vec<String> = self
+            .guilds
+            .roles(guild_id)
+            .await
+            .into_iter()
+            .filter(|role| role.name != "@everyone" && !role.managed)
+            .filter(|role| settings.can_assign(&role.name))
+            .filter(|role| role.name.to_lowercase().starts_with(&partial))
+            .map(|role| role.name)
+            .collect();
+        names.sort();
+        names
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::fakes::{self, FakeGuilds, FakeMessenger};
+
+    #[tokio::test]
+    async fn add_creates_a_role() {
+        let (guilds, messenger) = (FakeGuilds::new(), FakeMessenger::new());
+
+        RoleService::new(guilds.clone())
+            .add(
+                &messenger,
+                &fakes::invocation(),
+                &RoleConfig::default(),
+                "doomers",
+            )
+            .await
+            .unwrap();
+
+        let role = guilds.role_by_name(fakes::GUILD, "doomers").await.unwrap();
+        assert_eq!(
+            messenger.replies(),
+            vec![format!("<@&{}> has been created!", role.id)]
+        );
+    }
+
+    #[tokio::test]
+    async fn roles_are_refused_in_dms() {
+        let (guilds, messenger) = (FakeGuilds::new(), FakeMessenger::new());
+        let service = RoleService::new(guilds.clone());
+        let invocation = Invocation {
+            guild_id: None,
+            ..fakes::invocation()
+        };
+
+        let result = service
+            .add(&messenger, &invocation, &RoleConfig::default(), "doomers")
+            .await;
+        assert_eq!(
+            fakes::user_error(result),
+            "This command only works in a server"
+        );
+        let result = service
+            .assign(
+                &messenger,
+                &invocation,
+                &GuildSettings::default(),
+                "doomers",
+            )
+            .await;
+        assert_eq!(
+            fakes::user_error(result),
+            "This command only works in a server"
+        );
+    }
+
+    #[tokio::test]
+    async fn add_refuses_duplicates() {
+        let (guilds, messenger) = (FakeGuilds::new(), FakeMessenger::new());
+        guilds.add_guild_role("doomers", false);
+
+        let result = RoleService::new(guilds.clone())
+            .add(
+                &messenger,
+                &fakes::invocation(),
+                &RoleConfig::default(),
+                "doomers",
+            )
+            .await;
+
+        assert_eq!(fakes::user_error(result), "\"doomers\" already exists.");
+        assert_eq!(guilds.roles(fakes::GUILD).await.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn assign_toggles_the_role() {
+        let (guilds, messenger) = (FakeGuilds::new(), FakeMessenger::new());
+        let role = guilds.add_guild_role("doomers", false);
+        let service = RoleService::new(guilds.clone());
+        let settings = GuildSettings::default();
+
+        service
+            .assign(&messenger, &fakes::invocation(), &settings, "doomers")
+            .await
+            .unwrap();
+        assert!(guilds.has_role(fakes::USER, role));
+        service
+            .assign(&messenger, &fakes::invocation(), &settings, "doomers")
+            .await
+            .unwrap();
+        assert!(!guilds.has_role(fakes::USER, role));
+
+        assert_eq!(
+            messenger.replies(),
+            vec![
+                format!("<@{}>: You now have the \"doomers\" role", fakes::USER),
+                format!(
+                    "<@{}>: You no longer have the \"doomers\" role",
+                    fakes::USER
+                ),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn assign_unknown_role() {
+        let (guilds, messenger) = (FakeGuilds::new(), FakeMessenger::new());
+
+        let result = RoleService::new(guilds)
+            .assign(
+                &messenger,
+                &fakes::invocation(),
+                &GuildSettings::default(),
+                "nope",
+            )
+            .await;
+
+        assert_eq!(fakes::user_error(result), "Could not find role");
+        assert!(messenger.replies().is_empty());
+    }
+
+    #[tokio::test]
+    async fn assign_respects_assignable_roles() {
+        let (guilds, messenger) = (FakeGuilds::new(), FakeMessenger::new());
+        let role = guilds.add_guild_role("mods", false);
+        let settings = GuildSettings {
+            assignable_roles: vec![String::from("doomers")],
+            ..GuildSettings::default()
+        };
+
+        let result = RoleService::new(guilds.clone())
+            .assign(&messenger, &fakes::invocation(), &settings, "mods")
+            .await;
+
+        assert_eq!(
+            fakes::user_error(result),
+            "\"mods\" can't be assigned to yourself"
+        );
+        assert!(!guilds.has_role(fakes::USER, role));
+    }
+
+    #[tokio::test]
+    async fn matching_filters_roles() {
+        let guilds = FakeGuilds::new();
+        guilds.add_guild_role("@everyone", false);
+        guilds.add_guild_role("Doomers", false);
+        guilds.add_guild_role("doom bot", true);
+        guilds.add_guild_role("dj", false);
+        guilds.add_guild_role("quakers", false);
+
+        let names = RoleService::new(guilds)
+            .matching(&fakes::invocation(), &GuildSettings::default(), "d")
+            .await;
+
+        assert_eq!(names, vec!["Doomers", "dj"]);
+    }
+}
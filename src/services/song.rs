@@ -0,0 +1,1409 @@
+use super::{GuildDirectory, Invocation, LoopMode, Messenger, QueuedTrack, Track, VoiceBackend};
+use crate::{
+    error::CommandError,
+    settings::{GuildSettings, MAX_VOLUME},
+};
+use rand::seq::SliceRandom;
+use serenity::{
+    framework::standard::CommandResult,
+    model::{
+        id::{ChannelId, GuildId},
+        misc::Mentionable,
+    },
+};
+use std::sync::Arc;
+
+/// Upcoming tracks shown on each page of `song queue`.
+pub const QUEUE_PAGE_SIZE: usize = 10;
+
+/// Results `song search` lets you pick from.
+pub const SEARCH_RESULTS: usize = 5;
+
+/// A guild's queue as shown by `song queue`.
+pub struct QueueListing {
+    pub now_playing: QueuedTrack,
+    /// How far into the current track the player is, in milliseconds.
+    pub position: u64,
+    pub upcoming: Vec<QueuedTrack>,
+    /// Milliseconds until everything queued has played, not counting streams.
+    pub remaining: u64,
+    pub loop_mode: LoopMode,
+}
+
+impl QueueListing {
+    pub fn page_count(&self) -> usize {
+        ((self.upcoming.len() + QUEUE_PAGE_SIZE - 1) / QUEUE_PAGE_SIZE).max(1)
+    }
+
+    /// The upcoming tracks on a page, counting both from 1.
+    pub fn page(&self, page: usize) -> impl Iterator<Item = (usize, &QueuedTrack)> {
+        self.upcoming
+            .iter()
+            .enumerate()
+            .skip((page.max(1) - 1) * QUEUE_PAGE_SIZE)
+            .take(QUEUE_PAGE_SIZE)
+            .map(|(index, queued)| (index + 1, queued))
+    }
+}
+
+/// The track that's playing, as shown by `song current`.
+pub struct NowPlaying {
+    pub current: QueuedTrack,
+    pub next: Option<QueuedTrack>,
+    /// How far into the track the player is, in milliseconds.
+    pub position: u64,
+    pub paused: bool,
+    pub loop_mode: LoopMode,
+}
+
+/// A bar `width` characters long with a knob at `position`.
+pub fn progress_bar(position: u64, length: u64, width: usize) -> String {
+    let before = if length == 0 {
+        0
+    } else {
+        (position.min(length) * width as u64 / length) as usize
+    };
+    format!(
+        "{}\u{1f518}{}",
+        "\u{25ac}".repeat(before),
+        "\u{25ac}".repeat(width - before)
+    )
+}
+
+/// Whether `play` was given a link rather than text to search for.
+fn is_link(query: &str) -> bool {
+    query.starts_with("http://") || query.starts_with("https://")
+}
+
+/// Reads which results were picked from a reply like `1 3` or `2, 4`, as
+/// indexes into the `count` results. `None` if the reply isn't a pick.
+pub fn parse_picks(reply: &str, count: usize) -> Option<Vec<usize>> {
+    let mut picks = Vec::new();
+    for part in reply.split(|c: char| c == ',' || c.is_whitespace()) {
+        if part.is_empty() {
+            continue;
+        }
+        match part.parse::<usize>() {
+            Ok(number) if (1..=count).contains(&number) => {
+                if !picks.contains(&(number - 1)) {
+                    picks.push(number - 1);
+                }
+            }
+            _ => return None,
+        }
+    }
+    if picks.is_empty() {
+        None
+    } else {
+        Some(picks)
+    }
+}
+
+/// Formats milliseconds like `3:07` or `1:02:03`.
+pub fn format_duration(ms: u64) -> String {
+    let seconds = ms / 1000;
+    let (hours, minutes, seconds) = (seconds / 3600, seconds / 60 % 60, seconds % 60);
+    if hours > 0 {
+        format!("{}:{:02}:{:02}", hours, minutes, seconds)
+    } else {
+        format!("{}:{:02}", minutes, seconds)
+    }
+}
+
+/// Where `song seek` should go.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SeekTarget {
+    To(u64),
+    Forward(u64),
+    Back(u64),
+}
+
+impl SeekTarget {
+    /// Parses `1:30`, `90`, `+30s` or `-1:00`. Amounts are in milliseconds.
+    pub fn parse(input: &str) -> Option<SeekTarget> {
+        let input = input.trim();
+        let (target, time): (fn(u64) -> SeekTarget, &str) = match input.chars().next()? {
+            '+' => (SeekTarget::Forward, &input[1..]),
+            '-' => (SeekTarget::Back, &input[1..]),
+            _ => (SeekTarget::To, input),
+        };
+        let time = time.strip_suffix('s').unwrap_or(time);
+
+        let mut seconds = 0u64;
+        for (i, part) in time.split(':').enumerate() {
+            // At most hours, minutes and seconds.
+            if i > 2 || part.is_empty() {
+                return None;
+            }
+            seconds = seconds
+                .checked_mul(60)?
+                .checked_add(part.parse::<u64>().ok()?)?;
+        }
+        Some(target(seconds.checked_mul(1000)?))
+    }
+}
+
+/// The music commands.
+pub struct SongService {
+    voice: Arc<dyn VoiceBackend>,
+    guilds: Arc<dyn GuildDirectory>,
+}
+
+impl SongService {
+    pub fn new(voice: Arc<dyn VoiceBackend>, guilds: Arc<dyn GuildDirectory>) -> Self {
+        SongService { voice, guilds }
+    }
+
+    /// Joins the invoking user's voice channel.
+    pub async fn join(&self, invocation: &Invocation) -> CommandResult {
+        let guild_id = invocation.guild()?;
+        let channel_id = self.callers_channel(guild_id, invocation).await?;
+
+        match self.voice.current_channel(guild_id).await {
+            Some(current) if current != channel_id => Err(CommandError::user(format!(
+                "Already playing in {}, use `move` to bring me to your channel",
+                current.mention()
+            ))
+            .into()),
+            _ => {
+                self.voice
+                    .join(guild_id, channel_id, invocation.channel_id)
+                    .await
+            }
+        }
+    }
+
+    /// Moves to the invoking user's voice channel, keeping the queue.
+    pub async fn follow(&self, invocation: &Invocation) -> CommandResult {
+        let guild_id = invocation.guild()?;
+        let current = match self.voice.current_channel(guild_id).await {
+            Some(current) => current,
+            None => return Err(CommandError::user("Not in a voice channel to play in").into()),
+        };
+        let channel_id = self.callers_channel(guild_id, invocation).await?;
+
+        if current == channel_id {
+            return Err(CommandError::user("Already in your voice channel").into());
+        }
+        self.voice
+            .join(guild_id, channel_id, invocation.channel_id)
+            .await
+    }
+
+    pub async fn leave(&self, invocation: &Invocation) -> CommandResult {
+        let guild_id = self.listening_guild(invocation).await?;
+
+        if !self.voice.is_connected(guild_id).await {
+            return Err(CommandError::user("Not in a voice channel to play in").into());
+        }
+
+        self.voice.leave(guild_id).await
+    }
+
+    /// Searches for `query` and queues what was found, within the guild's
+    /// limits. Links queue everything they load, text only the top result.
+    pub async fn play(
+        &self,
+        messenger: &dyn Messenger,
+        invocation: &Invocation,
+        settings: &GuildSettings,
+        query: &str,
+    ) -> CommandResult {
+        let guild_id = self.connected_guild(invocation).await?;
+        let tracks = self.find(query).await?;
+        self.add(messenger, invocation, guild_id, settings, tracks)
+            .await
+    }
+
+    /// What `play` queues for `query`: everything a link loads, or the top
+    /// result for text.
+    pub async fn find(&self, query: &str) -> CommandResult<Vec<Track>> {
+        let mut tracks = self.voice.search(query).await?;
+        if tracks.is_empty() {
+            return Err(CommandError::user("Could not find video").into());
+        }
+        if !is_link(query) {
+            tracks.truncate(1);
+        }
+        Ok(tracks)
+    }
+
+    /// The song playing and everything after it, to save as a playlist.
+    pub async fn snapshot(&self, invocation: &Invocation) -> CommandResult<Vec<Track>> {
+        let queue = self.voice.queue(invocation.guild()?).await;
+        if queue.is_empty() {
+            return Err(CommandError::user("Nothing is playing").into());
+        }
+        Ok(queue.into_iter().map(|queued| queued.track).collect())
+    }
+
+    /// The top results for `query`, for `song search` to choose from.
+    pub async fn search(&self, invocation: &Invocation, query: &str) -> CommandResult<Vec<Track>> {
+        self.listening_guild(invocation).await?;
+
+        let mut tracks = self.voice.search(query).await?;
+        if tracks.is_empty() {
+            return Err(CommandError::user("Could not find video").into());
+        }
+        tracks.truncate(SEARCH_RESULTS);
+        Ok(tracks)
+    }
+
+    /// Queues tracks that were already found, like those picked from `search`.
+    pub async fn play_tracks(
+        &self,
+        messenger: &dyn Messenger,
+        invocation: &Invocation,
+        settings: &GuildSettings,
+        tracks: Vec<Track>,
+    ) -> CommandResult {
+        let guild_id = self.connected_guild(invocation).await?;
+        self.add(messenger, invocation, guild_id, settings, tracks)
+            .await
+    }
+
+    /// Joins the caller's channel if nothing is playing yet.
+    async fn connected_guild(&self, invocation: &Invocation) -> CommandResult<GuildId> {
+        let guild_id = self.listening_guild(invocation).await?;
+        if !self.voice.is_connected(guild_id).await {
+            self.join(invocation).await?;
+        }
+        Ok(guild_id)
+    }
+
+    async fn add(
+        &self,
+        messenger: &dyn Messenger,
+        invocation: &Invocation,
+        guild_id: GuildId,
+        settings: &GuildSettings,
+        mut tracks: Vec<Track>,
+    ) -> CommandResult {
+        if let Some(max_length) = settings.max_track_length {
+            tracks.retain(|track| track.length <= max_length.saturating_mul(1000));
+            if tracks.is_empty() {
+                return Err(CommandError::user(format!(
+                    "Songs can be at most {} seconds long",
+                    max_length
+                ))
+                .into());
+            }
+        }
+        if let Some(max_queue_length) = settings.max_queue_length {
+            let queued = self.voice.queue_len(guild_id).await;
+            let space = max_queue_length.saturating_sub(queued);
+            if space == 0 {
+                return Err(CommandError::user(format!(
+                    "The queue is full ({} songs)",
+                    max_queue_length
+                ))
+                .into());
+            }
+            tracks.truncate(space);
+        }
+
+        for track in tracks.iter() {
+            self.voice
+                .enqueue(guild_id, track, invocation.user_id)
+                .await?;
+        }
+        if let Some(volume) = settings.volume {
+            self.voice.set_volume(guild_id, volume).await?;
+        }
+
+        if tracks.len() > 1 {
+            messenger
+                .say(&format!("Added {} songs to the queue", tracks.len()))
+                .await
+        } else {
+            messenger
+                .say(&format!("Added song to queue: {}", tracks[0].title))
+                .await
+        }
+    }
+
+    pub async fn skip(&self, messenger: &dyn Messenger, invocation: &Invocation) -> CommandResult {
+        let guild_id = self.listening_guild(invocation).await?;
+
+        if let Some(track) = self.voice.skip(guild_id).await {
+            messenger.say(&format!("Skipped: {}", track.title)).await?;
+        }
+        Ok(())
+    }
+
+    pub async fn stop(&self, invocation: &Invocation) -> CommandResult {
+        self.voice
+            .stop(self.listening_guild(invocation).await?)
+            .await
+    }
+
+    /// What's playing and what's coming up.
+    pub async fn queue(&self, invocation: &Invocation) -> CommandResult<QueueListing> {
+        let guild_id = invocation.guild()?;
+
+        let mut upcoming = self.voice.queue(guild_id).await;
+        if upcoming.is_empty() {
+            return Err(CommandError::user("Nothing is queued").into());
+        }
+        let now_playing = upcoming.remove(0);
+        let position = self.voice.position(guild_id).await;
+
+        let length = |queued: &QueuedTrack| {
+            if queued.track.is_stream {
+                0
+            } else {
+                queued.track.length
+            }
+        };
+        let remaining = length(&now_playing).saturating_sub(position)
+            + upcoming.iter().map(length).sum::<u64>();
+
+        Ok(QueueListing {
+            now_playing,
+            position,
+            upcoming,
+            remaining,
+            loop_mode: self.voice.loop_mode(guild_id).await,
+        })
+    }
+
+    pub async fn pause(&self, messenger: &dyn Messenger, invocation: &Invocation) -> CommandResult {
+        let guild_id = self.listening_guild(invocation).await?;
+        let track = self.playing(guild_id).await?;
+        if self.voice.is_paused(guild_id).await {
+            return Err(CommandError::user("Already paused, use `resume` to keep playing").into());
+        }
+
+        self.voice.set_paused(guild_id, true).await?;
+        messenger.say(&format!("Paused: {}", track.title)).await
+    }
+
+    pub async fn resume(
+        &self,
+        messenger: &dyn Messenger,
+        invocation: &Invocation,
+    ) -> CommandResult {
+        let guild_id = self.listening_guild(invocation).await?;
+        let track = self.playing(guild_id).await?;
+        if !self.voice.is_paused(guild_id).await {
+            return Err(CommandError::user("Not paused").into());
+        }
+
+        self.voice.set_paused(guild_id, false).await?;
+        messenger.say(&format!("Resumed: {}", track.title)).await
+    }
+
+    /// Jumps to a time in the current track, or forward or back from where it is.
+    pub async fn seek(
+        &self,
+        messenger: &dyn Messenger,
+        invocation: &Invocation,
+        target: &str,
+    ) -> CommandResult {
+        let guild_id = self.listening_guild(invocation).await?;
+        let target = SeekTarget::parse(target).ok_or_else(|| {
+            CommandError::user("Give a time like `1:30`, or `+30s` or `-30s` to skip ahead or back")
+        })?;
+        let track = self.playing(guild_id).await?;
+        if track.is_stream || !track.is_seekable {
+            return Err(CommandError::user("Can't seek in this song").into());
+        }
+
+        let position = self.voice.position(guild_id).await;
+        let position = match target {
+            SeekTarget::To(time) => time,
+            SeekTarget::Forward(amount) => position.saturating_add(amount),
+            SeekTarget::Back(amount) => position.saturating_sub(amount),
+        };
+        if position >= track.length {
+            return Err(CommandError::user(format!(
+                "The song is only {} long",
+                format_duration(track.length)
+            ))
+            .into());
+        }
+
+        self.voice.seek(guild_id, position).await?;
+        messenger
+            .say(&format!("Seeked to {}", format_duration(position)))
+            .await
+    }
+
+    /// Shows the volume, or changes it if `volume` is given. Saving it for
+    /// next time is up to the caller.
+    pub async fn volume(
+        &self,
+        messenger: &dyn Messenger,
+        invocation: &Invocation,
+        settings: &GuildSettings,
+        volume: Option<u16>,
+    ) -> CommandResult {
+        let guild_id = self.listening_guild(invocation).await?;
+
+        let volume = match volume {
+            Some(volume) if volume > MAX_VOLUME => {
+                return Err(
+                    CommandError::user(format!("Volume goes from 0 to {}", MAX_VOLUME)).into(),
+                )
+            }
+            Some(volume) => volume,
+            None => {
+                return messenger
+                    .say(&format!("Volume is {}%", settings.volume.unwrap_or(100)))
+                    .await
+            }
+        };
+
+        if self.voice.is_connected(guild_id).await {
+            self.voice.set_volume(guild_id, volume).await?;
+        }
+        messenger.say(&format!("Volume set to {}%", volume)).await
+    }
+
+    /// Shows the loop mode, or changes it to `track`, `queue` or `off`.
+    pub async fn set_loop(
+        &self,
+        messenger: &dyn Messenger,
+        invocation: &Invocation,
+        mode: Option<&str>,
+    ) -> CommandResult {
+        let guild_id = self.listening_guild(invocation).await?;
+
+        let mode = match mode {
+            Some(mode) => LoopMode::parse(mode)
+                .ok_or_else(|| CommandError::user("Loop can be `track`, `queue` or `off`"))?,
+            None => {
+                return messenger
+                    .say(self.voice.loop_mode(guild_id).await.describe())
+                    .await
+            }
+        };
+
+        self.voice.set_loop_mode(guild_id, mode).await;
+        messenger.say(mode.describe()).await
+    }
+
+    /// The track that's playing, failing if there isn't one.
+    async fn playing(&self, guild_id: GuildId) -> Result<Track, CommandError> {
+        self.voice
+            .now_playing(guild_id)
+            .await
+            .ok_or_else(|| CommandError::user("Nothing is playing"))
+    }
+
+    /// Removes the upcoming tracks from `start` to `end`, or just `start`.
+    pub async fn remove(
+        &self,
+        messenger: &dyn Messenger,
+        invocation: &Invocation,
+        start: usize,
+        end: Option<usize>,
+    ) -> CommandResult {
+        let guild_id = self.listening_guild(invocation).await?;
+        let upcoming = self.upcoming(guild_id).await?;
+
+        let end = end.unwrap_or(start);
+        check_position(start, upcoming.len())?;
+        check_position(end, upcoming.len())?;
+        if end < start {
+            return Err(CommandError::user("The range has to go from low to high").into());
+        }
+
+        let order: Vec<usize> = (0..upcoming.len())
+            .filter(|&i| i + 1 < start || i + 1 > end)
+            .collect();
+        self.voice.reorder_upcoming(guild_id, &order).await;
+
+        if start == end {
+            messenger
+                .say(&format!("Removed: {}", upcoming[start - 1].track.title))
+                .await
+        } else {
+            messenger
+                .say(&format!("Removed {} songs", end - start + 1))
+                .await
+        }
+    }
+
+    /// Moves the upcoming track at `from` to `to`.
+    pub async fn reorder(
+        &self,
+        messenger: &dyn Messenger,
+        invocation: &Invocation,
+        from: usize,
+        to: usize,
+    ) -> CommandResult {
+        let guild_id = self.listening_guild(invocation).await?;
+        let upcoming = self.upcoming(guild_id).await?;
+        check_position(from, upcoming.len())?;
+        check_position(to, upcoming.len())?;
+
+        let mut order: Vec<usize> = (0..upcoming.len()).collect();
+        let moved = order.remove(from - 1);
+        order.insert(to - 1, moved);
+        self.voice.reorder_upcoming(guild_id, &order).await;
+
+        messenger
+            .say(&format!(
+                "Moved {} to position {}",
+                upcoming[from - 1].track.title,
+                to
+            ))
+            .await
+    }
+
+    /// Shuffles the upcoming tracks, leaving the one playing alone.
+    pub async fn shuffle(
+        &self,
+        messenger: &dyn Messenger,
+        invocation: &Invocation,
+    ) -> CommandResult {
+        let guild_id = self.listening_guild(invocation).await?;
+        let upcoming = self.upcoming(guild_id).await?;
+
+        let mut order: Vec<usize> = (0..upcoming.len()).collect();
+        order.shuffle(&mut rand::thread_rng());
+        self.voice.reorder_upcoming(guild_id, &order).await;
+
+        messenger
+            .say(&format!("Shuffled {} songs", upcoming.len()))
+            .await
+    }
+
+    /// Empties the queue without stopping the song that's playing.
+    pub async fn clear(&self, messenger: &dyn Messenger, invocation: &Invocation) -> CommandResult {
+        let guild_id = self.listening_guild(invocation).await?;
+        let upcoming = self.upcoming(guild_id).await?;
+
+        self.voice.reorder_upcoming(guild_id, &[]).await;
+
+        messenger
+            .say(&format!("Cleared {} songs from the queue", upcoming.len()))
+            .await
+    }
+
+    /// Skips straight to the upcoming track at `position`.
+    pub async fn jump(
+        &self,
+        messenger: &dyn Messenger,
+        invocation: &Invocation,
+        position: usize,
+    ) -> CommandResult {
+        let guild_id = self.listening_guild(invocation).await?;
+        let upcoming = self.upcoming(guild_id).await?;
+        check_position(position, upcoming.len())?;
+
+        let order: Vec<usize> = (position - 1..upcoming.len()).collect();
+        self.voice.reorder_upcoming(guild_id, &order).await;
+        self.voice.skip(guild_id).await;
+
+        messenger
+            .say(&format!(
+                "Jumped to: {}",
+                upcoming[position - 1].track.title
+            ))
+            .await
+    }
+
+    /// The voice channel the caller is in, failing if they aren't in one.
+    async fn callers_channel(
+        &self,
+        guild_id: GuildId,
+        invocation: &Invocation,
+    ) -> Result<ChannelId, CommandError> {
+        self.guilds
+            .voice_channel(guild_id, invocation.user_id)
+            .await
+            .ok_or_else(|| CommandError::user("Not in a voice channel"))
+    }
+
+    /// The guild, unless something is playing and the caller isn't listening
+    /// to it.
+    async fn listening_guild(&self, invocation: &Invocation) -> Result<GuildId, CommandError> {
+        let guild_id = invocation.guild()?;
+        let current = self.voice.current_channel(guild_id).await;
+        let callers = self
+            .guilds
+            .voice_channel(guild_id, invocation.user_id)
+            .await;
+
+        match current {
+            Some(current) if callers != Some(current) => Err(CommandError::user(format!(
+                "You need to be in {} to do that",
+                current.mention()
+            ))),
+            _ => Ok(guild_id),
+        }
+    }
+
+    /// The tracks after the one playing, failing if there aren't any.
+    async fn upcoming(&self, guild_id: GuildId) -> Result<Vec<QueuedTrack>, CommandError> {
+        let mut upcoming = self.voice.queue(guild_id).await;
+        if upcoming.len() < 2 {
+            return Err(CommandError::user("Nothing is queued after this song"));
+        }
+        upcoming.remove(0);
+        Ok(upcoming)
+    }
+
+    /// What's playing, how far along it is and what comes after it.
+    pub async fn now_playing(&self, invocation: &Invocation) -> CommandResult<NowPlaying> {
+        let guild_id = invocation.guild()?;
+
+        let mut queue = self.voice.queue(guild_id).await.into_iter();
+        let current = queue
+            .next()
+            .ok_or_else(|| CommandError::user("Nothing is playing"))?;
+        Ok(NowPlaying {
+            current,
+            next: queue.next(),
+            position: self.voice.position(guild_id).await,
+            paused: self.voice.is_paused(guild_id).await,
+            loop_mode: self.voice.loop_mode(guild_id).await,
+        })
+    }
+}
+
+fn check_position(position: usize, len: usize) -> Result<(), CommandError> {
+    if position == 0 || position > len {
+        Err(CommandError::user(format!(
+            "There's no song at position {}, the queue goes from 1 to {}",
+            position, len
+        )))
+    } else {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::fakes::{self, FakeGuilds, FakeMessenger, FakeVoice};
+    use serenity::model::id::UserId;
+
+    const PLAYLIST: &str = "https://example.com/list";
+
+    fn service(voice: &Arc<FakeVoice>, guilds: &Arc<FakeGuilds>) -> SongService {
+        SongService::new(voice.clone(), guilds.clone())
+    }
+
+    #[tokio::test]
+    async fn join_requires_a_voice_channel() {
+        let (voice, guilds) = (FakeVoice::new(), FakeGuilds::new());
+
+        let result = service(&voice, &guilds).join(&fakes::invocation()).await;
+
+        assert_eq!(fakes::user_error(result), "Not in a voice channel");
+        assert!(!voice.is_connected(fakes::GUILD).await);
+    }
+
+    #[tokio::test]
+    async fn join_connects_to_the_callers_channel() {
+        let (voice, guilds) = (FakeVoice::new(), FakeGuilds::new());
+        guilds.put_in_voice(fakes::USER, fakes::VOICE_CHANNEL);
+
+        service(&voice, &guilds)
+            .join(&fakes::invocation())
+            .await
+            .unwrap();
+
+        assert_eq!(voice.channel(fakes::GUILD), Some(fakes::VOICE_CHANNEL));
+        assert_eq!(voice.text_channel(fakes::GUILD), Some(fakes::CHANNEL));
+    }
+
+    #[tokio::test]
+    async fn leave_without_a_connection() {
+        let (voice, guilds) = (FakeVoice::new(), FakeGuilds::new());
+
+        let result = service(&voice, &guilds).leave(&fakes::invocation()).await;
+
+        assert_eq!(
+            fakes::user_error(result),
+            "Not in a voice channel to play in"
+        );
+    }
+
+    #[tokio::test]
+    async fn play_queues_the_search_results() {
+        let (voice, guilds, messenger) =
+            (FakeVoice::new(), FakeGuilds::new(), FakeMessenger::new());
+        voice.connect(fakes::GUILD);
+        guilds.put_in_voice(fakes::USER, fakes::VOICE_CHANNEL);
+        voice.add_result("song", fakes::track("Song", 60));
+
+        service(&voice, &guilds)
+            .play(
+                &messenger,
+                &fakes::invocation(),
+                &GuildSettings::default(),
+                "song",
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(messenger.replies(), vec!["Added song to queue: Song"]);
+        assert_eq!(voice.queued(fakes::GUILD), vec!["Song"]);
+    }
+
+    #[tokio::test]
+    async fn play_reports_playlists() {
+        let (voice, guilds, messenger) =
+            (FakeVoice::new(), FakeGuilds::new(), FakeMessenger::new());
+        voice.connect(fakes::GUILD);
+        guilds.put_in_voice(fakes::USER, fakes::VOICE_CHANNEL);
+        voice.add_result(PLAYLIST, fakes::track("One", 60));
+        voice.add_result(PLAYLIST, fakes::track("Two", 60));
+
+        service(&voice, &guilds)
+            .play(
+                &messenger,
+                &fakes::invocation(),
+                &GuildSettings::default(),
+                PLAYLIST,
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(messenger.replies(), vec!["Added 2 songs to the queue"]);
+    }
+
+    #[tokio::test]
+    async fn play_queues_only_the_top_search_result() {
+        let (voice, guilds, messenger) =
+            (FakeVoice::new(), FakeGuilds::new(), FakeMessenger::new());
+        voice.connect(fakes::GUILD);
+        guilds.put_in_voice(fakes::USER, fakes::VOICE_CHANNEL);
+        voice.add_result("song", fakes::track("One", 60));
+        voice.add_result("song", fakes::track("Two", 60));
+
+        service(&voice, &guilds)
+            .play(
+                &messenger,
+                &fakes::invocation(),
+                &GuildSettings::default(),
+                "song",
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(voice.queued(fakes::GUILD), vec!["One"]);
+    }
+
+    #[tokio::test]
+    async fn search_then_play_the_picks() {
+        let (voice, guilds, messenger) =
+            (FakeVoice::new(), FakeGuilds::new(), FakeMessenger::new());
+        voice.connect(fakes::GUILD);
+        guilds.put_in_voice(fakes::USER, fakes::VOICE_CHANNEL);
+        for i in 1..=7 {
+            voice.add_result("song", fakes::track(&format!("Song {}", i), 60));
+        }
+        let service = service(&voice, &guilds);
+        let invocation = fakes::invocation();
+
+        let results = service.search(&invocation, "song").await.unwrap();
+        let picks = parse_picks("3, 1", results.len()).unwrap();
+        let picked = picks.iter().map(|&i| results[i].clone()).collect();
+        service
+            .play_tracks(&messenger, &invocation, &GuildSettings::default(), picked)
+            .await
+            .unwrap();
+
+        assert_eq!(results.len(), SEARCH_RESULTS);
+        assert_eq!(voice.queued(fakes::GUILD), vec!["Song 3", "Song 1"]);
+        assert!(service.search(&invocation, "nothing").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn snapshot_and_play_again() {
+        let (voice, guilds, messenger) =
+            (FakeVoice::new(), FakeGuilds::new(), FakeMessenger::new());
+        let service = service(&voice, &guilds);
+        let invocation = fakes::invocation();
+
+        let empty = service.snapshot(&invocation).await;
+        queue_of(&voice, &service, &["One", "Two"]).await;
+        let tracks = service.snapshot(&invocation).await.unwrap();
+        service.stop(&invocation).await.unwrap();
+        service
+            .play_tracks(&messenger, &invocation, &GuildSettings::default(), tracks)
+            .await
+            .unwrap();
+
+        match empty {
+            Err(error) => assert_eq!(error.to_string(), "Nothing is playing"),
+            Ok(_) => panic!("expected nothing to be playing"),
+        }
+        assert_eq!(voice.queued(fakes::GUILD), vec!["One", "Two"]);
+    }
+
+    #[test]
+    fn parses_picks() {
+        assert_eq!(parse_picks("2", 5), Some(vec![1]));
+        assert_eq!(parse_picks("1 3,5", 5), Some(vec![0, 2, 4]));
+        assert_eq!(parse_picks("1 1", 5), Some(vec![0]));
+        assert_eq!(parse_picks("6", 5), None);
+        assert_eq!(parse_picks("0", 5), None);
+        assert_eq!(parse_picks("play 2", 5), None);
+        assert_eq!(parse_picks("", 5), None);
+    }
+
+    #[tokio::test]
+    async fn play_requires_a_voice_channel_when_not_connected() {
+        let (voice, guilds, messenger) =
+            (FakeVoice::new(), FakeGuilds::new(), FakeMessenger::new());
+        voice.add_result("song", fakes::track("Song", 60));
+
+        let result = service(&voice, &guilds)
+            .play(
+                &messenger,
+                &fakes::invocation(),
+                &GuildSettings::default(),
+                "song",
+            )
+            .await;
+
+        assert_eq!(fakes::user_error(result), "Not in a voice channel");
+        assert!(voice.queued(fakes::GUILD).is_empty());
+    }
+
+    #[tokio::test]
+    async fn play_is_refused_in_dms() {
+        let (voice, guilds, messenger) =
+            (FakeVoice::new(), FakeGuilds::new(), FakeMessenger::new());
+        let invocation = Invocation {
+            guild_id: None,
+            ..fakes::invocation()
+        };
+
+        let result = service(&voice, &guilds)
+            .play(&messenger, &invocation, &GuildSettings::default(), "song")
+            .await;
+
+        assert_eq!(
+            fakes::user_error(result),
+            "This command only works in a server"
+        );
+    }
+
+    #[tokio::test]
+    async fn play_joins_the_callers_channel() {
+        let (voice, guilds, messenger) =
+            (FakeVoice::new(), FakeGuilds::new(), FakeMessenger::new());
+        guilds.put_in_voice(fakes::USER, fakes::VOICE_CHANNEL);
+        voice.add_result("song", fakes::track("Song", 60));
+
+        service(&voice, &guilds)
+            .play(
+                &messenger,
+                &fakes::invocation(),
+                &GuildSettings::default(),
+                "song",
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(voice.channel(fakes::GUILD), Some(fakes::VOICE_CHANNEL));
+        assert_eq!(voice.queued(fakes::GUILD), vec!["Song"]);
+    }
+
+    #[tokio::test]
+    async fn callers_in_another_channel_are_refused() {
+        let (voice, guilds, messenger) =
+            (FakeVoice::new(), FakeGuilds::new(), FakeMessenger::new());
+        voice.connect(fakes::GUILD);
+        guilds.put_in_voice(fakes::USER, ChannelId(99));
+        voice.add_result("song", fakes::track("Song", 60));
+        let service = service(&voice, &guilds);
+
+        let played = service
+            .play(
+                &messenger,
+                &fakes::invocation(),
+                &GuildSettings::default(),
+                "song",
+            )
+            .await;
+        let joined = service.join(&fakes::invocation()).await;
+
+        // Nor can anyone who isn't in a voice channel at all.
+        let outsider = Invocation {
+            user_id: UserId(5),
+            ..fakes::invocation()
+        };
+        let skipped = service.skip(&messenger, &outsider).await;
+
+        assert_eq!(
+            fakes::user_error(played),
+            "You need to be in <#3> to do that"
+        );
+        assert_eq!(
+            fakes::user_error(joined),
+            "Already playing in <#3>, use `move` to bring me to your channel"
+        );
+        assert_eq!(
+            fakes::user_error(skipped),
+            "You need to be in <#3> to do that"
+        );
+        assert!(voice.queued(fakes::GUILD).is_empty());
+    }
+
+    #[tokio::test]
+    async fn follow_moves_to_the_callers_channel() {
+        let (voice, guilds) = (FakeVoice::new(), FakeGuilds::new());
+        voice.connect(fakes::GUILD);
+        guilds.put_in_voice(fakes::USER, fakes::VOICE_CHANNEL);
+        let service = service(&voice, &guilds);
+
+        let same = service.follow(&fakes::invocation()).await;
+        guilds.put_in_voice(fakes::USER, ChannelId(99));
+        service.follow(&fakes::invocation()).await.unwrap();
+
+        assert_eq!(fakes::user_error(same), "Already in your voice channel");
+        assert_eq!(voice.channel(fakes::GUILD), Some(ChannelId(99)));
+    }
+
+    #[tokio::test]
+    async fn play_with_no_results() {
+        let (voice, guilds, messenger) =
+            (FakeVoice::new(), FakeGuilds::new(), FakeMessenger::new());
+        voice.connect(fakes::GUILD);
+        guilds.put_in_voice(fakes::USER, fakes::VOICE_CHANNEL);
+
+        let result = service(&voice, &guilds)
+            .play(
+                &messenger,
+                &fakes::invocation(),
+                &GuildSettings::default(),
+                "nothing",
+            )
+            .await;
+
+        assert_eq!(fakes::user_error(result), "Could not find video");
+        assert!(messenger.replies().is_empty());
+    }
+
+    #[tokio::test]
+    async fn play_enforces_the_track_length() {
+        let (voice, guilds, messenger) =
+            (FakeVoice::new(), FakeGuilds::new(), FakeMessenger::new());
+        voice.connect(fakes::GUILD);
+        guilds.put_in_voice(fakes::USER, fakes::VOICE_CHANNEL);
+        voice.add_result("long", fakes::track("Long", 600));
+        let settings = GuildSettings {
+            max_track_length: Some(300),
+            ..GuildSettings::default()
+        };
+
+        let service = service(&voice, &guilds);
+
+        let result = service
+            .play(&messenger, &fakes::invocation(), &settings, "long")
+            .await;
+        assert_eq!(
+            fakes::user_error(result),
+            "Songs can be at most 300 seconds long"
+        );
+        assert!(voice.queued(fakes::GUILD).is_empty());
+
+        // Limits too big to count in milliseconds allow anything.
+        let settings = GuildSettings {
+            max_track_length: Some(u64::MAX),
+            ..GuildSettings::default()
+        };
+        service
+            .play(&messenger, &fakes::invocation(), &settings, "long")
+            .await
+            .unwrap();
+        assert_eq!(voice.queued(fakes::GUILD), vec!["Long"]);
+    }
+
+    #[tokio::test]
+    async fn play_enforces_the_queue_length() {
+        let (voice, guilds, messenger) =
+            (FakeVoice::new(), FakeGuilds::new(), FakeMessenger::new());
+        voice.connect(fakes::GUILD);
+        guilds.put_in_voice(fakes::USER, fakes::VOICE_CHANNEL);
+        voice.add_result(PLAYLIST, fakes::track("One", 60));
+        voice.add_result(PLAYLIST, fakes::track("Two", 60));
+        voice.add_result(PLAYLIST, fakes::track("Three", 60));
+        let settings = GuildSettings {
+            max_queue_length: Some(2),
+            ..GuildSettings::default()
+        };
+        let service = service(&voice, &guilds);
+
+        service
+            .play(&messenger, &fakes::invocation(), &settings, PLAYLIST)
+            .await
+            .unwrap();
+        let result = service
+            .play(&messenger, &fakes::invocation(), &settings, PLAYLIST)
+            .await;
+
+        assert_eq!(messenger.replies(), vec!["Added 2 songs to the queue"]);
+        assert_eq!(fakes::user_error(result), "The queue is full (2 songs)");
+        assert_eq!(voice.queued(fakes::GUILD), vec!["One", "Two"]);
+    }
+
+    #[tokio::test]
+    async fn queue_when_empty() {
+        let (voice, guilds) = (FakeVoice::new(), FakeGuilds::new());
+
+        let result = service(&voice, &guilds).queue(&fakes::invocation()).await;
+
+        match result {
+            Err(error) => assert_eq!(error.to_string(), "Nothing is queued"),
+            Ok(_) => panic!("expected an empty queue"),
+        }
+    }
+
+    #[tokio::test]
+    async fn queue_lists_upcoming_tracks() {
+        let (voice, guilds, messenger) =
+            (FakeVoice::new(), FakeGuilds::new(), FakeMessenger::new());
+        voice.connect(fakes::GUILD);
+        guilds.put_in_voice(fakes::USER, fakes::VOICE_CHANNEL);
+        for i in 0..12 {
+            voice.add_result(PLAYLIST, fakes::track(&format!("Song {}", i), 60));
+        }
+        voice.set_position(fakes::GUILD, 20_000);
+        let service = service(&voice, &guilds);
+        let invocation = fakes::invocation();
+
+        service
+            .play(&messenger, &invocation, &GuildSettings::default(), PLAYLIST)
+            .await
+            .unwrap();
+        let listing = service.queue(&invocation).await.unwrap();
+
+        assert_eq!(listing.now_playing.track.title, "Song 0");
+        assert_eq!(listing.now_playing.requester, Some(fakes::USER));
+        assert_eq!(listing.upcoming.len(), 11);
+        assert_eq!(listing.remaining, 40_000 + 11 * 60_000);
+        assert_eq!(listing.page_count(), 2);
+        let last: Vec<(usize, String)> = listing
+            .page(2)
+            .map(|(position, queued)| (position, queued.track.title.clone()))
+            .collect();
+        assert_eq!(last, vec![(11, String::from("Song 11"))]);
+    }
+
+    async fn queue_of(voice: &Arc<FakeVoice>, service: &SongService, titles: &[&str]) {
+        voice.connect(fakes::GUILD);
+        for title in titles {
+            voice.add_result(PLAYLIST, fakes::track(title, 60));
+        }
+        service
+            .play(
+                &FakeMessenger::new(),
+                &fakes::invocation(),
+                &GuildSettings::default(),
+                PLAYLIST,
+            )
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn remove_and_reorder() {
+        let (voice, guilds, messenger) =
+            (FakeVoice::new(), FakeGuilds::new(), FakeMessenger::new());
+        let service = service(&voice, &guilds);
+        let invocation = fakes::invocation();
+        queue_of(&voice, &service, &["A", "B", "C", "D", "E", "F"]).await;
+
+        service
+            .remove(&messenger, &invocation, 2, Some(3))
+            .await
+            .unwrap();
+        service
+            .remove(&messenger, &invocation, 1, None)
+            .await
+            .unwrap();
+        service
+            .reorder(&messenger, &invocation, 2, 1)
+            .await
+            .unwrap();
+        let result = service.remove(&messenger, &invocation, 3, None).await;
+
+        assert_eq!(voice.queued(fakes::GUILD), vec!["A", "F", "E"]);
+        assert_eq!(
+            messenger.replies(),
+            vec!["Removed 2 songs", "Removed: B", "Moved F to position 1"]
+        );
+        assert_eq!(
+            fakes::user_error(result),
+            "There's no song at position 3, the queue goes from 1 to 2"
+        );
+    }
+
+    #[tokio::test]
+    async fn shuffle_and_clear_keep_the_current_song() {
+        let (voice, guilds, messenger) =
+            (FakeVoice::new(), FakeGuilds::new(), FakeMessenger::new());
+        let service = service(&voice, &guilds);
+        let invocation = fakes::invocation();
+        queue_of(&voice, &service, &["A", "B", "C", "D"]).await;
+
+        service.shuffle(&messenger, &invocation).await.unwrap();
+        let mut queued = voice.queued(fakes::GUILD);
+        assert_eq!(queued[0], "A");
+        queued.sort();
+        assert_eq!(queued, vec!["A", "B", "C", "D"]);
+
+        service.clear(&messenger, &invocation).await.unwrap();
+        let result = service.clear(&messenger, &invocation).await;
+
+        assert_eq!(voice.queued(fakes::GUILD), vec!["A"]);
+        assert_eq!(
+            fakes::user_error(result),
+            "Nothing is queued after this song"
+        );
+    }
+
+    #[tokio::test]
+    async fn jump_skips_to_a_position() {
+        let (voice, guilds, messenger) =
+            (FakeVoice::new(), FakeGuilds::new(), FakeMessenger::new());
+        let service = service(&voice, &guilds);
+        queue_of(&voice, &service, &["A", "B", "C", "D"]).await;
+
+        service
+            .jump(&messenger, &fakes::invocation(), 2)
+            .await
+            .unwrap();
+
+        assert_eq!(voice.queued(fakes::GUILD), vec!["C", "D"]);
+        assert_eq!(messenger.replies(), vec!["Jumped to: C"]);
+    }
+
+    #[tokio::test]
+    async fn pause_and_resume() {
+        let (voice, guilds, messenger) =
+            (FakeVoice::new(), FakeGuilds::new(), FakeMessenger::new());
+        let service = service(&voice, &guilds);
+        let invocation = fakes::invocation();
+
+        let nothing = service.pause(&messenger, &invocation).await;
+        queue_of(&voice, &service, &["A"]).await;
+        service.pause(&messenger, &invocation).await.unwrap();
+        let again = service.pause(&messenger, &invocation).await;
+        service.resume(&messenger, &invocation).await.unwrap();
+        let not_paused = service.resume(&messenger, &invocation).await;
+
+        assert_eq!(fakes::user_error(nothing), "Nothing is playing");
+        assert_eq!(
+            fakes::user_error(again),
+            "Already paused, use `resume` to keep playing"
+        );
+        assert_eq!(fakes::user_error(not_paused), "Not paused");
+        assert_eq!(messenger.replies(), vec!["Paused: A", "Resumed: A"]);
+    }
+
+    #[test]
+    fn parses_seek_targets() {
+        assert_eq!(SeekTarget::parse("1:30"), Some(SeekTarget::To(90_000)));
+        assert_eq!(SeekTarget::parse("45"), Some(SeekTarget::To(45_000)));
+        assert_eq!(
+            SeekTarget::parse("1:02:03"),
+            Some(SeekTarget::To(3_723_000))
+        );
+        assert_eq!(SeekTarget::parse("+30s"), Some(SeekTarget::Forward(30_000)));
+        assert_eq!(SeekTarget::parse("-1:00"), Some(SeekTarget::Back(60_000)));
+        assert_eq!(SeekTarget::parse("1::30"), None);
+        assert_eq!(SeekTarget::parse("soon"), None);
+        assert_eq!(SeekTarget::parse(""), None);
+        // Too long to fit in milliseconds.
+        assert_eq!(SeekTarget::parse("99999999999999999999"), None);
+        assert_eq!(SeekTarget::parse("18446744073709552"), None);
+        assert_eq!(SeekTarget::parse("+307445734561825861:00"), None);
+        assert_eq!(
+            SeekTarget::parse("+18446744073709551"),
+            Some(SeekTarget::Forward(18_446_744_073_709_551_000))
+        );
+    }
+
+    #[tokio::test]
+    async fn seek_within_the_track() {
+        let (voice, guilds, messenger) =
+            (FakeVoice::new(), FakeGuilds::new(), FakeMessenger::new());
+        let service = service(&voice, &guilds);
+        let invocation = fakes::invocation();
+        queue_of(&voice, &service, &["A"]).await;
+        voice.set_position(fakes::GUILD, 10_000);
+
+        service.seek(&messenger, &invocation, "+30s").await.unwrap();
+        let far_ahead = service
+            .seek(&messenger, &invocation, "+18446744073709551")
+            .await;
+        service
+            .seek(&messenger, &invocation, "-1:00")
+            .await
+            .unwrap();
+        let past_the_end = service.seek(&messenger, &invocation, "1:00").await;
+
+        assert_eq!(
+            messenger.replies(),
+            vec!["Seeked to 0:40", "Seeked to 0:00"]
+        );
+        assert_eq!(
+            fakes::user_error(past_the_end),
+            "The song is only 1:00 long"
+        );
+        assert_eq!(fakes::user_error(far_ahead), "The song is only 1:00 long");
+    }
+
+    #[tokio::test]
+    async fn volume_is_checked_and_applied() {
+        let (voice, guilds, messenger) =
+            (FakeVoice::new(), FakeGuilds::new(), FakeMessenger::new());
+        let service = service(&voice, &guilds);
+        let invocation = fakes::invocation();
+        voice.connect(fakes::GUILD);
+        guilds.put_in_voice(fakes::USER, fakes::VOICE_CHANNEL);
+        let settings = GuildSettings {
+            volume: Some(80),
+            ..GuildSettings::default()
+        };
+
+        service
+            .volume(&messenger, &invocation, &settings, None)
+            .await
+            .unwrap();
+        service
+            .volume(&messenger, &invocation, &settings, Some(120))
+            .await
+            .unwrap();
+        let too_loud = service
+            .volume(&messenger, &invocation, &settings, Some(200))
+            .await;
+
+        assert_eq!(
+            messenger.replies(),
+            vec!["Volume is 80%", "Volume set to 120%"]
+        );
+        assert_eq!(voice.volume(fakes::GUILD), Some(120));
+        assert_eq!(fakes::user_error(too_loud), "Volume goes from 0 to 150");
+    }
+
+    #[tokio::test]
+    async fn play_applies_the_saved_volume() {
+        let (voice, guilds) = (FakeVoice::new(), FakeGuilds::new());
+        voice.connect(fakes::GUILD);
+        guilds.put_in_voice(fakes::USER, fakes::VOICE_CHANNEL);
+        voice.add_result("song", fakes::track("Song", 60));
+        let settings = GuildSettings {
+            volume: Some(50),
+            ..GuildSettings::default()
+        };
+
+        service(&voice, &guilds)
+            .play(
+                &FakeMessenger::new(),
+                &fakes::invocation(),
+                &settings,
+                "song",
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(voice.volume(fakes::GUILD), Some(50));
+    }
+
+    #[tokio::test]
+    async fn loop_modes() {
+        let (voice, guilds, messenger) =
+            (FakeVoice::new(), FakeGuilds::new(), FakeMessenger::new());
+        let service = service(&voice, &guilds);
+        let invocation = fakes::invocation();
+        queue_of(&voice, &service, &["A", "B"]).await;
+
+        service
+            .set_loop(&messenger, &invocation, Some("Queue"))
+            .await
+            .unwrap();
+        let now_playing = service.now_playing(&invocation).await.unwrap();
+        service
+            .set_loop(&messenger, &invocation, None)
+            .await
+            .unwrap();
+        let listing = service.queue(&invocation).await.unwrap();
+        let result = service
+            .set_loop(&messenger, &invocation, Some("forever"))
+            .await;
+
+        assert_eq!(now_playing.loop_mode, LoopMode::Queue);
+        assert_eq!(listing.loop_mode, LoopMode::Queue);
+        assert_eq!(
+            messenger.replies(),
+            vec!["Looping the queue", "Looping the queue"]
+        );
+        assert_eq!(
+            fakes::user_error(result),
+            "Loop can be `track`, `queue` or `off`"
+        );
+    }
+
+    #[test]
+    fn draws_progress_bars() {
+        assert_eq!(
+            progress_bar(0, 60_000, 4),
+            "\u{1f518}\u{25ac}\u{25ac}\u{25ac}\u{25ac}"
+        );
+        assert_eq!(
+            progress_bar(30_000, 60_000, 4),
+            "\u{25ac}\u{25ac}\u{1f518}\u{25ac}\u{25ac}"
+        );
+        assert_eq!(
+            progress_bar(90_000, 60_000, 4),
+            "\u{25ac}\u{25ac}\u{25ac}\u{25ac}\u{1f518}"
+        );
+        assert_eq!(progress_bar(10, 0, 2), "\u{1f518}\u{25ac}\u{25ac}");
+    }
+
+    #[test]
+    fn formats_durations() {
+        assert_eq!(format_duration(7_000), "0:07");
+        assert_eq!(format_duration(187_000), "3:07");
+        assert_eq!(format_duration(3_723_000), "1:02:03");
+    }
+
+    #[tokio::test]
+    async fn skip_and_now_playing() {
+        let (voice, guilds, messenger) =
+            (FakeVoice::new(), FakeGuilds::new(), FakeMessenger::new());
+        voice.connect(fakes::GUILD);
+        guilds.put_in_voice(fakes::USER, fakes::VOICE_CHANNEL);
+        voice.add_result(PLAYLIST, fakes::track("One", 60));
+        voice.add_result(PLAYLIST, fakes::track("Two", 60));
+        let service = service(&voice, &guilds);
+        let invocation = fakes::invocation();
+        let title = |queued: Option<QueuedTrack>| queued.map(|queued| queued.track.title);
+
+        service
+            .play(&messenger, &invocation, &GuildSettings::default(), PLAYLIST)
+            .await
+            .unwrap();
+        let first = service.now_playing(&invocation).await.unwrap();
+        service.skip(&messenger, &invocation).await.unwrap();
+        let second = service.now_playing(&invocation).await.unwrap();
+        service.stop(&invocation).await.unwrap();
+        let stopped = service.now_playing(&invocation).await;
+        service.skip(&messenger, &invocation).await.unwrap();
+
+        assert_eq!(first.current.track.title, "One");
+        assert_eq!(first.current.requester, Some(fakes::USER));
+        assert_eq!(title(first.next), Some(String::from("Two")));
+        assert_eq!(second.current.track.title, "Two");
+        assert_eq!(title(second.next), None);
+        match stopped {
+            Err(error) => assert_eq!(error.to_string(), "Nothing is playing"),
+            Ok(_) => panic!("expected nothing to be playing"),
+        }
+        assert_eq!(
+            messenger.replies(),
+            vec!["Added 2 songs to the queue", "Skipped: One"]
+        );
+    }
+}
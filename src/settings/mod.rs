@@ -0,0 +1,280 @@
+use crate::{access::AccessRule, config::doom::Preset};
+use serde::{Deserialize, Serialize};
+use serenity::model::id::{GuildId, RoleId};
+use std::collections::BTreeMap;
+
+const TREE: &str = "guild_settings";
+
+/// Loudest the music can be set to, in percent.
+pub const MAX_VOLUME: u16 = 150;
+
+/// Keys that can be used with `config get`, `config set` and `config unset`.
+pub const KEYS: &[&str] = &[
+    "prefix",
+    "assignable_roles",
+    "max_queue_length",
+    "max_track_length",
+    "volume",
+    "live_now_playing",
+    "schedule_role",
+    "presets.<name>",
+];
+
+/// Settings that admins can change for their own guild.
+#[derive(Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct GuildSettings {
+    /// Command prefix, used instead of the one in the config.
+    pub prefix: Option<String>,
+    /// Roles members may give themselves. Any role can be assigned when empty.
+    pub assignable_roles: Vec<String>,
+    pub max_queue_length: Option<usize>,
+    /// Longest track that can be queued, in seconds.
+    pub max_track_length: Option<u64>,
+    /// Music volume in percent, kept between sessions.
+    pub volume: Option<u16>,
+    /// Keep the last `song current` message up to date as songs change.
+    pub live_now_playing: bool,
+    /// Role mentioned when a scheduled server starts.
+    pub schedule_role: Option<RoleId>,
+    pub presets: BTreeMap<String, Preset>,
+    /// Who may use each group or command, changed with the `access` commands.
+    pub access: BTreeMap<String, AccessRule>,
+}
+
+impl GuildSettings {
+    pub fn can_assign(&self, role: &str) -> bool {
+        self.assignable_roles.is_empty()
+            || self
+                .assignable_roles
+                .iter()
+                .any(|assignable| assignable.eq_ignore_ascii_case(role))
+    }
+
+    /// Describes the value of a key, or `None` if it isn't set.
+    pub fn get(&self, key: &str) -> Result<Option<String>, String> {
+        let value = match key {
+            "prefix" => self.prefix.clone(),
+            "assignable_roles" if self.assignable_roles.is_empty() => None,
+            "assignable_roles" => Some(self.assignable_roles.join(", ")),
+            "max_queue_length" => self.max_queue_length.map(|length| length.to_string()),
+            "max_track_length" => self.max_track_length.map(|length| length.to_string()),
+            "volume" => self.volume.map(|volume| volume.to_string()),
+            "live_now_playing" if self.live_now_playing => Some(String::from("true")),
+            "live_now_playing" => None,
+            "schedule_role" => self.schedule_role.map(|role| role.to_string()),
+            _ => match key.strip_prefix("presets.") {
+                Some(name) => self
+                    .presets
+                    .get(name)
+                    .map(|preset| format!("{} {}", preset.iwad, preset.url)),
+                None => return Err(format!("\"{}\" is not a setting", key)),
+            },
+        };
+        Ok(value)
+    }
+
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), String> {
+        match key {
+            "prefix" => {
+                if value.chars().any(char::is_whitespace) {
+                    return Err(String::from("The prefix can't contain spaces"));
+                }
+                self.prefix = Some(value.to_string());
+            }
+            "assignable_roles" => {
+                self.assignable_roles = value
+                    .split(',')
+                    .map(|role| role.trim().to_string())
+                    .filter(|role| !role.is_empty())
+                    .collect();
+            }
+            "max_queue_length" => {
+                self.max_queue_length = Some(
+                    value
+                        .parse()
+                        .map_err(|_| String::from("max_queue_length must be a number"))?,
+                );
+            }
+            "max_track_length" => {
+                self.max_track_length =
+                    Some(value.parse().map_err(|_| {
+                        String::from("max_track_length must be a number of seconds")
+                    })?);
+            }
+            "volume" => {
+                self.volume = Some(
+                    value
+                        .parse()
+                        .ok()
+                        .filter(|volume| *volume <= MAX_VOLUME)
+                        .ok_or_else(|| format!("volume must be from 0 to {}", MAX_VOLUME))?,
+                );
+            }
+            "live_now_playing" => {
+                self.live_now_playing = value
+                    .parse()
+                    .map_err(|_| String::from("live_now_playing must be true or false"))?;
+            }
+            "schedule_role" => {
+                // Either a mention or the role's ID.
+                let id = value.trim_start_matches("<@&").trim_end_matches('>');
+                self.schedule_role =
+                    Some(RoleId(id.parse().map_err(|_| {
+                        String::from("schedule_role must be a role mention or ID")
+                    })?));
+            }
+            _ => match key.strip_prefix("presets.") {
+                Some(name) if !name.is_empty() => {
+                    let mut parts = value.split_whitespace();
+                    match (parts.next(), parts.next()) {
+                        (Some(iwad), Some(_)) if !crate::doom::IWADS.contains(&iwad) => {
+                            return Err(format!(
+                                "\"{}\" is not an IWAD, use one of {}",
+                                iwad,
+                                crate::doom::IWADS.join(", ")
+                            ))
+                        }
+                        (Some(iwad), Some(url)) => {
+                            self.presets.insert(
+                                name.to_string(),
+                                Preset {
+                                    iwad: iwad.to_string(),
+                                    url: url.to_string(),
+                                },
+                            );
+                        }
+                        _ => return Err(String::from("A preset is set as `<iwad> <url>`")),
+                    }
+                }
+                _ => return Err(format!("\"{}\" is not a setting", key)),
+            },
+        }
+        Ok(())
+    }
+
+    pub fn unset(&mut self, key: &str) -> Result<(), String> {
+        match key {
+            "prefix" => self.prefix = None,
+            "assignable_roles" => self.assignable_roles.clear(),
+            "max_queue_length" => self.max_queue_length = None,
+            "max_track_length" => self.max_track_length = None,
+            "volume" => self.volume = None,
+            "live_now_playing" => self.live_now_playing = false,
+            "schedule_role" => self.schedule_role = None,
+            _ => match key.strip_prefix("presets.") {
+                Some(name) => {
+                    self.presets.remove(name);
+                }
+                None => return Err(format!("\"{}\" is not a setting", key)),
+            },
+        }
+        Ok(())
+    }
+
+    /// Every key that is set along with its value.
+    pub fn list(&self) -> Vec<(String, String)> {
+        let mut keys: Vec<String> = KEYS
+            .iter()
+            .filter(|key| !key.starts_with("presets."))
+            .map(|key| key.to_string())
+            .collect();
+        keys.extend(self.presets.keys().map(|name| format!("presets.{}", name)));
+
+        keys.into_iter()
+            .filter_map(|key| match self.get(&key) {
+                Ok(Some(value)) => Some((key, value)),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+pub fn get(db: &sled::Db, guild_id: GuildId) -> anyhow::Result<GuildSettings> {
+    let settings = crate::db::get_value(&db.open_tree(TREE)?, guild_id.0.to_be_bytes())?;
+    Ok(settings.unwrap_or_default())
+}
+
+pub fn save(db: &sled::Db, guild_id: GuildId, settings: &GuildSettings) -> anyhow::Result<()> {
+    crate::db::insert(&db.open_tree(TREE)?, guild_id.0.to_be_bytes(), settings)
+}
+
+/// Settings for the guild, or the defaults outside of guilds.
+pub async fn for_guild(
+    ctx: &serenity::client::Context,
+    guild_id: Option<GuildId>,
+) -> anyhow::Result<GuildSettings> {
+    match guild_id {
+        Some(guild_id) => get(&crate::db::get(ctx).await, guild_id),
+        None => Ok(GuildSettings::default()),
+    }
+}
+
+/// Applies a change to a guild's settings and saves them.
+pub async fn modify(
+    ctx: &serenity::client::Context,
+    guild_id: GuildId,
+    change: impl FnOnce(&mut GuildSettings),
+) -> anyhow::Result<()> {
+    let db = crate::db::get(ctx).await;
+    let mut settings = get(&db, guild_id)?;
+    change(&mut settings);
+    save(&db, guild_id, &settings)
+}
+
+/// The prefix commands use in a guild.
+pub async fn prefix(ctx: &serenity::client::Context, guild_id: Option<GuildId>) -> String {
+    let settings = match for_guild(ctx, guild_id).await {
+        Ok(settings) => settings,
+        Err(why) => {
+            log::error!("Could not read guild settings: {:?}", why);
+            GuildSettings::default()
+        }
+    };
+
+    match settings.prefix {
+        Some(prefix) => prefix,
+        None => crate::config::get(ctx).await.bot.prefix,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn presets_need_a_known_iwad() {
+        let mut settings = GuildSettings::default();
+
+        assert_eq!(
+            settings.set("presets.av", "quake https://example.com/av.zip"),
+            Err(String::from(
+                "\"quake\" is not an IWAD, use one of doom, doom2, tnt, plutonia"
+            ))
+        );
+        settings
+            .set("presets.av", "doom2 https://example.com/av.zip")
+            .unwrap();
+
+        assert_eq!(
+            settings.get("presets.av"),
+            Ok(Some(String::from("doom2 https://example.com/av.zip")))
+        );
+    }
+
+    #[test]
+    fn schedule_roles_are_mentions_or_ids() {
+        let mut settings = GuildSettings::default();
+
+        settings.set("schedule_role", "<@&123>").unwrap();
+        assert_eq!(settings.schedule_role, Some(RoleId(123)));
+        settings.set("schedule_role", "456").unwrap();
+        assert_eq!(settings.get("schedule_role"), Ok(Some(String::from("456"))));
+        assert_eq!(
+            settings.set("schedule_role", "Doom Players"),
+            Err(String::from("schedule_role must be a role mention or ID"))
+        );
+        settings.unset("schedule_role").unwrap();
+        assert_eq!(settings.schedule_role, None);
+    }
+}
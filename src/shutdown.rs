@@ -0,0 +1,73 @@
+use crate::{
+    config::doom::{DoomConfigKey, ShutdownAction},
+    doom::server::ServersKey,
+    util::LavalinkKey,
+};
+use serenity::{
+    model::id::GuildId,
+    prelude::{RwLock, TypeMap},
+};
+use songbird::serenity::SongbirdKey;
+use std::sync::Arc;
+
+/// Waits until the process is asked to stop with SIGINT or SIGTERM.
+pub async fn wait_for_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        let mut terminate = signal(SignalKind::terminate()).expect("Could not listen for SIGTERM");
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => {}
+            _ = terminate.recv() => {}
+        }
+    }
+
+    #[cfg(not(unix))]
+    {
+        let _ = tokio::signal::ctrl_c().await;
+    }
+}
+
+/// Stops or detaches hosted servers and closes every voice session.
+pub async fn cleanup(data: &Arc<RwLock<TypeMap>>) {
+    let (servers, action, lavalink, songbird) = {
+        let data = data.read().await;
+        (
+            data.get::<ServersKey>().cloned(),
+            data.get::<DoomConfigKey>()
+                .map(|config| config.on_shutdown)
+                .unwrap_or_default(),
+            data.get::<LavalinkKey>().cloned(),
+            data.get::<SongbirdKey>().cloned(),
+        )
+    };
+
+    if let Some(servers) = servers {
+        servers.shutdown(action == ShutdownAction::Detach).await;
+    }
+
+    if let Some(lavalink) = lavalink {
+        let guilds: Vec<GuildId> = lavalink
+            .nodes()
+            .await
+            .iter()
+            .map(|node| GuildId(*node.key()))
+            .collect();
+
+        for guild_id in guilds {
+            if let Err(why) = lavalink.destroy(guild_id).await {
+                log::error!(
+                    "Could not destroy Lavalink session in {}: {:?}",
+                    guild_id,
+                    why
+                );
+            }
+            if let Some(songbird) = &songbird {
+                if let Err(why) = songbird.remove(guild_id).await {
+                    log::error!("Could not leave voice in {}: {:?}", guild_id, why);
+                }
+            }
+        }
+    }
+}
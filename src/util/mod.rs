@@ -6,3 +6,85 @@ pub struct LavalinkKey;
 impl TypeMapKey for LavalinkKey {
     type Value = LavalinkClient;
 }
+
+/// Discord rejects messages longer than this.
+pub const MAX_MESSAGE_LENGTH: usize = 2000;
+
+/// Joins lines that fit in `length` bytes, cutting off the line that doesn't
+/// fit and saying how many were left out after it.
+pub fn fit_lines(lines: &[String], length: usize) -> String {
+    let joined = lines.join("\n");
+    if joined.len() <= length {
+        return joined;
+    }
+
+    let mut text = String::new();
+    for (shown, line) in lines.iter().enumerate() {
+        // Leave room to say how many are left out after this one.
+        let left = lines.len() - shown - 1;
+        let reserved = if left == 0 { 0 } else { more(left).len() + 1 };
+        let separator = if shown == 0 { 0 } else { 1 };
+        let room = length.saturating_sub(text.len() + separator + reserved);
+        if shown > 0 {
+            text.push('\n');
+        }
+        if line.len() > room {
+            text.push_str(&cut(line, room));
+            if left > 0 {
+                text.push('\n');
+                text.push_str(&more(left));
+            }
+            break;
+        }
+        text.push_str(line);
+    }
+    text
+}
+
+/// Shortens `line` to at most `length` bytes, marking that it was cut.
+fn cut(line: &str, length: usize) -> String {
+    let marker = '…';
+    if length < marker.len_utf8() {
+        return String::new();
+    }
+    let mut end = 0;
+    for (i, c) in line.char_indices() {
+        if i + c.len_utf8() + marker.len_utf8() > length {
+            break;
+        }
+        end = i + c.len_utf8();
+    }
+    format!("{}{}", &line[..end], marker)
+}
+
+fn more(count: usize) -> String {
+    format!("and {} more", count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(count: usize, line: &str) -> Vec<String> {
+        (0..count).map(|_| line.to_string()).collect()
+    }
+
+    #[test]
+    fn short_lists_are_kept() {
+        assert_eq!(fit_lines(&lines(2, "abc"), 7), "abc\nabc");
+        assert_eq!(fit_lines(&[], 7), "");
+    }
+
+    #[test]
+    fn long_lists_are_cut() {
+        // Multibyte characters, so a cut by bytes would land inside one.
+        let text = fit_lines(&lines(100, "ööööö"), 50);
+        assert!(text.len() <= 50);
+        assert!(text.starts_with("ööööö\nööööö\n"));
+        assert!(text.ends_with("more"));
+
+        let text = fit_lines(&lines(1, &"ö".repeat(100)), 50);
+        assert!(text.len() <= 50);
+        assert!(text.ends_with("ö…"));
+    }
+}